@@ -0,0 +1,408 @@
+//! Command line arguments
+//!
+//! Running the installer without a command does the interactive install,
+//! which is what people double-clicking the binary get.
+
+use std::path::PathBuf;
+
+use clap::{Args, Parser, Subcommand};
+
+use crate::installer::{copy::CopyStrategy, platform::TargetPlatform, runner::RunnerKind};
+
+#[derive(Debug, Parser)]
+#[command(version, about = "Automatic installer for the Alloy modloader")]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Install Alloy interactively (the default)
+    Install(InstallArgs),
+    /// Manage the daisyMoon folders you've imported before
+    Sources {
+        #[command(subcommand)]
+        command: SourcesCommand,
+    },
+    /// List every Alloy install
+    List,
+    /// Rename an install's folder
+    Rename {
+        /// The path or folder name of the install
+        install: String,
+        /// The new folder name
+        new_name: String,
+    },
+    /// Make another copy of an install, mods included
+    Clone(CloneArgs),
+    /// Bring copies up to date after Steam updated the game
+    Sync {
+        /// The path or folder name of the install, every copy if left out
+        install: Option<String>,
+        /// Don't ask before syncing
+        #[arg(long, short)]
+        yes: bool,
+    },
+    /// Update Alloy in an install, keeping the edits you made to daisyMoon
+    Update {
+        /// The path or folder name of the install
+        install: String,
+        /// Update to this diff instead of downloading the latest one
+        #[arg(long)]
+        diff: Option<PathBuf>,
+    },
+    /// Check whether Steam reverted files of Alloy installed straight into the game
+    Verify {
+        /// The path or folder name of the install, every in-place install if left out
+        install: Option<String>,
+    },
+    /// Put back the files Steam reverted in Alloy installed straight into the game
+    Repair {
+        /// The path or folder name of the install, every in-place install if left out
+        install: Option<String>,
+        /// Don't ask before repairing
+        #[arg(long, short)]
+        yes: bool,
+    },
+    /// See the backups made before the installer changed an install
+    Backups {
+        #[command(subcommand)]
+        command: BackupsCommand,
+    },
+    /// Put an install's files back the way they were in a backup
+    Restore {
+        /// The id of the backup, or enough of its start to be unique
+        id: String,
+        /// The path or folder name of the install, needed if several have a matching backup
+        #[arg(long)]
+        install: Option<String>,
+        /// Don't ask before restoring
+        #[arg(long, short)]
+        yes: bool,
+    },
+    /// See or undo what merging Alloy's translations did
+    Translations {
+        #[command(subcommand)]
+        command: TranslationsCommand,
+    },
+    /// Add an install to Steam as a non-Steam game
+    Shortcut {
+        /// The path or folder name of the install
+        install: String,
+        /// The Steam account to add it to (defaults to every account on this machine)
+        #[arg(long)]
+        user: Option<String>,
+        /// The name shown in the Steam library
+        #[arg(long)]
+        name: Option<String>,
+        /// The Proton version Windows installs run with on Linux
+        #[arg(long, default_value = crate::installer::shortcuts::DEFAULT_COMPAT_TOOL)]
+        compat_tool: String,
+        /// Leave the Alloy version out of the library artwork
+        #[arg(long)]
+        no_label: bool,
+        /// A folder with grid.png, wide.png, hero.png, logo.png or icon.png to use instead of the generated artwork
+        #[arg(long)]
+        artwork: Option<PathBuf>,
+    },
+    /// Add an install to the Linux applications menu
+    Desktop {
+        /// The path or folder name of the install
+        install: String,
+        /// Remove its entry instead
+        #[arg(long)]
+        remove: bool,
+    },
+    /// Start the game of an install
+    Launch(LaunchArgs),
+    /// Install and remove the mods of an install
+    Mod {
+        #[command(subcommand)]
+        command: ModCommand,
+    },
+    /// Write alloy.lock files to make an install again elsewhere
+    Lock {
+        #[command(subcommand)]
+        command: LockCommand,
+    },
+    /// Delete an install
+    Remove {
+        /// The path or folder name of the install
+        install: String,
+        /// Don't ask before deleting
+        #[arg(long, short)]
+        yes: bool,
+    },
+}
+
+impl Default for Command {
+    fn default() -> Self {
+        Command::Install(InstallArgs::default())
+    }
+}
+
+#[derive(Debug, Default, Args)]
+pub struct InstallArgs {
+    /// The platform the game will run on, e.g. `windows` for Proton (defaults to this one)
+    #[arg(long, value_enum)]
+    pub target: Option<TargetPlatform>,
+
+    /// API key used when downloading daisyMoon from a Google Drive folder
+    #[arg(long, env = "GOOGLE_DRIVE_API_KEY", hide_env_values = true)]
+    pub drive_api_key: Option<String>,
+
+    /// How the game's files end up in the new copy, linked files take no extra space
+    #[arg(long, value_enum, default_value_t)]
+    pub copy_strategy: CopyStrategy,
+
+    /// Check the hash of every file copied from the game
+    #[arg(long)]
+    pub verify_copy: bool,
+
+    /// Put the original line endings of patched files back after patching
+    #[arg(long)]
+    pub restore_line_endings: bool,
+
+    /// Make exactly the install described by an alloy.lock, or fail
+    #[arg(long, value_name = "LOCKFILE")]
+    pub locked: Option<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+pub struct CloneArgs {
+    /// The path or folder name of the install
+    pub install: String,
+
+    /// Where the new copy goes (defaults to the next free CobaltAlloy folder next to it)
+    pub destination: Option<PathBuf>,
+
+    /// How the files end up in the new copy
+    #[arg(long, value_enum, default_value_t)]
+    pub copy_strategy: CopyStrategy,
+}
+
+#[derive(Debug, Args)]
+pub struct LaunchArgs {
+    /// The path or folder name of the install (asked when there are several)
+    pub install: Option<String>,
+
+    /// What runs the game on Linux
+    #[arg(long, value_enum, default_value_t)]
+    pub runner: RunnerKind,
+
+    /// The Proton to use, a Proton folder or part of its name like `GE-Proton9` (defaults to the newest)
+    #[arg(long)]
+    pub proton: Option<String>,
+
+    /// The Wine binary to use (defaults to the one in your PATH)
+    #[arg(long)]
+    pub wine: Option<PathBuf>,
+
+    /// Arguments given to the game
+    #[arg(last = true)]
+    pub args: Vec<String>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum SourcesCommand {
+    /// List every stored daisyMoon
+    List,
+    /// Remove a stored daisyMoon
+    Remove {
+        /// The hash of the daisyMoon, or enough of its start to be unique
+        id: String,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum BackupsCommand {
+    /// List the backups of every install, or of one
+    List {
+        /// The path or folder name of the install
+        #[arg(long)]
+        install: Option<String>,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum LockCommand {
+    /// Write the Alloy, translations, daisyMoon and mods of an install to a lock
+    Export {
+        /// The path or folder name of the install
+        install: String,
+        /// Where to write it
+        #[arg(long, short, default_value = crate::installer::lock::LOCK_FILENAME)]
+        output: PathBuf,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum TranslationsCommand {
+    /// List the keys where Alloy replaced different text, and how many it added
+    Report {
+        /// The path or folder name of the install
+        install: String,
+    },
+    /// Put the translations back the way they were before Alloy
+    Restore {
+        /// The path or folder name of the install
+        install: String,
+        /// Don't ask before restoring
+        #[arg(long, short)]
+        yes: bool,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ModCommand {
+    /// Install mod packages and what they depend on, or update the installed versions of them
+    Install {
+        /// The path or folder name of the install
+        install: String,
+        /// Zips, folders or URLs of zips with an alloy_mod.ron, or ids of mods in your mod indexes
+        #[arg(required = true)]
+        packages: Vec<String>,
+        /// A package to take dependencies from, only installed if something needs it
+        #[arg(long)]
+        from: Vec<String>,
+    },
+    /// Remove an installed mod
+    Remove {
+        /// The path or folder name of the install
+        install: String,
+        /// The id of the mod
+        id: String,
+    },
+    /// List the mods of an install, and whether they're enabled
+    List {
+        /// The path or folder name of the install
+        install: String,
+    },
+    /// Put disabled mods back into alloys
+    Enable {
+        /// The path or folder name of the install
+        install: String,
+        /// The ids of the mods
+        #[arg(required = true)]
+        ids: Vec<String>,
+    },
+    /// Move mods out of alloys without uninstalling them
+    Disable {
+        /// The path or folder name of the install
+        install: String,
+        /// The ids of the mods
+        #[arg(required = true)]
+        ids: Vec<String>,
+    },
+    /// Save and switch between named sets of enabled mods
+    Profile {
+        #[command(subcommand)]
+        command: ModProfileCommand,
+    },
+    /// Look for mods in your mod indexes
+    Search {
+        /// Part of the id, name or description, every mod is listed without it
+        query: Option<String>,
+    },
+    /// Show every version of a mod in your mod indexes
+    Info {
+        /// The id of the mod
+        id: String,
+    },
+    /// List the mods of an install with a newer version in your mod indexes
+    Outdated {
+        /// The path or folder name of the install
+        install: String,
+    },
+    /// Update the mods of an install to the newest versions in your mod indexes
+    Update {
+        /// The path or folder name of the install
+        install: String,
+        /// The mods to update, every outdated one without them
+        ids: Vec<String>,
+    },
+    /// Manage the mod indexes mods are looked for in
+    Index {
+        #[command(subcommand)]
+        command: ModIndexCommand,
+    },
+    /// Start a new mod package
+    New {
+        /// The folder to start it in
+        folder: PathBuf,
+        /// The id of the mod (defaults to the folder's name)
+        #[arg(long)]
+        id: Option<String>,
+        /// Its human readable name (defaults to the id)
+        #[arg(long)]
+        name: Option<String>,
+        /// Who makes it (defaults to your user name)
+        #[arg(long)]
+        author: Option<String>,
+    },
+    /// Write the changes made to the daisyMoon of an install as a diff mods can ship
+    Diff {
+        /// The path or folder name of the install
+        install: String,
+        /// Only these files or folders, relative to the install
+        paths: Vec<String>,
+        /// Where to write the diff (defaults to printing it)
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+    },
+    /// Check a mod package and zip it for sharing
+    Pack {
+        /// The folder with the alloy_mod.ron
+        folder: PathBuf,
+        /// Where the zip goes (defaults to <id>-<version>.zip here)
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ModIndexCommand {
+    /// Add a mod index, a JSON or RON file
+    Add {
+        /// Its URL or path
+        location: String,
+    },
+    /// Stop using a mod index
+    Remove {
+        /// Its URL or path
+        location: String,
+    },
+    /// List your mod indexes
+    List,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ModProfileCommand {
+    /// Save the mods enabled now as a profile, which becomes the active one
+    Save {
+        /// The path or folder name of the install
+        install: String,
+        /// The name of the profile
+        name: String,
+    },
+    /// Enable exactly the mods of a profile
+    Use {
+        /// The path or folder name of the install
+        install: String,
+        /// The name of the profile
+        name: String,
+    },
+    /// List the profiles of an install
+    List {
+        /// The path or folder name of the install
+        install: String,
+    },
+    /// Delete a profile, its mods stay as they are
+    Delete {
+        /// The path or folder name of the install
+        install: String,
+        /// The name of the profile
+        name: String,
+    },
+}
@@ -0,0 +1,144 @@
+//! `backups list` and `restore`, putting back files the installer changed
+
+use colored::Colorize;
+use indicatif::HumanBytes;
+
+use crate::cli::BackupsCommand;
+use crate::installer::backup::{self, Backup};
+use crate::installer::metadata::read_metadata;
+use crate::installer::registry::{self, RegisteredInstall};
+
+/// Runs a `backups` command
+pub fn run(command: BackupsCommand) {
+    match command {
+        BackupsCommand::List { install } => list(install.as_deref()),
+    }
+}
+
+/// The installs to look at, the one asked for or every one
+fn installs(query: Option<&str>) -> Vec<RegisteredInstall> {
+    let installs = match query {
+        Some(query) => registry::find(query).map(|install| vec![install]),
+        None => registry::read_registry(),
+    };
+
+    installs.unwrap_or_else(|e| {
+        println!("Failed to find your installs: {}", e);
+        std::process::exit(1);
+    })
+}
+
+fn list(query: Option<&str>) {
+    let mut any = false;
+
+    for install in installs(query) {
+        let index = match backup::read_index(&install.path) {
+            Ok(index) => index,
+            Err(e) => {
+                println!("Failed to read the backups of {}: {}", install.name(), e);
+                continue;
+            }
+        };
+
+        if index.is_empty() {
+            continue;
+        }
+
+        any = true;
+        println!("{}", install.path.display().to_string().bold());
+
+        for backup in index.iter().rev() {
+            println!(
+                "  {}  {} ({})",
+                backup.id.bold(),
+                backup.describe(),
+                HumanBytes(backup.size)
+            );
+        }
+    }
+
+    if !any {
+        println!("There are no backups yet.");
+    }
+}
+
+/// Puts the files of a backup back
+pub fn restore(id: &str, query: Option<&str>, yes: bool) {
+    let mut found: Vec<(RegisteredInstall, Backup)> = Vec::new();
+
+    for install in installs(query) {
+        match backup::find(&install.path, id) {
+            Ok(Some(backup)) => found.push((install, backup)),
+            Ok(None) => {}
+            Err(e) => {
+                println!("Failed to find the backup in {}: {}", install.name(), e);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    let (install, backup) = match found.len() {
+        0 => {
+            println!("Failed to restore: no backup matches {}", id);
+            std::process::exit(1);
+        }
+        1 => found.remove(0),
+        _ => {
+            println!(
+                "Failed to restore: more than one install has a backup matching {}, use --install",
+                id
+            );
+            std::process::exit(1);
+        }
+    };
+
+    if let Err(e) = backup::check_restorable(&install.path, &backup) {
+        println!("Can't restore {}: {}", backup.id, e);
+        std::process::exit(1);
+    }
+
+    if !yes {
+        let prompt = format!(
+            "Put {} in {} back the way it was on {}?",
+            backup.paths.join(", "),
+            install.name(),
+            backup.describe()
+        );
+        let confirmed = inquire::Confirm::new(&prompt)
+            .with_default(false)
+            .prompt()
+            .unwrap();
+
+        if !confirmed {
+            return;
+        }
+    }
+
+    // Restoring is a change too
+    let reason = format!("before restoring {}", backup.id);
+    if let Err(e) = backup::create_keeping(&install.path, &reason, Some(&backup.id)) {
+        println!("Failed to back up the current files, not restoring: {}", e);
+        std::process::exit(1);
+    }
+
+    if let Err(e) = backup::restore(&install.path, &backup) {
+        println!("Failed to restore {}: {}", backup.id, e);
+        std::process::exit(1);
+    }
+
+    // What the install is now is what the backup recorded
+    let registered = match read_metadata(install.path.clone()) {
+        Some(metadata) => registry::register(&install.path, &metadata).map(|_| ()),
+        None => {
+            println!("{} doesn't have Alloy anymore.", install.name());
+            registry::unregister(&install.path)
+        }
+    };
+    if let Err(e) = registered {
+        println!("Couldn't update your list of installs: {}", e);
+    }
+
+    crate::commands::verify::refresh_marker(&install);
+
+    println!("Restored {} in {}!", backup.id.bold(), install.name());
+}
@@ -0,0 +1,99 @@
+//! `desktop`, adding installs to the Linux applications menu
+
+use std::path::Path;
+
+use colored::Colorize;
+
+use crate::installer::desktop;
+use crate::installer::errors::DesktopError;
+use crate::installer::metadata::read_metadata;
+use crate::installer::platform::TargetPlatform;
+use crate::installer::registry;
+use crate::installer::runner;
+use crate::installer::shortcuts::default_name;
+
+/// Writes the entry of an install with the runner found for it
+fn add(install_dir: &Path, target: TargetPlatform) -> Result<(), DesktopError> {
+    let runner = runner::detect(target).ok_or(DesktopError::NoRunner)?;
+    let path = desktop::write_entry(install_dir, &default_name(install_dir), &runner)?;
+
+    println!(
+        "Added {} to your applications, it runs {}.",
+        default_name(install_dir).bold(),
+        runner.describe()
+    );
+    println!("The entry is {}", path.display());
+
+    Ok(())
+}
+
+/// Adds an install to the applications menu, or removes it
+pub fn run(query: &str, remove: bool) {
+    if !cfg!(target_os = "linux") {
+        println!("Applications menu entries are only made on Linux.");
+        std::process::exit(1);
+    }
+
+    let install = registry::find(query).unwrap_or_else(|e| {
+        println!("Failed to find the install: {}", e);
+        std::process::exit(1);
+    });
+
+    if remove {
+        match desktop::remove_entries(&install.path) {
+            Ok(0) => println!("{} isn't in your applications.", install.name()),
+            Ok(_) => println!("Removed {} from your applications.", install.name()),
+            Err(e) => {
+                println!("Failed to remove it from your applications: {}", e);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
+    let target = read_metadata(install.path.clone())
+        .map(|metadata| metadata.target)
+        .unwrap_or_default();
+
+    if let Err(e) = add(&install.path, target) {
+        println!("Failed to add it to your applications: {}", e);
+        std::process::exit(1);
+    }
+}
+
+/// Offers to add a new install to the applications menu on Linux, returns whether it was added
+pub fn offer(install_dir: &Path, target: TargetPlatform) -> bool {
+    if !cfg!(target_os = "linux") {
+        return false;
+    }
+
+    let confirmed = inquire::Confirm::new("Add it to your applications menu?")
+        .with_default(true)
+        .prompt()
+        .unwrap();
+
+    if !confirmed {
+        return false;
+    }
+
+    match add(install_dir, target) {
+        Ok(()) => true,
+        Err(e) => {
+            println!("Failed to add it to your applications: {}", e);
+            false
+        }
+    }
+}
+
+/// Moves the entries of an install to where it was moved, if it had some
+pub fn follow_rename(old: &Path, new: &Path, target: TargetPlatform) {
+    match desktop::remove_entries(old) {
+        Ok(0) => {}
+        Ok(_) => {
+            if let Err(e) = add(new, target) {
+                println!("Failed to update its applications menu entry: {}", e);
+            }
+        }
+        Err(e) => println!("Failed to update its applications menu entry: {}", e),
+    }
+}
@@ -0,0 +1,596 @@
+//! The interactive install, what running the installer without a command does
+
+use cfg_if::cfg_if;
+use colored::Colorize;
+use fs_extra::dir::CopyOptions;
+use indicatif::HumanBytes;
+use spinners::{Spinner, Spinners};
+use std::path::PathBuf;
+
+use crate::cli::InstallArgs;
+use crate::installer;
+use crate::installer::copy::{CopyStrategy, GameCopyOptions};
+use crate::installer::errors::AlloyDownloadError;
+use crate::installer::google_drive::GoogleDriveSource;
+use crate::installer::inquire::FilePathCompleter;
+use crate::installer::inquire::InquireGamePathValidator;
+use crate::installer::inquire::InquirePathDoesntExistValidator;
+use crate::installer::inquire::InquireDaisyMoonSourceValidator;
+use crate::installer::windows::exit_or_windows;
+use crate::installer::INSTALLER_FOLDER;
+
+/// Runs the interactive install
+pub async fn run(args: InstallArgs) {
+    println!();
+    println!("┏━┓╻  ╻  ┏━┓╻ ╻   ╻┏┓╻┏━┓╺┳╸┏━┓╻  ╻  ┏━╸┏━┓");
+    println!("┣━┫┃  ┃  ┃ ┃┗┳┛   ┃┃┗┫┗━┓ ┃ ┣━┫┃  ┃  ┣╸ ┣┳┛");
+    println!("╹ ╹┗━╸┗━╸┗━┛ ╹    ╹╹ ╹┗━┛ ╹ ╹ ╹┗━╸┗━╸┗━╸╹┗╸");
+    println!();
+
+    // Everything a lock pins but daisyMoon is checked before anything changes
+    let locked = match &args.locked {
+        Some(path) => Some(crate::commands::lock::prepare(path, args.target).await),
+        None => None,
+    };
+
+    let mut cobalt_dir: Option<PathBuf> = None;
+
+    let cobalt_directory_found = installer::cobalt::find_cobalt_path();
+
+    if let Some(dir) = cobalt_directory_found {
+        let prompt = format!("Found Cobalt at {}, is that right?", dir.display());
+        let confirm = inquire::Confirm::new(&prompt).with_default(true).prompt();
+        if confirm.expect("Cancelled") {
+            cobalt_dir = Some(dir);
+        }
+    }
+
+    if cobalt_dir.is_none() {
+        let prompt = "Please enter your Cobalt game folder path:";
+
+        let path = inquire::Text::new(prompt)
+            .with_validator(InquireGamePathValidator {})
+            .with_autocomplete(FilePathCompleter::default())
+            .prompt()
+            .unwrap();
+        cobalt_dir = Some(path.into());
+    }
+
+    println!();
+    println!(
+        "It is {} recommended you create a new copy of Cobalt for Alloy.",
+        "highly".italic()
+    );
+    println!("Installing to your main copy might break your game");
+    let mut create_new_copy = inquire::Confirm::new("Create a new copy and install there?")
+        .with_default(true)
+        .prompt()
+        .unwrap();
+
+    if !create_new_copy {
+        create_new_copy = !inquire::Confirm::new("Are you sure?")
+            .with_default(false)
+            .prompt()
+            .unwrap();
+
+        if create_new_copy {
+            println!("Okay, I will create a new copy");
+        }
+    }
+
+    let mut install_dir: Option<PathBuf> = None;
+    let mut copy_strategy: Option<CopyStrategy> = None;
+
+    if !create_new_copy && installer::steam::is_steam_managed(&cobalt_dir.clone().unwrap()) {
+        println!();
+        println!("{}", "Steam manages this folder.".bold());
+        println!("Verifying the game's files or a game update puts Steam's files back,");
+        println!("which undoes parts of Alloy and can leave the game unable to start.");
+        println!("I'll back up everything I change, and `verify` / `repair` can find and fix what Steam reverts.");
+
+        create_new_copy = !inquire::Confirm::new("Install into the Steam folder anyway?")
+            .with_default(false)
+            .prompt()
+            .unwrap();
+
+        if create_new_copy {
+            println!("Good call, I will create a new copy");
+        }
+    }
+
+    if !create_new_copy {
+        println!("Okay, if you say so...");
+        install_dir = cobalt_dir.clone();
+    }
+
+    if create_new_copy {
+        let mut parent_dir = cobalt_dir.clone().unwrap();
+        parent_dir = parent_dir.parent().unwrap().to_path_buf();
+
+        let mut copy_dir = parent_dir.join(installer::registry::COPY_FOLDER_NAME);
+
+        let mut update_existing = false;
+        if installer::metadata::metadata_path(&copy_dir).exists() {
+            // Copying the game over an install leaves what Alloy added behind
+            println!(
+                "{} already has Alloy, `sync` brings it up to date with the game and `update` updates Alloy.",
+                copy_dir.display()
+            );
+        } else if copy_dir.exists() {
+            let prompt = format!(
+                "You already have a copy at {}, update it instead of making another one?",
+                copy_dir.display()
+            );
+            update_existing = inquire::Confirm::new(&prompt)
+                .with_default(false)
+                .with_help_message("Only files that changed since it was made get copied")
+                .prompt()
+                .unwrap();
+        }
+
+        if !update_existing {
+            // If we already have a CobaltAlloy
+            copy_dir = installer::registry::next_copy_dir(&parent_dir);
+
+            let prompt = format!(
+                "Is {} okay? (for the new copy of Cobalt)",
+                copy_dir.display()
+            );
+            let copy_dir_ok = inquire::Confirm::new(&prompt)
+                .with_default(true)
+                .prompt()
+                .unwrap();
+
+            if !copy_dir_ok {
+                let prompt = "Okay, where should I create a new copy then?";
+                copy_dir = inquire::Text::new(prompt)
+                    .with_validator(InquirePathDoesntExistValidator {})
+                    .with_autocomplete(FilePathCompleter::default())
+                    .prompt()
+                    .unwrap()
+                    .into();
+            }
+        }
+
+        if update_existing {
+            if let Err(e) = installer::copy::clear_installer_paths(&copy_dir) {
+                println!("Failed to clear what I wrote to the copy before: {}", e);
+                exit_or_windows(10);
+            }
+        }
+
+        let plan_result = installer::copy::plan_copy(&cobalt_dir.clone().unwrap(), &copy_dir);
+        if let Err(e) = &plan_result {
+            println!("Failed to read your Cobalt folder: {}", e);
+            exit_or_windows(10);
+        }
+
+        let plan = plan_result.unwrap();
+
+        println!(
+            "Cobalt is {}, {} of it needs copying ({} files are already there).",
+            HumanBytes(plan.total_bytes),
+            HumanBytes(plan.bytes_to_copy),
+            plan.unchanged
+        );
+
+        let strategy = args.copy_strategy;
+
+        if let Err(e) = installer::copy::check_free_space(&copy_dir, plan.bytes_needed(strategy)) {
+            println!("Can't create a copy at {}: {}", copy_dir.display(), e);
+            exit_or_windows(11);
+        }
+
+        let options = GameCopyOptions {
+            verify: args.verify_copy,
+            strategy,
+            ..Default::default()
+        };
+
+        println!("Creating a copy of Cobalt at {}...", copy_dir.display());
+
+        let copy_result =
+            installer::copy::copy_game(&cobalt_dir.clone().unwrap(), &copy_dir, &plan, &options);
+        if let Err(e) = &copy_result {
+            println!("Failed to copy Cobalt: {}", e);
+            exit_or_windows(12);
+        }
+
+        let copy_report = copy_result.unwrap();
+        if let Some(warning) = copy_report.fallback_warning(strategy) {
+            println!("{}", warning.yellow());
+        }
+
+        println!("Created copy of Cobalt at {}!", copy_dir.display());
+
+        let mut sp = Spinner::new(
+            Spinners::Dots,
+            "Recording Cobalt's files so the copy can follow its updates...".into(),
+        );
+
+        let snapshot_result = installer::sync::snapshot_game(&cobalt_dir.clone().unwrap())
+            .and_then(|snapshot| installer::sync::write_snapshot(&copy_dir, &snapshot));
+
+        match snapshot_result {
+            Ok(()) => sp.stop_with_message("Recorded Cobalt's files!".into()),
+            Err(e) => sp.stop_with_message(format!(
+                "Couldn't record Cobalt's files, `sync` won't be able to tell which files you changed: {}",
+                e
+            )),
+        }
+
+        copy_strategy = Some(strategy);
+
+        install_dir = Some(copy_dir);
+    } 
+
+    // Whatever we write to from now on must not be shared with the game
+    if let Err(e) = installer::copy::ensure_installer_paths_unlinked(&install_dir.clone().unwrap()) {
+        println!("Failed to separate the copy from your game: {}", e);
+        exit_or_windows(13);
+    }
+
+    match installer::backup::create(&install_dir.clone().unwrap(), "before installing Alloy") {
+        Ok(backup) => println!(
+            "Backed up the files I'm about to change, `restore {}` puts them back.",
+            backup.id
+        ),
+        Err(e) => {
+            println!("Failed to back up the files I'm about to change: {}", e);
+            exit_or_windows(14);
+        }
+    }
+
+    let stored_daisymoons = installer::library::read_index().unwrap_or_else(|e| {
+        println!("Couldn't read your daisyMoon library, ignoring it: {}", e);
+        Vec::new()
+    });
+
+    let mut daisymoon_folder_path: Option<PathBuf> = None;
+    let mut daisymoon_source: Option<String> = None;
+    let mut reused_daisymoon = false;
+
+    // The daisyMoon a lock needs is used straight away when it's in the library
+    let locked_daisymoon = locked.as_ref().and_then(|locked| {
+        stored_daisymoons
+            .iter()
+            .position(|stored| stored.hash == locked.lock.daisymoon_hash)
+    });
+
+    if !stored_daisymoons.is_empty() {
+        println!();
+
+        let mut options: Vec<String> = stored_daisymoons
+            .iter()
+            .map(|stored| format!("Use the {}", stored.describe()))
+            .collect();
+        options.push("Import a different daisyMoon".to_string());
+
+        let index = match locked_daisymoon {
+            Some(index) => {
+                println!("Using the {}, which the lock needs.", stored_daisymoons[index].describe());
+                index
+            }
+            None => {
+                let prompt = "You've imported daisyMoon before, which one should I use?";
+                inquire::Select::new(prompt, options).raw_prompt().unwrap().index
+            }
+        };
+
+        if let Some(stored) = stored_daisymoons.get(index) {
+            let mut sp = Spinner::new(Spinners::Dots, "Creating daisyMoon folder...".into());
+
+            let daisy_path = install_dir.clone().unwrap().join("daisyMoon");
+
+            if let Err(e) = installer::library::copy_into(stored, &daisy_path) {
+                println!("Failed to copy the stored daisyMoon: {}", e);
+                exit_or_windows(9);
+            }
+
+            sp.stop_with_message("Created daisyMoon folder!".into());
+
+            daisymoon_folder_path = Some(daisy_path);
+            reused_daisymoon = true;
+        }
+    }
+
+    if daisymoon_folder_path.is_none() {
+        println!();
+        println!("One last thing: I need a decompiled daisyMoon folder.");
+        println!("You can either decompile it yourself, or you can download it from the Cobalt Archive:");
+        println!("(https://drive.google.com/drive/folders/1jasI5F9X8kWauTzx3fT-qy6_aMJZx_fi)");
+        println!();
+        println!(
+            "Once you have that ready, give me either the path to a zip or a full daisyMoon folder."
+        );
+    }
+
+    while daisymoon_folder_path.is_none() {
+        let input = inquire::Text::new("daisyMoon (folder, .zip, URL or Google Drive folder link)")
+            .with_validator(InquireDaisyMoonSourceValidator {})
+            .with_autocomplete(FilePathCompleter::default())
+            .prompt()
+            .unwrap();
+
+        let mut drive_api_key = args.drive_api_key.clone();
+        if drive_api_key.is_none() && GoogleDriveSource::folder_id_from_url(&input).is_some() {
+            println!("Downloading from Google Drive needs an API key from the Google Cloud console.");
+            drive_api_key = Some(
+                inquire::Password::new("Google Drive API key:")
+                    .without_confirmation()
+                    .prompt()
+                    .unwrap(),
+            );
+        }
+
+        let Some(source) = installer::daisymoon_source::source_from_input(&input, drive_api_key)
+        else {
+            println!("That's not a folder, zip or URL, please try again.");
+            continue;
+        };
+
+        println!("Fetching daisyMoon from {}...", source.describe());
+
+        let staging_result = installer::daisymoon_source::fetch_to_staging(source.as_ref()).await;
+        if let Err(e) = &staging_result {
+            println!("Failed to fetch daisyMoon: {}, please try again.", e);
+            continue;
+        }
+
+        let staging = staging_result.unwrap();
+
+        if !staging.join(installer::daisymoon::REQUIRED_FILE).exists() {
+            println!("That daisyMoon doesn't have the right files, please try again.");
+            continue;
+        }
+
+        let scan_result = installer::bytecode::scan_folder(&staging);
+        if let Err(e) = &scan_result {
+            println!("Failed to read that daisyMoon: {}, please try again.", e);
+            continue;
+        }
+
+        let scan = scan_result.unwrap();
+        if !scan.is_clean() {
+            installer::bytecode::print_scan(&scan);
+            continue;
+        }
+
+        let mut sp = Spinner::new(Spinners::Dots, "Creating daisyMoon folder...".into());
+
+        let daisy_path = install_dir.clone().unwrap().join("daisyMoon");
+
+        std::fs::create_dir_all(daisy_path.clone()).unwrap();
+
+        let options = CopyOptions {
+            copy_inside: true,
+            content_only: true,
+            overwrite: true, // We might be copying an entire daisyMoon folder from another alloy install
+            ..Default::default()
+        };
+
+        fs_extra::dir::copy(&staging, daisy_path.clone(), &options).unwrap();
+        let _ = std::fs::remove_dir_all(&staging);
+
+        sp.stop_with_message("Created daisyMoon folder!".into());
+
+        daisymoon_folder_path = Some(daisy_path);
+        daisymoon_source = Some(source.describe());
+    }
+
+    let game_build = installer::steam::read_build_id(&cobalt_dir.clone().unwrap());
+
+    let fingerprint_result =
+        installer::daisymoon::fingerprint_folder(&daisymoon_folder_path.clone().unwrap());
+    if let Err(e) = &fingerprint_result {
+        println!("Failed to read the daisyMoon folder: {}", e);
+        exit_or_windows(8);
+    }
+
+    let daisy_fingerprint = fingerprint_result.unwrap();
+    let daisy_hash = installer::library::content_hash(&daisy_fingerprint);
+    if let Some(locked) = &locked {
+        crate::commands::lock::check_daisymoon(locked, &daisy_hash);
+    }
+
+    let daisy_report = installer::daisymoon::identify(
+        &daisy_fingerprint,
+        &installer::daisymoon::known_releases(),
+    );
+    installer::daisymoon::print_report(&daisy_report, game_build.as_deref());
+
+    if !daisy_report.missing.is_empty() {
+        let keep_going = inquire::Confirm::new("Your daisyMoon is missing files, continue anyway?")
+            .with_default(false)
+            .prompt()
+            .unwrap();
+
+        if !keep_going {
+            println!("Okay, get a complete daisyMoon and try again.");
+            exit_or_windows(8);
+        }
+    }
+
+    if !reused_daisymoon {
+        let store_result = installer::library::store(
+            &daisymoon_folder_path.clone().unwrap(),
+            &daisy_fingerprint,
+            &daisy_report,
+            &daisymoon_source.clone().unwrap(),
+        );
+
+        match store_result {
+            Ok(stored) => println!(
+                "Saved this daisyMoon for next time ({}).",
+                stored.short_hash()
+            ),
+            Err(e) => println!("Couldn't save this daisyMoon for next time: {}", e),
+        }
+    }
+
+    let target = match &locked {
+        Some(locked) => locked.lock.target,
+        None => args.target.unwrap_or_default(),
+    };
+
+    if target.is_host() {
+        println!("Installing to {}...", install_dir.clone().unwrap().display());
+    } else {
+        println!(
+            "Installing to {} for {}...",
+            install_dir.clone().unwrap().display(),
+            target
+        );
+    }
+
+    installer::steam::create_app_id_txt(install_dir.clone().unwrap()).await;
+    println!("Created appid!");
+
+    let mut sp = Spinner::new(Spinners::Dots, "Downloading Alloy...".into());
+
+    if let Err(e) = std::fs::create_dir_all(install_dir.clone().unwrap().join(INSTALLER_FOLDER)) {
+        println!("Failed to create installer file directory: {}", e);
+        exit_or_windows(102);
+    }
+
+    let alloy_dl_result =
+        installer::alloy::download_alloy_files(install_dir.clone().unwrap(), target).await;
+    match &alloy_dl_result {
+        Err(AlloyDownloadError::Download { source }) => {
+            println!("Failed to download Alloy with error: {}", source);
+            println!("Are you connected to the internet?");
+            exit_or_windows(2);
+        }
+        Err(AlloyDownloadError::Translations { source }) => {
+            println!("Failed to put Alloy's translations into the game's: {}", source);
+            println!(
+                "Check the files in {} are the game's text files.",
+                install_dir.clone().unwrap().join("translations").display()
+            );
+            exit_or_windows(15);
+        }
+        Err(AlloyDownloadError::Io { source }) => {
+            println!("Failed to save Alloy: {}", source);
+            exit_or_windows(102);
+        }
+        Ok(_) => {}
+    }
+
+    sp.stop_with_message("Downloaded Alloy!".into());
+
+    let translations_report = alloy_dl_result.unwrap();
+    let conflicts = translations_report.conflicts().count();
+    if conflicts > 0 {
+        println!(
+            "{} of Alloy's translations replaced different text, the old text is listed in {}/{}",
+            conflicts,
+            INSTALLER_FOLDER,
+            installer::translations::TRANSLATIONS_REPORT_FILENAME
+        );
+    }
+
+    cfg_if! {
+        if #[cfg(target_os = "windows")] {
+            println!("Since you're running Windows, I'll need to download patch.exe");
+
+            let mut sp = Spinner::new(Spinners::Dots, "Downloading patch...".into());
+
+            let patch_dl_result = installer::gnuwin32::get_win32_patch(install_dir.clone().unwrap()).await;
+            if let Err(e) = patch_dl_result {
+                println!("Failed to download patch with error: {}", e);
+                println!("Are you connected to the internet?");
+                exit_or_windows(2);
+            }
+
+            sp.stop_with_message("Downloaded!".into());
+        }
+    }
+
+    let mut sp = Spinner::new(
+        Spinners::Dots,
+        "Syncing line endings with your system...".into(),
+    );
+
+    let normalize_result =
+        installer::line_endings::normalize_for_patch(&install_dir.clone().unwrap(), target.line_ending());
+    if let Err(e) = &normalize_result {
+        println!("Failed to sync line endings: {}", e);
+        exit_or_windows(3);
+    }
+
+    let normalized = normalize_result.unwrap();
+    sp.stop_with_message("Synced line endings!".into());
+
+    if let Err(e) = installer::update::keep_vanilla(&install_dir.clone().unwrap()) {
+        println!("Couldn't keep a copy of daisyMoon, `update` won't work on this install: {}", e);
+    }
+
+    println!("Running patch!");
+    installer::alloy::patch_daisy_with_alloy(install_dir.clone().unwrap(), target).await;
+
+    if let Err(e) = installer::update::keep_base(&install_dir.clone().unwrap()) {
+        println!("Couldn't keep a copy of the patched files, `update` won't work on this install: {}", e);
+    }
+
+    if let Err(e) = installer::line_endings::restore_after_patch(
+        &install_dir.clone().unwrap(),
+        &normalized,
+        args.restore_line_endings,
+    ) {
+        println!("Failed to restore line endings after patching: {}", e);
+        exit_or_windows(3);
+    }
+
+    println!("Successfully patched!");
+    
+    println!("Writing metadata to make future updating easier...");
+    let mut metadata = installer::metadata::InstallMetadata::new();
+    metadata.daisymoon_build = daisy_report.release.map(|release| release.cobalt_build);
+    metadata.daisymoon_hash = Some(daisy_hash);
+    metadata.translations_sha256 = Some(translations_report.alloy_sha256);
+    metadata.target = target;
+    metadata.copy_strategy = copy_strategy;
+    metadata.source_game = cobalt_dir.clone();
+    if copy_strategy.is_some() {
+        metadata.source_build = game_build;
+    }
+    // Installing again over an install keeps its mods
+    if let Some(previous) = installer::metadata::read_metadata(install_dir.clone().unwrap()) {
+        metadata.mods = previous.mods;
+        metadata.mod_profiles = previous.mod_profiles;
+        metadata.active_profile = previous.active_profile;
+        // Mod patches go back on top of the fresh diff
+        crate::commands::mods::reapply_patches(&install_dir.clone().unwrap(), &mut metadata, &previous.patch_stack).await;
+    }
+    installer::metadata::write_metadata(install_dir.clone().unwrap(), &metadata);
+
+    if let Err(e) = installer::registry::register(&install_dir.clone().unwrap(), &metadata) {
+        println!("Couldn't add this install to your list of installs: {}", e);
+    }
+    println!("Done!");
+    
+    if let Err(e) = std::fs::create_dir_all(install_dir.clone().unwrap().join(installer::mods::ALLOYS_FOLDER)) {
+        println!("Failed to create alloys directory: {}", e);
+        exit_or_windows(99);
+    }
+    
+    if copy_strategy.is_none() {
+        if let Err(e) = installer::in_place::write_marker(&install_dir.clone().unwrap()) {
+            println!("Couldn't record the installed files, `verify` won't work on this install: {}", e);
+        }
+    }
+
+    if let Some(locked) = locked {
+        crate::commands::lock::apply(&install_dir.clone().unwrap(), locked).await;
+    }
+
+    println!();
+    println!("{}", "Successfully installed Alloy!".bold());
+    if !crate::commands::shortcut::offer(install_dir.as_ref().unwrap(), target)
+        && !crate::commands::desktop::offer(install_dir.as_ref().unwrap(), target)
+    {
+        target.print_launch_instructions(&install_dir.unwrap());
+    }
+
+    exit_or_windows(0);
+}
@@ -0,0 +1,208 @@
+//! `list`, `rename`, `clone` and `remove`, managing the registry of installs
+
+use colored::Colorize;
+use indicatif::HumanBytes;
+
+use crate::cli::CloneArgs;
+use crate::installer::copy::{self, GameCopyOptions};
+use crate::installer::desktop;
+use crate::installer::errors::RegistryError;
+use crate::installer::metadata::{read_metadata, write_metadata};
+use crate::installer::registry;
+
+/// Lists every recorded install
+pub fn list() {
+    let installs = registry::read_registry().unwrap_or_else(|e| fail("read your installs", e));
+
+    if installs.is_empty() {
+        println!("You don't have any Alloy installs yet.");
+        return;
+    }
+
+    for install in installs {
+        if install.exists() {
+            println!("{}", install.name().bold());
+        } else {
+            println!("{} {}", install.name().bold(), "(missing)".red());
+        }
+        println!("  {}", install.describe());
+        if let Some(source) = &install.source_game {
+            if !install.is_in_place() {
+                println!("  Copied from {}", source.display());
+            }
+        }
+    }
+}
+
+/// Renames an install's folder, keeping it in the same place
+pub fn rename(query: &str, new_name: &str) {
+    let install = registry::find(query).unwrap_or_else(|e| fail("find that install", e));
+
+    if new_name.is_empty() || new_name.contains(['/', '\\']) || new_name == "." || new_name == ".."
+    {
+        println!("{} isn't a folder name.", new_name);
+        std::process::exit(1);
+    }
+
+    if install.is_in_place() {
+        fail(
+            "rename that install",
+            RegistryError::Refused {
+                reason: "it is the game folder Steam manages".to_string(),
+            },
+        );
+    }
+
+    let destination = install
+        .path
+        .parent()
+        .map(|parent| parent.join(new_name))
+        .unwrap_or_else(|| new_name.into());
+
+    if destination.exists() {
+        fail(
+            "rename that install",
+            RegistryError::AlreadyExists {
+                path: destination.display().to_string(),
+            },
+        );
+    }
+
+    if let Err(e) = std::fs::rename(&install.path, &destination) {
+        fail("rename that install", e.into());
+    }
+
+    let renamed = read_metadata(destination.clone())
+        .ok_or_else(|| RegistryError::NotFound {
+            query: destination.display().to_string(),
+        })
+        .and_then(|metadata| {
+            registry::unregister(&install.path)?;
+            registry::register(&destination, &metadata)
+        })
+        .unwrap_or_else(|e| fail("record the new name", e));
+
+    let target = read_metadata(destination.clone())
+        .map(|metadata| metadata.target)
+        .unwrap_or_default();
+    super::desktop::follow_rename(&install.path, &destination, target);
+
+    println!("Renamed {} to {}", install.name(), renamed.path.display());
+}
+
+/// Makes another copy of an install, with its mods
+pub fn clone(args: CloneArgs) {
+    let install = registry::find(&args.install).unwrap_or_else(|e| fail("find that install", e));
+
+    let destination = match args.destination {
+        Some(destination) => destination,
+        None => registry::next_copy_dir(install.path.parent().unwrap_or(&install.path)),
+    };
+
+    if destination.exists() {
+        fail(
+            "clone that install",
+            RegistryError::AlreadyExists {
+                path: destination.display().to_string(),
+            },
+        );
+    }
+
+    let plan = copy::plan_copy(&install.path, &destination)
+        .unwrap_or_else(|e| fail("read that install", e.into()));
+
+    println!(
+        "{} is {}, cloning it to {}...",
+        install.name(),
+        HumanBytes(plan.total_bytes),
+        destination.display()
+    );
+
+    let strategy = args.copy_strategy;
+
+    if let Err(e) = copy::check_free_space(&destination, plan.bytes_needed(strategy)) {
+        println!("Can't clone to {}: {}", destination.display(), e);
+        std::process::exit(1);
+    }
+
+    let options = GameCopyOptions {
+        strategy,
+        ..Default::default()
+    };
+
+    match copy::copy_game(&install.path, &destination, &plan, &options) {
+        Ok(report) => {
+            if let Some(warning) = report.fallback_warning(strategy) {
+                println!("{}", warning.yellow());
+            }
+        }
+        Err(e) => {
+            println!("Failed to clone {}: {}", install.name(), e);
+            std::process::exit(1);
+        }
+    }
+
+    let Some(mut metadata) = read_metadata(destination.clone()) else {
+        println!("The clone has no install metadata, was the install complete?");
+        std::process::exit(1);
+    };
+
+    metadata.created_at = Some(chrono::Utc::now());
+    metadata.copy_strategy = Some(strategy);
+    write_metadata(destination.clone(), &metadata);
+
+    let cloned =
+        registry::register(&destination, &metadata).unwrap_or_else(|e| fail("record the clone", e));
+
+    println!("Cloned {} to {}", install.name(), cloned.path.display());
+}
+
+/// Deletes an install and forgets it
+pub fn remove(query: &str, yes: bool) {
+    let install = registry::find(query).unwrap_or_else(|e| fail("find that install", e));
+
+    if install.is_in_place() {
+        fail(
+            "remove that install",
+            RegistryError::Refused {
+                reason: format!(
+                    "{} is the game itself, uninstall it from Steam instead",
+                    install.path.display()
+                ),
+            },
+        );
+    }
+
+    if install.path.exists() {
+        if !yes {
+            let prompt = format!("Delete {} and everything in it?", install.path.display());
+            let confirmed = inquire::Confirm::new(&prompt)
+                .with_default(false)
+                .prompt()
+                .unwrap();
+
+            if !confirmed {
+                println!("Okay, I won't touch it.");
+                return;
+            }
+        }
+
+        if let Err(e) = std::fs::remove_dir_all(&install.path) {
+            fail("delete that install", e.into());
+        }
+    }
+
+    registry::unregister(&install.path).unwrap_or_else(|e| fail("forget that install", e));
+
+    // The applications menu entry would start nothing now
+    if let Err(e) = desktop::remove_entries(&install.path) {
+        println!("Failed to remove it from your applications: {}", e);
+    }
+
+    println!("Removed {} ({})", install.name(), install.path.display());
+}
+
+fn fail(doing: &str, e: RegistryError) -> ! {
+    println!("Failed to {}: {}", doing, e);
+    std::process::exit(1);
+}
@@ -0,0 +1,157 @@
+//! `launch`, starting the game of an install
+
+use std::fs::File;
+use std::io::Write;
+use std::process::Stdio;
+
+use chrono::Local;
+use colored::Colorize;
+
+use crate::cli::LaunchArgs;
+use crate::installer::metadata::read_metadata;
+use crate::installer::registry::{self, RegisteredInstall};
+use crate::installer::runner::{self, Runner, COMPAT_DATA_FOLDER, LAUNCH_LOG_FILENAME};
+use crate::installer::steam::read_app_id;
+use crate::installer::INSTALLER_FOLDER;
+
+/// How many lines of the log are shown when the game fails
+const LOG_TAIL_LINES: usize = 15;
+
+/// The install asked for, or the only one, or the one picked from every install
+fn pick_install(query: Option<&str>) -> RegisteredInstall {
+    if let Some(query) = query {
+        return registry::find(query).unwrap_or_else(|e| {
+            println!("Failed to find the install: {}", e);
+            std::process::exit(1);
+        });
+    }
+
+    let mut installs: Vec<RegisteredInstall> = registry::read_registry()
+        .unwrap_or_else(|e| {
+            println!("Failed to find your installs: {}", e);
+            std::process::exit(1);
+        })
+        .into_iter()
+        .filter(RegisteredInstall::exists)
+        .collect();
+
+    match installs.len() {
+        0 => {
+            println!("You don't have any Alloy installs yet.");
+            std::process::exit(1);
+        }
+        1 => installs.remove(0),
+        _ => {
+            let names: Vec<String> = installs.iter().map(|install| install.describe()).collect();
+            let picked = inquire::Select::new("Which install?", names.clone())
+                .prompt()
+                .unwrap();
+            let index = names.iter().position(|name| *name == picked).unwrap();
+
+            installs.remove(index)
+        }
+    }
+}
+
+/// Starts an install's game and waits for it, its output going to the launch log
+pub fn run(args: LaunchArgs) {
+    let install = pick_install(args.install.as_deref());
+
+    let target = read_metadata(install.path.clone())
+        .map(|metadata| metadata.target)
+        .unwrap_or_default();
+
+    let runner = runner::choose(
+        args.runner,
+        target,
+        args.proton.as_deref(),
+        args.wine.as_deref(),
+    )
+    .unwrap_or_else(|e| {
+        println!("Can't start {}: {}", install.name(), e);
+        std::process::exit(1);
+    });
+
+    let appid = read_app_id(&install.path);
+    if appid.is_none() {
+        println!(
+            "{}",
+            "steam_appid.txt is missing, the game won't see Steam. `repair` puts it back.".yellow()
+        );
+    }
+
+    let installer_dir = install.path.join(INSTALLER_FOLDER);
+    let result = std::fs::create_dir_all(&installer_dir).and_then(|_| match runner {
+        Runner::Proton { .. } => std::fs::create_dir_all(installer_dir.join(COMPAT_DATA_FOLDER)),
+        _ => Ok(()),
+    });
+    if let Err(e) = result {
+        println!("Failed to prepare {}: {}", install.name(), e);
+        std::process::exit(1);
+    }
+
+    let command = runner::launch_command(&runner, &install.path, appid.as_deref(), &args.args);
+
+    let log_path = installer_dir.join(LAUNCH_LOG_FILENAME);
+    let log = File::create(&log_path).and_then(|mut log| {
+        writeln!(
+            log,
+            "Started {} {}",
+            Local::now().to_rfc2822(),
+            runner.describe()
+        )?;
+        writeln!(log, "{:?}", command.to_command())?;
+        writeln!(log)?;
+        Ok(log)
+    });
+    let log = log.unwrap_or_else(|e| {
+        println!("Failed to create {}: {}", log_path.display(), e);
+        std::process::exit(1);
+    });
+
+    let stdout = log.try_clone().map(Stdio::from);
+    let stderr = log.try_clone().map(Stdio::from);
+    let (Ok(stdout), Ok(stderr)) = (stdout, stderr) else {
+        println!("Failed to open {}", log_path.display());
+        std::process::exit(1);
+    };
+
+    println!(
+        "Starting {} {}, its output goes to {}",
+        install.name().bold(),
+        runner.describe(),
+        log_path.display()
+    );
+
+    let status = command
+        .to_command()
+        .stdin(Stdio::null())
+        .stdout(stdout)
+        .stderr(stderr)
+        .status();
+
+    let status = status.unwrap_or_else(|e| {
+        println!("Failed to start {}: {}", command.program.display(), e);
+        std::process::exit(1);
+    });
+
+    let Some(problem) = runner::describe_exit(status) else {
+        println!("Cobalt exited normally.");
+        return;
+    };
+
+    println!("{}", format!("Cobalt {}.", problem).red());
+
+    let contents = std::fs::read_to_string(&log_path).unwrap_or_default();
+    let lines: Vec<&str> = contents.lines().collect();
+    let tail = &lines[lines.len().saturating_sub(LOG_TAIL_LINES)..];
+
+    if !tail.is_empty() {
+        println!("The end of {}:", log_path.display());
+        for line in tail {
+            println!("  {}", line);
+        }
+    }
+
+    std::process::exit(1);
+}
@@ -0,0 +1,222 @@
+//! `lock`, and `install --locked`, making an install again exactly from an `alloy.lock`
+
+use std::collections::BTreeSet;
+use std::path::Path;
+
+use colored::Colorize;
+
+use crate::cli::LockCommand;
+use crate::commands::mods::{install_plan, load_indexes, remove_checked, restack, switch};
+use crate::installer::errors::LockProblem;
+use crate::installer::lock::{self, AlloyLock};
+use crate::installer::metadata::{read_metadata, write_metadata};
+use crate::installer::mod_patches::AppliedPatch;
+use crate::installer::mod_plan;
+use crate::installer::mods::{self, ModPackage};
+use crate::installer::platform::TargetPlatform;
+use crate::installer::registry::{self, RegisteredInstall};
+use crate::installer::windows::exit_or_windows;
+
+/// A lock checked before installing, with the packages of its mods
+pub struct Locked {
+    pub lock: AlloyLock,
+    pub packages: Vec<ModPackage>,
+}
+
+/// Runs a `lock` command
+pub async fn run(command: LockCommand) {
+    match command {
+        LockCommand::Export { install, output } => export(&find(&install), &output).await,
+    }
+}
+
+fn find(query: &str) -> RegisteredInstall {
+    registry::find(query).unwrap_or_else(|e| {
+        println!("Failed to find the install: {}", e);
+        std::process::exit(1);
+    })
+}
+
+async fn export(install: &RegisteredInstall, output: &Path) {
+    let Some(metadata) = read_metadata(install.path.clone()) else {
+        println!("{} has no metadata, reinstall it first.", install.name());
+        std::process::exit(1);
+    };
+
+    let indexes = match metadata.mods.is_empty() {
+        true => Vec::new(),
+        false => load_indexes().await,
+    };
+
+    let lock = lock::from_install(&install.path, &metadata, &indexes).unwrap_or_else(|e| {
+        println!("Failed to lock {}: {}", install.name(), e);
+        std::process::exit(1);
+    });
+
+    if let Err(e) = lock::write_lock(output, &lock) {
+        println!("Failed to write {}: {}", output.display(), e);
+        std::process::exit(1);
+    }
+
+    println!(
+        "Locked {} to Alloy {} for {} with {} mods in {}.",
+        install.name(),
+        lock.alloy_version,
+        lock.target,
+        lock.mods.len(),
+        output.display().to_string().bold()
+    );
+
+    for locked in lock.mods.iter().filter(|locked| locked.url.is_none()) {
+        println!(
+            "{}",
+            format!(
+                "{} {} isn't in your mod indexes, installing from the lock will need it in one.",
+                locked.id, locked.version
+            )
+            .yellow()
+        );
+    }
+}
+
+fn print_problems(problems: &[LockProblem]) {
+    println!("{}", "The lock can't be reproduced:".red());
+    for problem in problems {
+        println!("  {}", problem);
+    }
+}
+
+/// Reads a lock and checks everything it pins can be had, exiting and listing what can't
+///
+/// Only daisyMoon is left to check, once one is given.
+pub async fn prepare(path: &Path, target: Option<TargetPlatform>) -> Locked {
+    let lock = lock::read_lock(path).unwrap_or_else(|e| {
+        println!("Failed to read {}: {}", path.display(), e);
+        exit_or_windows(15);
+    });
+
+    println!("Checking everything {} pins can be had...", path.display());
+
+    let mut problems = lock::check_alloy(&lock, target).await;
+
+    let indexes = match lock.mods.iter().all(|locked| locked.url.is_some()) {
+        true => Vec::new(),
+        false => load_indexes().await,
+    };
+    let packages = match lock::fetch_mods(&lock, &indexes).await {
+        Ok(packages) => packages,
+        Err(mod_problems) => {
+            problems.extend(mod_problems);
+            Vec::new()
+        }
+    };
+
+    if !problems.is_empty() {
+        print_problems(&problems);
+        exit_or_windows(15);
+    }
+
+    Locked { lock, packages }
+}
+
+/// Exits, listing the problem, if daisyMoon isn't the one a lock needs
+pub fn check_daisymoon(locked: &Locked, hash: &str) {
+    if hash != locked.lock.daisymoon_hash {
+        print_problems(&[LockProblem::DaisyMoon {
+            expected: locked.lock.daisymoon_hash.clone(),
+            found: hash.to_string(),
+        }]);
+        exit_or_windows(15);
+    }
+}
+
+/// Makes the mods of a freshly installed Alloy exactly the ones of a lock
+pub async fn apply(install_dir: &Path, locked: Locked) {
+    let Some(mut metadata) = read_metadata(install_dir.to_path_buf()) else {
+        println!("Failed to read the metadata just written.");
+        exit_or_windows(15);
+    };
+    let install = RegisteredInstall::from_metadata(install_dir, &metadata);
+
+    // Mods the lock doesn't have, dependents first
+    let mut extra: Vec<String> = metadata
+        .mods
+        .iter()
+        .map(|installed| installed.id.clone())
+        .filter(|id| !locked.lock.mods.iter().any(|locked| locked.id == *id))
+        .collect();
+
+    while let Some(index) = extra
+        .iter()
+        .position(|id| mods::check_removable(&metadata, id).is_ok())
+    {
+        let id = extra.remove(index);
+        remove_checked(&install, &mut metadata, &id).await;
+    }
+
+    if !extra.is_empty() {
+        write_metadata(install_dir.to_path_buf(), &metadata);
+        println!(
+            "Failed to remove {}, which the installed mods need and the lock doesn't have.",
+            extra.join(", ")
+        );
+        exit_or_windows(15);
+    }
+
+    // Mods already installed exactly as locked stay as they are
+    let needed: Vec<ModPackage> = locked
+        .lock
+        .mods
+        .iter()
+        .zip(locked.packages)
+        .filter(|(locked, _)| {
+            !metadata.mods.iter().any(|installed| {
+                installed.id == locked.id
+                    && installed.version == locked.version
+                    && installed.content_hash() == locked.sha256
+            })
+        })
+        .map(|(_, package)| package)
+        .collect();
+
+    if !needed.is_empty() {
+        let plan = mod_plan::resolve(&metadata.mods, &metadata.alloy_version, &needed, &[])
+            .unwrap_or_else(|problems| {
+                print_problems(
+                    &problems
+                        .into_iter()
+                        .map(|problem| LockProblem::Plan { problem })
+                        .collect::<Vec<_>>(),
+                );
+                exit_or_windows(15);
+            });
+        install_plan(&install, &mut metadata, &plan).await;
+    }
+
+    let enabled: BTreeSet<String> = locked
+        .lock
+        .mods
+        .iter()
+        .filter(|locked| !locked.disabled)
+        .map(|locked| locked.id.clone())
+        .collect();
+    switch(&install, &mut metadata, &enabled).await;
+
+    // The patches end up stacked like they were locked
+    let wanted: Vec<AppliedPatch> = locked
+        .lock
+        .mods
+        .iter()
+        .filter(|locked| !locked.disabled)
+        .filter_map(|locked| {
+            metadata
+                .mods
+                .iter()
+                .find(|installed| installed.id == locked.id)
+        })
+        .flat_map(|installed| installed.patches.clone())
+        .collect();
+    restack(&install, &mut metadata, wanted).await;
+
+    write_metadata(install_dir.to_path_buf(), &metadata);
+}
@@ -0,0 +1,15 @@
+//! What each command of the installer does
+
+pub mod backups;
+pub mod desktop;
+pub mod install;
+pub mod installs;
+pub mod launch;
+pub mod lock;
+pub mod mods;
+pub mod shortcut;
+pub mod sources;
+pub mod sync;
+pub mod translations;
+pub mod update;
+pub mod verify;
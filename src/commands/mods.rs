@@ -0,0 +1,914 @@
+//! `mod`, installing, removing and updating the mods of an install
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use colored::Colorize;
+use semver::Version;
+
+use crate::cli::{ModCommand, ModIndexCommand, ModProfileCommand};
+use crate::installer::backup;
+use crate::installer::errors::{ModError, PlanProblem};
+use crate::installer::metadata::{read_metadata, write_metadata, InstallMetadata};
+use crate::installer::mod_authoring;
+use crate::installer::mod_index::{self, Found, IndexedMod, LoadedIndex};
+use crate::installer::mod_patches::{self, AppliedPatch};
+use crate::installer::mod_plan::{self, InstallPlan};
+use crate::installer::mod_profiles::{self, enabled_ids};
+use crate::installer::mods::{self, InstalledMod, ModPackage};
+use crate::installer::registry::{self, RegisteredInstall};
+
+/// Runs a `mod` command
+pub async fn run(command: ModCommand) {
+    match command {
+        ModCommand::Install {
+            install,
+            packages,
+            from,
+        } => install_packages(&find(&install), &packages, &from).await,
+        ModCommand::Remove { install, id } => remove(&find(&install), &id).await,
+        ModCommand::List { install } => list(&find(&install)),
+        ModCommand::Enable { install, ids } => toggle(&find(&install), &ids, true).await,
+        ModCommand::Disable { install, ids } => toggle(&find(&install), &ids, false).await,
+        ModCommand::Profile { command } => profile(command).await,
+        ModCommand::Search { query } => search(query.as_deref().unwrap_or_default()).await,
+        ModCommand::Info { id } => info(&id).await,
+        ModCommand::Outdated { install } => outdated(&find(&install)).await,
+        ModCommand::Update { install, ids } => update(&find(&install), &ids).await,
+        ModCommand::Index { command } => index(command).await,
+        ModCommand::New {
+            folder,
+            id,
+            name,
+            author,
+        } => new(&folder, id, name, author),
+        ModCommand::Diff {
+            install,
+            paths,
+            output,
+        } => diff(&find(&install), &paths, output),
+        ModCommand::Pack { folder, output } => pack(&folder, output.as_deref()),
+    }
+}
+
+fn find(query: &str) -> RegisteredInstall {
+    registry::find(query).unwrap_or_else(|e| {
+        println!("Failed to find the install: {}", e);
+        std::process::exit(1);
+    })
+}
+
+fn metadata_of(install: &RegisteredInstall) -> InstallMetadata {
+    read_metadata(install.path.clone()).unwrap_or_else(|| {
+        println!("{} has no metadata, reinstall it first.", install.name());
+        std::process::exit(1);
+    })
+}
+
+/// Reads every mod index in the list, warning about the ones that can't be read
+pub async fn load_indexes() -> Vec<LoadedIndex> {
+    let locations = mod_index::configured_indexes().unwrap_or_else(|e| {
+        println!("Failed to read your list of mod indexes: {}", e);
+        std::process::exit(1);
+    });
+
+    let mut loaded = Vec::new();
+    for location in locations {
+        match mod_index::load_index(&location).await {
+            Ok(index) => loaded.push(index),
+            Err(e) => println!(
+                "{}",
+                format!("Couldn't read the mod index {}: {}", location, e).yellow()
+            ),
+        }
+    }
+
+    loaded
+}
+
+/// Reads the mod indexes, exiting if there are none to look in
+async fn require_indexes() -> Vec<LoadedIndex> {
+    let indexes = load_indexes().await;
+
+    if indexes.is_empty() {
+        println!(
+            "There are no mod indexes to look in, add one with `mod index add <url or path>`."
+        );
+        std::process::exit(1);
+    }
+
+    indexes
+}
+
+/// The mod indexes, read the first time they're needed
+async fn lazy_indexes(indexes: &mut Option<Vec<LoadedIndex>>) -> &[LoadedIndex] {
+    if indexes.is_none() {
+        *indexes = Some(load_indexes().await);
+    }
+
+    indexes.as_deref().unwrap_or_default()
+}
+
+/// Downloads a version from an index, exiting if it fails
+async fn fetch_indexed(found: Found<'_>) -> ModPackage {
+    mod_index::fetch_indexed(found).await.unwrap_or_else(|e| {
+        println!(
+            "Failed to download {} {}: {}",
+            found.listed.id, found.version.version, e
+        );
+        std::process::exit(1);
+    })
+}
+
+/// Fetches packages, or the newest version of mods in the indexes, exiting if one can't be read
+async fn fetch_all(
+    inputs: &[String],
+    alloy_version: &str,
+    indexes: &mut Option<Vec<LoadedIndex>>,
+) -> Vec<ModPackage> {
+    let mut packages = Vec::new();
+
+    for input in inputs {
+        match mods::fetch_package(input).await {
+            Ok(package) => packages.push(package),
+            Err(ModError::NotAPackage { .. }) => {
+                let indexes = lazy_indexes(indexes).await;
+
+                let Some(found) = mod_index::newest(indexes, input, "*", Some(alloy_version))
+                else {
+                    println!(
+                        "{} isn't a package, and no mod index has a version of it for Alloy {}.",
+                        input, alloy_version
+                    );
+                    std::process::exit(1);
+                };
+
+                packages.push(fetch_indexed(found).await);
+            }
+            Err(e) => {
+                println!("Failed to read the mod {}: {}", input, e);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    packages
+}
+
+/// Works out how to install packages, downloading the dependencies missing from the indexes
+async fn plan(
+    metadata: &InstallMetadata,
+    requested: &[ModPackage],
+    mut available: Vec<ModPackage>,
+    indexes: &mut Option<Vec<LoadedIndex>>,
+) -> InstallPlan {
+    loop {
+        let problems = match mod_plan::resolve(
+            &metadata.mods,
+            &metadata.alloy_version,
+            requested,
+            &available,
+        ) {
+            Ok(plan) => return plan,
+            Err(problems) => problems,
+        };
+
+        let mut fetched = false;
+        for problem in &problems {
+            let (PlanProblem::Missing {
+                dependency,
+                requirement,
+                ..
+            }
+            | PlanProblem::WrongVersion {
+                dependency,
+                requirement,
+                ..
+            }) = problem
+            else {
+                continue;
+            };
+
+            let indexes = lazy_indexes(indexes).await;
+            let Some(found) = mod_index::newest(
+                indexes,
+                dependency,
+                requirement,
+                Some(&metadata.alloy_version),
+            ) else {
+                continue;
+            };
+
+            let have = available.iter().chain(requested).any(|package| {
+                package.manifest.id == found.listed.id
+                    && package.manifest.version == found.version.version
+            });
+            if !have {
+                available.push(fetch_indexed(found).await);
+                fetched = true;
+            }
+        }
+
+        if !fetched {
+            println!("{}", "These mods can't be installed together:".red());
+            for problem in problems {
+                println!("  {}", problem);
+            }
+            std::process::exit(1);
+        }
+    }
+}
+
+/// The patches of the `enabled` mods, in the order the mods are in
+fn patches_of<'a>(
+    metadata: &'a InstallMetadata,
+    enabled: &BTreeSet<String>,
+) -> Vec<&'a AppliedPatch> {
+    metadata
+        .mods
+        .iter()
+        .filter(|installed| enabled.contains(&installed.id))
+        .flat_map(|installed| &installed.patches)
+        .collect()
+}
+
+/// Records the patches applied to daisyMoon, keeping what `repair` needs
+fn record_patches(install_dir: &Path, metadata: &mut InstallMetadata, stack: Vec<AppliedPatch>) {
+    if let Err(e) = mod_patches::record_stack(install_dir, &metadata.patch_stack, &stack) {
+        println!(
+            "Couldn't keep a copy of the patched files, `repair` won't know about them: {}",
+            e
+        );
+    }
+
+    metadata.patch_stack = stack;
+}
+
+/// Patches daisyMoon so exactly `wanted` is applied, exiting with nothing changed if a patch fails
+pub async fn restack(
+    install: &RegisteredInstall,
+    metadata: &mut InstallMetadata,
+    wanted: Vec<AppliedPatch>,
+) {
+    if wanted == metadata.patch_stack {
+        return;
+    }
+
+    if let Err(e) = backup::create(&install.path, "before patching mods") {
+        println!("Failed to back up the files I'm about to change: {}", e);
+        std::process::exit(1);
+    }
+
+    let result = mod_patches::rebuild_stack(
+        &install.path,
+        metadata.target,
+        &metadata.patch_stack,
+        &wanted,
+    )
+    .await;
+
+    if let Err(e) = result {
+        println!("Failed to patch daisyMoon, nothing was changed: {}", e);
+        clean_patches(install, metadata);
+        std::process::exit(1);
+    }
+
+    record_patches(&install.path, metadata, wanted);
+}
+
+/// Applies the mod patches again after daisyMoon was patched anew, disabling the mods whose patches don't apply anymore
+pub async fn reapply_patches(
+    install_dir: &Path,
+    metadata: &mut InstallMetadata,
+    stack: &[AppliedPatch],
+) {
+    if stack.is_empty() {
+        return;
+    }
+
+    let (applied, left_out) = mod_patches::reapply_stack(install_dir, metadata.target, stack).await;
+    record_patches(install_dir, metadata, applied);
+
+    if left_out.is_empty() {
+        return;
+    }
+
+    let mut wanted = enabled_ids(metadata);
+    for (id, e) in &left_out {
+        println!("{}", format!("Disabled {}: {}", id, e).yellow());
+        wanted.remove(id);
+    }
+
+    for problem in mod_profiles::check_enabled(metadata, &wanted) {
+        println!("{}", format!("  {}", problem).yellow());
+    }
+
+    if let Err(e) = mod_profiles::set_enabled(install_dir, metadata, &wanted) {
+        println!("Failed to move the disabled mods out of alloys: {}", e);
+    }
+}
+
+/// Deletes the kept patches of mods that were removed or replaced
+fn clean_patches(install: &RegisteredInstall, metadata: &InstallMetadata) {
+    if let Err(e) = mod_patches::clean_patches(&install.path, metadata) {
+        println!("Couldn't delete the patches of mods that are gone: {}", e);
+    }
+}
+
+/// Patches daisyMoon, then installs the packages of a plan and writes the metadata
+pub async fn install_plan(
+    install: &RegisteredInstall,
+    metadata: &mut InstallMetadata,
+    plan: &InstallPlan,
+) {
+    if !plan.pulled_in.is_empty() {
+        println!(
+            "Also installing what they depend on: {}",
+            plan.pulled_in.join(", ")
+        );
+    }
+
+    // The new patches go on top of the ones of the mods staying
+    let mut enabled = enabled_ids(metadata);
+    for package in &plan.packages {
+        enabled.remove(&package.manifest.id);
+    }
+    let mut patches: Vec<AppliedPatch> = patches_of(metadata, &enabled)
+        .into_iter()
+        .cloned()
+        .collect();
+    for package in &plan.packages {
+        match mod_patches::store_patches(&install.path, package) {
+            Ok(stored) => patches.extend(stored),
+            Err(e) => {
+                println!("Failed to install {}: {}", package.manifest.id, e);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    let wanted = mod_patches::wanted_stack(&metadata.patch_stack, &patches);
+    restack(install, metadata, wanted).await;
+
+    for package in &plan.packages {
+        let manifest = &package.manifest;
+
+        match mods::install_mod(&install.path, metadata, package) {
+            Ok(Some(previous)) if previous.version == manifest.version => println!(
+                "Reinstalled {} {} in {}.",
+                manifest.name.bold(),
+                manifest.version,
+                install.name()
+            ),
+            Ok(Some(previous))
+                if Version::parse(&previous.version).ok()
+                    > Version::parse(&manifest.version).ok() =>
+            {
+                println!(
+                    "Downgraded {} from {} to {} in {}.",
+                    manifest.name.bold(),
+                    previous.version,
+                    manifest.version,
+                    install.name()
+                )
+            }
+            Ok(Some(previous)) => println!(
+                "Updated {} from {} to {} in {}.",
+                manifest.name.bold(),
+                previous.version,
+                manifest.version,
+                install.name()
+            ),
+            Ok(None) => println!(
+                "Installed {} {} by {} into {}.",
+                manifest.name.bold(),
+                manifest.version,
+                manifest.author,
+                install.name()
+            ),
+            Err(e) => {
+                println!("Failed to install {}: {}", manifest.id, e);
+                write_metadata(install.path.clone(), metadata);
+
+                // Only the patches of the mods that are installed stay on
+                let installed = patches_of(metadata, &enabled_ids(metadata));
+                let wanted = mod_patches::wanted_stack(&metadata.patch_stack, installed);
+                restack(install, metadata, wanted).await;
+
+                clean_patches(install, metadata);
+                write_metadata(install.path.clone(), metadata);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    clean_patches(install, metadata);
+    write_metadata(install.path.clone(), metadata);
+}
+
+async fn install_packages(install: &RegisteredInstall, inputs: &[String], from: &[String]) {
+    let mut metadata = metadata_of(install);
+    let mut indexes = None;
+
+    let requested = fetch_all(inputs, &metadata.alloy_version, &mut indexes).await;
+    let available = fetch_all(from, &metadata.alloy_version, &mut indexes).await;
+
+    let plan = plan(&metadata, &requested, available, &mut indexes).await;
+    install_plan(install, &mut metadata, &plan).await;
+}
+
+async fn remove(install: &RegisteredInstall, id: &str) {
+    let mut metadata = metadata_of(install);
+
+    if let Err(e) = mods::check_removable(&metadata, id) {
+        println!("Failed to remove {}: {}", id, e);
+        std::process::exit(1);
+    }
+
+    remove_checked(install, &mut metadata, id).await;
+    write_metadata(install.path.clone(), &metadata);
+}
+
+/// Takes the patches of a mod off and deletes it, once `check_removable` said it can go
+pub async fn remove_checked(install: &RegisteredInstall, metadata: &mut InstallMetadata, id: &str) {
+    let mut enabled = enabled_ids(metadata);
+    enabled.remove(id);
+    let wanted = mod_patches::wanted_stack(&metadata.patch_stack, patches_of(metadata, &enabled));
+    restack(install, metadata, wanted).await;
+
+    match mods::remove_mod(&install.path, metadata, id) {
+        Ok(removed) => println!(
+            "Removed {} {} from {}.",
+            removed.name.bold(),
+            removed.version,
+            install.name()
+        ),
+        Err(e) => {
+            println!("Failed to remove {}: {}", id, e);
+            write_metadata(install.path.clone(), metadata);
+            std::process::exit(1);
+        }
+    }
+
+    clean_patches(install, metadata);
+}
+
+fn list(install: &RegisteredInstall) {
+    let metadata = metadata_of(install);
+
+    if metadata.mods.is_empty() {
+        println!("{} has no mods.", install.name());
+        return;
+    }
+
+    for installed in &metadata.mods {
+        let line = format!(
+            "{} {} - {} by {}",
+            installed.id, installed.version, installed.name, installed.author
+        );
+
+        match installed.disabled {
+            true => println!("{} {}", line.dimmed(), "(disabled)".dimmed()),
+            false => println!("{}", line),
+        }
+    }
+
+    if let Some(active) = &metadata.active_profile {
+        match metadata.mod_profiles.get(active) == Some(&enabled_ids(&metadata)) {
+            true => println!("Profile: {}", active.bold()),
+            false => println!("Profile: {} (changed since)", active.bold()),
+        }
+    }
+}
+
+/// Enables exactly the `wanted` mods, exiting if a mod would miss a dependency
+pub async fn switch(
+    install: &RegisteredInstall,
+    metadata: &mut InstallMetadata,
+    wanted: &BTreeSet<String>,
+) {
+    let problems = mod_profiles::check_enabled(metadata, wanted);
+
+    if !problems.is_empty() {
+        println!("{}", "That would break mods:".red());
+        for problem in problems {
+            println!("  {}", problem);
+        }
+        std::process::exit(1);
+    }
+
+    let previous = metadata.patch_stack.clone();
+    let stack = mod_patches::wanted_stack(&metadata.patch_stack, patches_of(metadata, wanted));
+    restack(install, metadata, stack).await;
+
+    let changes = match mod_profiles::set_enabled(&install.path, metadata, wanted) {
+        Ok(changes) => changes,
+        Err(e) => {
+            restack(install, metadata, previous).await;
+            println!("Failed to move the mods, nothing was changed: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    if !changes.enabled.is_empty() {
+        println!("Enabled {}.", changes.enabled.join(", "));
+    }
+    if !changes.disabled.is_empty() {
+        println!("Disabled {}.", changes.disabled.join(", "));
+    }
+    if changes.enabled.is_empty() && changes.disabled.is_empty() {
+        println!("Nothing to change.");
+    }
+}
+
+async fn toggle(install: &RegisteredInstall, ids: &[String], enable: bool) {
+    let mut metadata = metadata_of(install);
+    let mut wanted = enabled_ids(&metadata);
+
+    for id in ids {
+        if !metadata.mods.iter().any(|installed| installed.id == *id) {
+            println!("{} isn't installed in {}.", id, install.name());
+            std::process::exit(1);
+        }
+
+        match enable {
+            true => wanted.insert(id.clone()),
+            false => wanted.remove(id),
+        };
+    }
+
+    switch(install, &mut metadata, &wanted).await;
+
+    write_metadata(install.path.clone(), &metadata);
+}
+
+fn describe_profile(ids: &BTreeSet<String>) -> String {
+    match ids.is_empty() {
+        true => "no mods".to_string(),
+        false => ids.iter().cloned().collect::<Vec<_>>().join(", "),
+    }
+}
+
+async fn profile(command: ModProfileCommand) {
+    match command {
+        ModProfileCommand::Save { install, name } => {
+            let install = find(&install);
+            let mut metadata = metadata_of(&install);
+
+            let enabled = enabled_ids(&metadata);
+            println!(
+                "Saved the profile {}: {}",
+                name.bold(),
+                describe_profile(&enabled)
+            );
+            metadata.mod_profiles.insert(name.clone(), enabled);
+            metadata.active_profile = Some(name);
+
+            write_metadata(install.path.clone(), &metadata);
+        }
+        ModProfileCommand::Use { install, name } => {
+            let install = find(&install);
+            let mut metadata = metadata_of(&install);
+
+            let (wanted, missing) =
+                mod_profiles::profile_mods(&metadata, &name).unwrap_or_else(|e| {
+                    println!("{}", e);
+                    std::process::exit(1);
+                });
+            if !missing.is_empty() {
+                println!(
+                    "{}",
+                    format!(
+                        "These mods of the profile aren't installed: {}",
+                        missing.join(", ")
+                    )
+                    .yellow()
+                );
+            }
+
+            switch(&install, &mut metadata, &wanted).await;
+            println!(
+                "Switched {} to the profile {}.",
+                install.name(),
+                name.bold()
+            );
+
+            metadata.active_profile = Some(name);
+            write_metadata(install.path.clone(), &metadata);
+        }
+        ModProfileCommand::List { install } => {
+            let install = find(&install);
+            let metadata = metadata_of(&install);
+
+            if metadata.mod_profiles.is_empty() {
+                println!("{} has no mod profiles.", install.name());
+            }
+
+            for (name, ids) in &metadata.mod_profiles {
+                let line = format!("{}: {}", name, describe_profile(ids));
+
+                match metadata.active_profile.as_ref() == Some(name) {
+                    true => println!("{} {}", line.bold(), "(active)".green()),
+                    false => println!("{}", line),
+                }
+            }
+        }
+        ModProfileCommand::Delete { install, name } => {
+            let install = find(&install);
+            let mut metadata = metadata_of(&install);
+
+            if metadata.mod_profiles.remove(&name).is_none() {
+                println!("{} has no mod profile {}.", install.name(), name);
+                std::process::exit(1);
+            }
+            if metadata.active_profile.as_ref() == Some(&name) {
+                metadata.active_profile = None;
+            }
+
+            println!("Deleted the profile {}.", name);
+            write_metadata(install.path.clone(), &metadata);
+        }
+    }
+}
+
+/// Every mod in the indexes once, with its newest version
+fn every_mod(indexes: &[LoadedIndex]) -> BTreeMap<&str, (&IndexedMod, String)> {
+    let mut found = BTreeMap::new();
+
+    for listed in indexes.iter().flat_map(|index| &index.index.mods) {
+        if let Some(newest) = mod_index::newest(indexes, &listed.id, "*", None) {
+            found
+                .entry(listed.id.as_str())
+                .or_insert((listed, newest.version.version.clone()));
+        }
+    }
+
+    found
+}
+
+async fn search(query: &str) {
+    let indexes = require_indexes().await;
+    let query = query.to_lowercase();
+
+    let matching: Vec<_> = every_mod(&indexes)
+        .into_values()
+        .filter(|(listed, _)| {
+            [&listed.id, &listed.name, &listed.description]
+                .iter()
+                .any(|text| text.to_lowercase().contains(&query))
+        })
+        .collect();
+
+    if matching.is_empty() {
+        println!("No mod matches {}.", query);
+        return;
+    }
+
+    for (listed, newest) in matching {
+        println!(
+            "{} {} - {} by {}",
+            listed.id.bold(),
+            newest,
+            listed.name,
+            listed.author
+        );
+        if !listed.description.is_empty() {
+            println!("    {}", listed.description);
+        }
+    }
+}
+
+async fn info(id: &str) {
+    let indexes = require_indexes().await;
+
+    let mut versions = mod_index::find_versions(&indexes, id);
+    let Some(first) = versions.first().copied() else {
+        println!("No mod index has {}.", id);
+        std::process::exit(1);
+    };
+
+    versions.sort_by_key(|found| Version::parse(&found.version.version).ok());
+    versions.reverse();
+
+    println!(
+        "{} ({}) by {}",
+        first.listed.name.bold(),
+        first.listed.id,
+        first.listed.author
+    );
+    if !first.listed.description.is_empty() {
+        println!("{}", first.listed.description);
+    }
+
+    for found in versions {
+        println!(
+            "  {} for Alloy {}, from {}",
+            found.version.version.bold(),
+            found.version.alloy_versions.join(" or "),
+            found.index.location
+        );
+
+        for (dependency, requirement) in &found.version.depends {
+            println!("      needs {} {}", dependency, requirement);
+        }
+        for (other, requirement) in &found.version.conflicts {
+            println!("      conflicts with {} {}", other, requirement);
+        }
+    }
+}
+
+/// The installed mods that have a newer version for the install's Alloy in the indexes
+fn find_outdated<'a>(
+    metadata: &'a InstallMetadata,
+    indexes: &'a [LoadedIndex],
+) -> Vec<(&'a InstalledMod, Found<'a>)> {
+    metadata
+        .mods
+        .iter()
+        .filter_map(|installed| {
+            let newest =
+                mod_index::newest(indexes, &installed.id, "*", Some(&metadata.alloy_version))?;
+
+            let current = Version::parse(&installed.version).ok()?;
+            let available = Version::parse(&newest.version.version).ok()?;
+
+            (available > current).then_some((installed, newest))
+        })
+        .collect()
+}
+
+async fn outdated(install: &RegisteredInstall) {
+    let metadata = metadata_of(install);
+    let indexes = require_indexes().await;
+
+    let outdated = find_outdated(&metadata, &indexes);
+
+    if outdated.is_empty() {
+        println!("Every mod of {} is up to date.", install.name());
+        return;
+    }
+
+    for (installed, newest) in outdated {
+        println!(
+            "{} {} -> {}",
+            installed.id.bold(),
+            installed.version,
+            newest.version.version.green()
+        );
+    }
+}
+
+async fn update(install: &RegisteredInstall, ids: &[String]) {
+    let mut metadata = metadata_of(install);
+    let indexes = require_indexes().await;
+
+    for id in ids {
+        if !metadata.mods.iter().any(|installed| installed.id == *id) {
+            println!("{} isn't installed in {}.", id, install.name());
+            std::process::exit(1);
+        }
+    }
+
+    let mut requested = Vec::new();
+    for (installed, newest) in find_outdated(&metadata, &indexes) {
+        if ids.is_empty() || ids.contains(&installed.id) {
+            requested.push(fetch_indexed(newest).await);
+        }
+    }
+
+    if requested.is_empty() {
+        println!("Every mod of {} is up to date.", install.name());
+        return;
+    }
+
+    let plan = plan(&metadata, &requested, Vec::new(), &mut Some(indexes)).await;
+    install_plan(install, &mut metadata, &plan).await;
+}
+
+async fn index(command: ModIndexCommand) {
+    let result = match command {
+        ModIndexCommand::Add { location } => {
+            if let Err(e) = mod_index::load_index(&location).await {
+                println!("Failed to read {}: {}", location, e);
+                std::process::exit(1);
+            }
+
+            mod_index::add_index(&location).map(|added| match added {
+                true => println!("Added the mod index {}.", location),
+                false => println!("{} is already one of your mod indexes.", location),
+            })
+        }
+        ModIndexCommand::Remove { location } => {
+            mod_index::remove_index(&location).map(|removed| match removed {
+                true => println!("Removed the mod index {}.", location),
+                false => println!("{} isn't one of your mod indexes.", location),
+            })
+        }
+        ModIndexCommand::List => mod_index::configured_indexes().map(|indexes| {
+            if indexes.is_empty() {
+                println!("You don't have any mod indexes yet.");
+            }
+            for index in indexes {
+                println!("{}", index);
+            }
+        }),
+    };
+
+    if let Err(e) = result {
+        println!("Failed to update your list of mod indexes: {}", e);
+        std::process::exit(1);
+    }
+}
+
+fn new(folder: &Path, id: Option<String>, name: Option<String>, author: Option<String>) {
+    let id = id.unwrap_or_else(|| {
+        folder
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default()
+    });
+    let name = name.unwrap_or_else(|| id.clone());
+    let author = author.unwrap_or_else(whoami::username);
+
+    match mod_authoring::scaffold(folder, &id, &name, &author) {
+        Ok(manifest) => {
+            println!("Started {} in {}.", manifest.name.bold(), folder.display());
+            println!(
+                "List its files in {}, then zip it with `mod pack {}`.",
+                mods::MANIFEST_FILENAME,
+                folder.display()
+            );
+        }
+        Err(e) => {
+            println!("Failed to start the mod: {}", e);
+            if matches!(e, ModError::BadId { .. }) {
+                println!("Give it one with --id.");
+            }
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Writes the diff to a file, or prints it with everything else going to stderr so it can be piped
+fn diff(install: &RegisteredInstall, paths: &[String], output: Option<PathBuf>) {
+    let daisy_diff = mod_authoring::diff_daisy(&install.path, paths).unwrap_or_else(|e| {
+        println!("Failed to diff {}: {}", install.name(), e);
+        std::process::exit(1);
+    });
+
+    for binary in &daisy_diff.binary {
+        eprintln!(
+            "{}",
+            format!("Left out {}, it isn't text.", binary).yellow()
+        );
+    }
+
+    if daisy_diff.changed.is_empty() {
+        eprintln!("daisyMoon is the way the installer left it.");
+        return;
+    }
+
+    let Some(output) = output else {
+        if let Err(e) = std::io::stdout().write_all(&daisy_diff.diff) {
+            eprintln!("Failed to print the diff: {}", e);
+            std::process::exit(1);
+        }
+        return;
+    };
+
+    if let Err(e) = std::fs::write(&output, &daisy_diff.diff) {
+        println!("Failed to write {}: {}", output.display(), e);
+        std::process::exit(1);
+    }
+
+    println!(
+        "Wrote the changes to these files into {}:",
+        output.display()
+    );
+    for changed in &daisy_diff.changed {
+        println!("  {}", changed);
+    }
+    println!(
+        "Put it in a mod's folder and list it in the patches of its {} to ship them.",
+        mods::MANIFEST_FILENAME
+    );
+}
+
+fn pack(folder: &Path, output: Option<&Path>) {
+    let packed = mod_authoring::pack(folder, output).unwrap_or_else(|e| {
+        println!("Failed to pack {}: {}", folder.display(), e);
+        std::process::exit(1);
+    });
+
+    for (file, sha256) in &packed.files {
+        println!("  {} {}", sha256.dimmed(), file);
+    }
+    println!(
+        "Packed {} {} into {}.",
+        packed.manifest.name.bold(),
+        packed.manifest.version,
+        packed.path.display()
+    );
+    println!("sha256: {}", packed.sha256);
+}
@@ -0,0 +1,166 @@
+//! `shortcut`, adding installs to Steam as non-Steam games
+
+use std::path::{Path, PathBuf};
+
+use colored::Colorize;
+
+use crate::installer::artwork;
+use crate::installer::errors::ShortcutError;
+use crate::installer::metadata::read_metadata;
+use crate::installer::platform::TargetPlatform;
+use crate::installer::registry;
+use crate::installer::shortcuts::{self, Shortcut, ShortcutOutcome, SteamUser};
+
+/// How an install shows up in the Steam library
+pub struct ShortcutOptions {
+    /// The name shown in the library
+    pub name: String,
+    /// The Proton version Windows installs run with on Linux
+    pub compat_tool: String,
+    /// Drawn under the icon in the artwork
+    pub label: Option<String>,
+    /// A folder of pictures replacing the generated artwork
+    pub artwork: Option<PathBuf>,
+}
+
+/// Adds an install to an account with its artwork, the shortcut is still added if the artwork fails
+fn add(
+    user: &SteamUser,
+    install_dir: &Path,
+    target: TargetPlatform,
+    options: &ShortcutOptions,
+) -> Result<ShortcutOutcome, ShortcutError> {
+    let mut shortcut = Shortcut::for_install(install_dir, &options.name);
+    let appid = shortcuts::appid_for(user, &shortcut);
+
+    match artwork::write_artwork(
+        &user.grid_dir(),
+        appid,
+        install_dir,
+        options.label.as_deref(),
+        options.artwork.as_deref(),
+    ) {
+        Ok(icon) => shortcut.icon = Some(icon),
+        Err(e) => println!("Couldn't make the library artwork: {}", e),
+    }
+
+    shortcuts::add_install(user, &shortcut, target, &options.compat_tool)
+        .map(|(outcome, _)| outcome)
+}
+
+/// The label of an install's artwork
+fn version_label(install_dir: &Path) -> Option<String> {
+    read_metadata(install_dir.to_path_buf())
+        .map(|metadata| format!("Alloy {}", metadata.alloy_version))
+}
+
+fn print_outcome(name: &str, user: &SteamUser, outcome: ShortcutOutcome) {
+    match outcome {
+        ShortcutOutcome::Added => println!("Added {} to Steam account {}.", name.bold(), user.id),
+        ShortcutOutcome::Updated => {
+            println!("Updated {} in Steam account {}.", name.bold(), user.id)
+        }
+    }
+}
+
+/// Adds an install to the library of one Steam account, or of every one
+pub fn run(
+    query: &str,
+    user: Option<String>,
+    name: Option<String>,
+    compat_tool: String,
+    no_label: bool,
+    artwork: Option<PathBuf>,
+) {
+    let install = registry::find(query).unwrap_or_else(|e| {
+        println!("Failed to find the install: {}", e);
+        std::process::exit(1);
+    });
+
+    let target = read_metadata(install.path.clone())
+        .map(|metadata| metadata.target)
+        .unwrap_or_default();
+
+    let users = shortcuts::find_user(user.as_deref()).unwrap_or_else(|e| {
+        println!("{}", e);
+        std::process::exit(1);
+    });
+
+    let options = ShortcutOptions {
+        name: name.unwrap_or_else(|| shortcuts::default_name(&install.path)),
+        compat_tool,
+        label: if no_label {
+            None
+        } else {
+            version_label(&install.path)
+        },
+        artwork,
+    };
+    let mut failed = false;
+
+    for user in &users {
+        match add(user, &install.path, target, &options) {
+            Ok(outcome) => print_outcome(&options.name, user, outcome),
+            Err(e) => {
+                println!(
+                    "Failed to add {} to Steam account {}: {}",
+                    options.name, user.id, e
+                );
+                failed = true;
+            }
+        }
+    }
+
+    if failed {
+        std::process::exit(1);
+    }
+
+    println!("Restart Steam to see it in your library.");
+}
+
+/// Offers to add a new install to Steam, returns whether it was added
+pub fn offer(install_dir: &Path, target: TargetPlatform) -> bool {
+    let users = shortcuts::steam_users();
+    if users.is_empty() {
+        return false;
+    }
+
+    let confirmed = inquire::Confirm::new("Add it to Steam as a non-Steam game?")
+        .with_default(true)
+        .prompt()
+        .unwrap();
+
+    if !confirmed {
+        return false;
+    }
+
+    let user = if users.len() == 1 {
+        users[0].clone()
+    } else {
+        let ids: Vec<String> = users.iter().map(|user| user.id.clone()).collect();
+        let picked = inquire::Select::new("Which Steam account?", ids)
+            .prompt()
+            .unwrap();
+
+        users.into_iter().find(|user| user.id == picked).unwrap()
+    };
+
+    let options = ShortcutOptions {
+        name: shortcuts::default_name(install_dir),
+        compat_tool: shortcuts::DEFAULT_COMPAT_TOOL.to_string(),
+        label: version_label(install_dir),
+        artwork: None,
+    };
+
+    match add(&user, install_dir, target, &options) {
+        Ok(outcome) => {
+            print_outcome(&options.name, &user, outcome);
+            println!("Restart Steam to see it in your library, and enjoy! :D");
+            true
+        }
+        Err(e) => {
+            println!("Failed to add it to Steam: {}", e);
+            false
+        }
+    }
+}
@@ -0,0 +1,51 @@
+//! `sources` commands, managing the library of imported daisyMoon folders
+
+use colored::Colorize;
+
+use crate::cli::SourcesCommand;
+use crate::installer::library;
+
+/// Runs a `sources` command
+pub fn run(command: SourcesCommand) {
+    match command {
+        SourcesCommand::List => list(),
+        SourcesCommand::Remove { id } => remove(&id),
+    }
+}
+
+fn list() {
+    let index = match library::read_index() {
+        Ok(index) => index,
+        Err(e) => {
+            println!("Failed to read the daisyMoon library: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    if index.is_empty() {
+        println!("You haven't imported any daisyMoon yet.");
+        return;
+    }
+
+    for stored in index {
+        println!("{}", stored.short_hash().bold());
+        println!("  {}", stored.describe());
+        if let Some(build) = &stored.cobalt_build {
+            println!("  Cobalt build: {}", build);
+        }
+        println!(
+            "  {} files, from {}",
+            stored.file_count, stored.imported_from
+        );
+    }
+}
+
+fn remove(id: &str) {
+    match library::remove(id) {
+        Ok(stored) => println!("Removed {}", stored.describe()),
+        Err(e) => {
+            println!("Failed to remove daisyMoon: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
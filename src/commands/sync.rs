@@ -0,0 +1,162 @@
+//! `sync`, bringing copies up to date after Steam updated the game
+
+use colored::Colorize;
+use spinners::{Spinner, Spinners};
+
+use crate::installer::copy::CopyStrategy;
+use crate::installer::files::path_key;
+use crate::installer::metadata::{read_metadata, write_metadata};
+use crate::installer::registry::{self, RegisteredInstall};
+use crate::installer::steam::read_build_id;
+use crate::installer::{alloy, backup, sync};
+
+/// Syncs one install, or every copy in the registry
+pub async fn run(query: Option<String>, yes: bool) {
+    let installs = match query {
+        Some(query) => registry::find(&query).map(|install| vec![install]),
+        None => registry::read_registry(),
+    };
+
+    let installs = installs.unwrap_or_else(|e| {
+        println!("Failed to find your installs: {}", e);
+        std::process::exit(1);
+    });
+
+    let mut failed = false;
+
+    for install in installs.iter().filter(|install| install.exists()) {
+        if let Err(e) = sync_install(install, yes).await {
+            println!("Failed to sync {}: {}", install.name(), e);
+            failed = true;
+        }
+    }
+
+    if failed {
+        std::process::exit(1);
+    }
+}
+
+async fn sync_install(install: &RegisteredInstall, yes: bool) -> Result<(), String> {
+    let Some(game) = install.source_game.clone() else {
+        println!(
+            "{} doesn't know which game it was copied from, skipping it.",
+            install.name()
+        );
+        return Ok(());
+    };
+
+    if install.is_in_place() {
+        return Ok(());
+    }
+
+    if !game.exists() {
+        println!(
+            "{} was copied from {}, which doesn't exist anymore.",
+            install.name(),
+            game.display()
+        );
+        return Ok(());
+    }
+
+    let mut metadata = read_metadata(install.path.clone()).ok_or("it has no install metadata")?;
+
+    if sync::game_was_updated(&game, &metadata) == Some(false) {
+        println!("{} is up to date with Cobalt.", install.name().bold());
+        return Ok(());
+    }
+
+    let recorded = sync::read_snapshot(&install.path).map_err(|e| e.to_string())?;
+
+    let mut sp = Spinner::new(
+        Spinners::Dots,
+        format!("Comparing {} with Cobalt...", install.name()),
+    );
+    let plan = sync::plan_sync(&game, &install.path, recorded.as_ref()).map_err(|e| e.to_string());
+    sp.stop_with_newline();
+    let plan = plan?;
+
+    metadata.source_build = read_build_id(&game);
+
+    if plan.daisymoon_changed {
+        println!(
+            "{}",
+            format!(
+                "Cobalt's daisyMoon changed, so the Alloy in {} was made for the old one and may not work with the new game.",
+                install.name()
+            )
+            .yellow()
+        );
+        println!(
+            "{}",
+            "Syncing leaves daisyMoon alone, install Alloy into a new copy with a daisyMoon decompiled from the updated game."
+                .yellow()
+        );
+    }
+
+    if plan.is_empty() {
+        println!("{} is up to date with Cobalt.", install.name().bold());
+        sync::write_snapshot(&install.path, &plan.snapshot).map_err(|e| e.to_string())?;
+        write_metadata(install.path.clone(), &metadata);
+        return Ok(());
+    }
+
+    println!("Cobalt changed since {} was made:", install.name().bold());
+    println!("  {} files were updated", plan.updated.len());
+    println!("  {} files were removed", plan.removed.len());
+
+    if recorded.is_none() {
+        println!(
+            "{}",
+            "This copy was made by an older installer, so every file that differs from the game counts as one you changed."
+                .yellow()
+        );
+    }
+
+    if !plan.conflicts.is_empty() {
+        println!(
+            "{} files changed both in Cobalt and in your copy, they will be left alone:",
+            plan.conflicts.len().to_string().yellow()
+        );
+        for relative in &plan.conflicts {
+            println!("  {}", path_key(relative));
+        }
+    }
+
+    if plan.updated.is_empty() && plan.removed.is_empty() {
+        return Ok(());
+    }
+
+    if !yes {
+        let prompt = format!("Sync {} with the updated game?", install.name());
+        let confirmed = inquire::Confirm::new(&prompt)
+            .with_default(true)
+            .prompt()
+            .unwrap();
+
+        if !confirmed {
+            return Ok(());
+        }
+    }
+
+    if plan.touches_reapplied() {
+        backup::create(&install.path, "before syncing with Cobalt").map_err(|e| e.to_string())?;
+    }
+
+    let strategy = metadata.copy_strategy.unwrap_or(CopyStrategy::Copy);
+    sync::apply_sync(&game, &install.path, &plan, strategy).map_err(|e| e.to_string())?;
+
+    if plan.touches_reapplied() {
+        println!("Putting Alloy's files back...");
+        let translations = alloy::download_translations(&reqwest::Client::new(), &install.path)
+            .await
+            .map_err(|e| e.to_string())?;
+        metadata.translations_sha256 = Some(translations.alloy_sha256);
+    }
+
+    write_metadata(install.path.clone(), &metadata);
+    crate::commands::verify::refresh_marker(install);
+
+    println!("Synced {}!", install.name().bold());
+
+    Ok(())
+}
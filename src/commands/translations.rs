@@ -0,0 +1,78 @@
+//! `translations`, showing and undoing what merging Alloy's translations did
+
+use colored::Colorize;
+
+use crate::cli::TranslationsCommand;
+use crate::installer::registry::{self, RegisteredInstall};
+use crate::installer::translations::{self, TRANSLATIONS_REPORT_FILENAME};
+use crate::installer::{backup, INSTALLER_FOLDER};
+
+/// Runs a `translations` command
+pub fn run(command: TranslationsCommand) {
+    match command {
+        TranslationsCommand::Report { install } => report(&find(&install)),
+        TranslationsCommand::Restore { install, yes } => restore(&find(&install), yes),
+    }
+}
+
+fn find(query: &str) -> RegisteredInstall {
+    registry::find(query).unwrap_or_else(|e| {
+        println!("Failed to find the install: {}", e);
+        std::process::exit(1);
+    })
+}
+
+fn report(install: &RegisteredInstall) {
+    let path = install
+        .path
+        .join(INSTALLER_FOLDER)
+        .join(TRANSLATIONS_REPORT_FILENAME);
+
+    match std::fs::read_to_string(&path) {
+        Ok(report) if report.is_empty() => println!("Alloy's translations didn't change anything."),
+        Ok(report) => print!("{}", report),
+        Err(_) => println!(
+            "{} was installed by an older installer, which overwrote the English translations.",
+            install.name()
+        ),
+    }
+}
+
+fn restore(install: &RegisteredInstall, yes: bool) {
+    if !yes {
+        let prompt = format!(
+            "Put the translations of {} back the way they were before Alloy?",
+            install.name()
+        );
+        let confirmed = inquire::Confirm::new(&prompt)
+            .with_default(false)
+            .prompt()
+            .unwrap();
+
+        if !confirmed {
+            return;
+        }
+    }
+
+    if let Err(e) = backup::create(&install.path, "before restoring the translations") {
+        println!("Failed to back up the files I'm about to change: {}", e);
+        std::process::exit(1);
+    }
+
+    match translations::restore_pristine(&install.path) {
+        Ok(restored) if restored.is_empty() => {
+            println!("There's no copy of the translations from before Alloy.")
+        }
+        Ok(restored) => println!(
+            "Restored {} translations files of {}, Alloy's menus will show their keys until it's reinstalled.",
+            restored.len(),
+            install.name().bold()
+        ),
+        Err(e) => {
+            println!("Failed to restore the translations: {}", e);
+            std::process::exit(1);
+        }
+    }
+
+    crate::commands::verify::refresh_marker(install);
+}
@@ -0,0 +1,163 @@
+//! `update`, moving an install to the latest Alloy while keeping local edits
+
+use std::path::PathBuf;
+
+use colored::Colorize;
+use spinners::{Spinner, Spinners};
+
+use crate::installer::alloy::{self, ALLOY_VERSION, SAVED_DIFF_NAME};
+use crate::installer::backup;
+use crate::installer::line_endings::normalize_diff;
+use crate::installer::metadata::{read_metadata, write_metadata, InstallMetadata};
+use crate::installer::mod_patches;
+use crate::installer::registry::{self, RegisteredInstall};
+use crate::installer::update::{self, MERGE_REPORT_FILENAME};
+use crate::installer::INSTALLER_FOLDER;
+
+/// Updates Alloy in an install
+pub async fn run(query: String, diff: Option<PathBuf>) {
+    let install = registry::find(&query).unwrap_or_else(|e| {
+        println!("Failed to find that install: {}", e);
+        std::process::exit(1);
+    });
+
+    let Some(mut metadata) = read_metadata(install.path.clone()) else {
+        println!("{} has no install metadata.", install.path.display());
+        std::process::exit(1);
+    };
+
+    let target = metadata.target;
+    let client = reqwest::Client::new();
+
+    let (new_diff, label) = match &diff {
+        Some(path) => match std::fs::read(path) {
+            Ok(bytes) => (bytes, path.display().to_string()),
+            Err(e) => {
+                println!("Failed to read {}: {}", path.display(), e);
+                std::process::exit(1);
+            }
+        },
+        None => match alloy::download_diff(&client, target).await {
+            Ok(bytes) => (bytes, format!("Alloy {}", ALLOY_VERSION)),
+            Err(e) => {
+                println!("Failed to download Alloy: {}", e);
+                println!("Are you connected to the internet?");
+                std::process::exit(2);
+            }
+        },
+    };
+
+    // The saved diff had its line endings converted when it was installed
+    let saved_diff = std::fs::read(install.path.join(INSTALLER_FOLDER).join(SAVED_DIFF_NAME));
+    if saved_diff.is_ok_and(|saved| saved == normalize_diff(&new_diff, target.line_ending())) {
+        // Installs that never recorded their translations get the latest ones, for locks
+        if diff.is_none() && metadata.translations_sha256.is_none() {
+            merge_latest_translations(&install, &mut metadata, &client).await;
+        }
+
+        println!("{} already has the latest Alloy.", install.name().bold());
+        return;
+    }
+
+    if let Err(e) = backup::create(&install.path, "before updating Alloy") {
+        println!("Failed to back up the files I'm about to change: {}", e);
+        std::process::exit(1);
+    }
+
+    // Mod patches come off while Alloy's diff changes, and go back on top of the new one
+    let stack = metadata.patch_stack.clone();
+    if let Err(e) = mod_patches::rebuild_stack(&install.path, target, &stack, &[]).await {
+        println!(
+            "Failed to take the mod patches off, nothing was changed: {}",
+            e
+        );
+        std::process::exit(1);
+    }
+
+    let mut sp = Spinner::new(Spinners::Dots, format!("Updating {}...", install.name()));
+    let result = update::update(&install.path, &new_diff, target, &label).await;
+    sp.stop_with_newline();
+
+    let report = match result {
+        Ok(report) => report,
+        Err(e) => {
+            println!("Failed to update {}: {}", install.name(), e);
+            if let Err(e) = mod_patches::rebuild_stack(&install.path, target, &[], &stack).await {
+                println!("Failed to put the mod patches back: {}", e);
+            }
+            std::process::exit(1);
+        }
+    };
+
+    if diff.is_none() {
+        match alloy::download_translations(&client, &install.path).await {
+            Ok(translations) => metadata.translations_sha256 = Some(translations.alloy_sha256),
+            Err(e) => println!("Failed to get Alloy's translations: {}", e),
+        }
+
+        metadata.alloy_version = ALLOY_VERSION.to_string();
+    }
+
+    crate::commands::mods::reapply_patches(&install.path, &mut metadata, &stack).await;
+
+    metadata.installer_version = env!("CARGO_PKG_VERSION").to_string();
+    write_metadata(install.path.clone(), &metadata);
+
+    if let Err(e) = registry::register(&install.path, &metadata) {
+        println!("Couldn't update your list of installs: {}", e);
+    }
+
+    crate::commands::verify::refresh_marker(&install);
+
+    println!(
+        "{} files replaced, {} files with your edits merged.",
+        report.replaced.len(),
+        report.merged.len()
+    );
+
+    if !report.conflicts.is_empty() {
+        println!(
+            "{}",
+            "These files have edits that conflict with the update, look for the <<<<<<< markers:"
+                .yellow()
+        );
+        for (relative, count) in &report.conflicts {
+            println!("  {} ({} conflicts)", relative, count);
+        }
+    }
+
+    println!(
+        "The full report is in {}",
+        install
+            .path
+            .join(INSTALLER_FOLDER)
+            .join(MERGE_REPORT_FILENAME)
+            .display()
+    );
+    println!("Updated {}!", install.name().bold());
+}
+
+/// Merges Alloy's latest translations into an install that has its latest diff
+async fn merge_latest_translations(
+    install: &RegisteredInstall,
+    metadata: &mut InstallMetadata,
+    client: &reqwest::Client,
+) {
+    if let Err(e) = backup::create(&install.path, "before merging Alloy's translations") {
+        println!("Failed to back up the files I'm about to change: {}", e);
+        std::process::exit(1);
+    }
+
+    match alloy::download_translations(client, &install.path).await {
+        Ok(translations) => {
+            metadata.translations_sha256 = Some(translations.alloy_sha256);
+            write_metadata(install.path.clone(), metadata);
+            crate::commands::verify::refresh_marker(install);
+            println!(
+                "Merged Alloy's latest translations into {}.",
+                install.name()
+            );
+        }
+        Err(e) => println!("Failed to get Alloy's translations: {}", e),
+    }
+}
@@ -0,0 +1,200 @@
+//! `verify` and `repair`, finding and undoing what Steam reverted in in-place installs
+
+use colored::Colorize;
+
+use crate::installer::in_place::{self, FileState, InPlaceMarker};
+use crate::installer::registry::{self, RegisteredInstall};
+use crate::installer::steam::read_build_id;
+use crate::installer::{alloy, backup, files, steam, update};
+
+/// The in-place installs asked for, with their marker
+fn in_place_installs(query: Option<&str>) -> Vec<(RegisteredInstall, InPlaceMarker)> {
+    let installs = match query {
+        Some(query) => registry::find(query).map(|install| vec![install]),
+        None => registry::read_registry(),
+    };
+
+    let installs = installs.unwrap_or_else(|e| {
+        println!("Failed to find your installs: {}", e);
+        std::process::exit(1);
+    });
+
+    let mut found = Vec::new();
+
+    for install in installs.into_iter().filter(|install| install.exists()) {
+        match in_place::read_marker(&install.path) {
+            Ok(Some(marker)) => found.push((install, marker)),
+            Ok(None) if query.is_some() => {
+                println!(
+                    "{} is a copy, Steam doesn't touch it so there's nothing to verify.",
+                    install.name()
+                );
+            }
+            Ok(None) => {}
+            Err(e) => println!("Failed to read what {} looked like: {}", install.name(), e),
+        }
+    }
+
+    found
+}
+
+fn check(install: &RegisteredInstall, marker: &InPlaceMarker) -> Vec<(String, FileState)> {
+    let vanilla = update::vanilla_dir(&install.path);
+
+    match in_place::check(&install.path, marker, Some(&vanilla)) {
+        Ok(changed) => changed.into_iter().collect(),
+        Err(e) => {
+            println!("Failed to check {}: {}", install.name(), e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Lists the files of in-place installs that aren't the way the installer left them
+pub fn verify(query: Option<String>) {
+    let installs = in_place_installs(query.as_deref());
+
+    if installs.is_empty() {
+        if query.is_none() {
+            println!("You don't have any Alloy installed straight into the game.");
+        }
+        return;
+    }
+
+    let mut repairable = false;
+
+    for (install, marker) in installs {
+        println!("{}", install.path.display().to_string().bold());
+
+        let current_build = read_build_id(&install.path);
+        if current_build.is_some() && current_build != marker.steam_build {
+            println!(
+                "  {}",
+                "Steam updated the game since Alloy was installed.".yellow()
+            );
+        }
+
+        let changed = check(&install, &marker);
+        if changed.is_empty() {
+            println!("  Everything is the way the installer left it.");
+            continue;
+        }
+
+        for (relative, state) in &changed {
+            let state = match state {
+                FileState::Missing => "missing".red(),
+                FileState::Reverted => "reverted by Steam".red(),
+                FileState::Edited => "edited".yellow(),
+            };
+            println!("  {} {}", relative, state);
+        }
+
+        repairable |= changed.iter().any(|(_, state)| state.is_repairable());
+    }
+
+    if repairable {
+        println!("Run `repair` to put Alloy's files back.");
+        std::process::exit(1);
+    }
+}
+
+/// Puts back the files Steam reverted or deleted in in-place installs
+pub async fn repair(query: Option<String>, yes: bool) {
+    let client = reqwest::Client::new();
+
+    for (install, mut marker) in in_place_installs(query.as_deref()) {
+        let broken: Vec<String> = check(&install, &marker)
+            .into_iter()
+            .filter(|(_, state)| state.is_repairable())
+            .map(|(relative, _)| relative)
+            .collect();
+
+        if broken.is_empty() {
+            println!("{} doesn't need repairing.", install.name().bold());
+            continue;
+        }
+
+        if !yes {
+            let prompt = format!(
+                "Put back {} files Steam reverted in {}?",
+                broken.len(),
+                install.name()
+            );
+            let confirmed = inquire::Confirm::new(&prompt)
+                .with_default(true)
+                .prompt()
+                .unwrap();
+
+            if !confirmed {
+                continue;
+            }
+        }
+
+        if let Err(e) = backup::create(&install.path, "before repairing") {
+            println!("Failed to back up the files I'm about to change: {}", e);
+            std::process::exit(1);
+        }
+
+        let mut unrepaired: Vec<String> = Vec::new();
+
+        for relative in broken {
+            let path = install.path.join(&relative);
+
+            let result = match relative.as_str() {
+                "steam_appid.txt" => {
+                    steam::create_app_id_txt(install.path.clone()).await;
+                    Ok(())
+                }
+                relative if relative.starts_with("translations/") => {
+                    alloy::download_translations(&client, &install.path)
+                        .await
+                        .map(|_| ())
+                        .map_err(|e| e.to_string())
+                }
+                _ => match update::installed_version(&install.path, &relative) {
+                    Some(kept) => std::fs::create_dir_all(path.parent().unwrap())
+                        .and_then(|_| std::fs::copy(kept, &path))
+                        .map(|_| ())
+                        .map_err(|e| e.to_string()),
+                    None => Err("the installer didn't keep a copy of it".to_string()),
+                },
+            };
+
+            match result.and_then(|_| files::sha256_file(&path).map_err(|e| e.to_string())) {
+                Ok(hash) => {
+                    marker.files.insert(relative, hash);
+                }
+                Err(e) => {
+                    println!("Couldn't repair {}: {}", relative, e);
+                    unrepaired.push(relative);
+                }
+            }
+        }
+
+        marker.steam_build = read_build_id(&install.path);
+        if let Err(e) = in_place::save_marker(&install.path, &marker) {
+            println!("Failed to record the repaired files: {}", e);
+        }
+
+        if unrepaired.is_empty() {
+            println!("Repaired {}!", install.name().bold());
+        } else {
+            println!(
+                "Repaired {}, except {} files, reinstalling Alloy fixes those.",
+                install.name().bold(),
+                unrepaired.len()
+            );
+        }
+    }
+}
+
+/// Records the files written in an in-place install again, for `verify`
+pub fn refresh_marker(install: &RegisteredInstall) {
+    if let Err(e) = in_place::refresh_marker(&install.path) {
+        println!(
+            "Couldn't record the files of {}, `verify` may report them as changed: {}",
+            install.name(),
+            e
+        );
+    }
+}
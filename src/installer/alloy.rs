@@ -1,87 +1,205 @@
-use std::{fs, path::PathBuf};
+use std::path::{Path, PathBuf};
 
-use cfg_if::cfg_if;
-
-#[allow(unused_imports)]
-use newline_converter::{dos2unix, unix2dos};
 use reqwest::Error;
 use tokio::process::Command;
 
 use crate::installer::windows::exit_or_windows;
 
-use super::INSTALLER_FOLDER;
+use super::{
+    errors::AlloyDownloadError,
+    platform::TargetPlatform,
+    translations::{merge_alloy_translations, TranslationsReport},
+    INSTALLER_FOLDER,
+};
 
 const TRANSLATIONS_FILE_URL: &str =
     "https://raw.githubusercontent.com/CobaltAlloy/Alloy/master/alloy/eng.translations";
-#[allow(dead_code)]
 const LATEST_WINDOWS_DIFF_URL: &str = "https://raw.githubusercontent.com/CobaltAlloy/Alloy/master/alloy/win/alloy_editor_mod_0_0_4_win.diff";
-#[allow(dead_code)]
 const LATEST_LINUX_DIFF_URL: &str = "https://raw.githubusercontent.com/CobaltAlloy/Alloy/master/alloy/lin/alloy_editor_mod_0_0_4_lin.diff";
 
+/// Version of Alloy this installer installs
+pub const ALLOY_VERSION: &str = "0.0.4";
+
 /// The name of the diff saved when downloading
 pub const SAVED_DIFF_NAME: &str = "alloy_editor_mod.diff";
 
+/// Returns the URL of the diff made for a platform
+pub fn diff_url(target: TargetPlatform) -> &'static str {
+    match target {
+        TargetPlatform::Windows => LATEST_WINDOWS_DIFF_URL,
+        TargetPlatform::Linux => LATEST_LINUX_DIFF_URL,
+    }
+}
+
 /// Downloads the required alloy files into the right folders
-pub async fn download_alloy_files(base_path: PathBuf) -> Result<(), Error> {
+pub async fn download_alloy_files(
+    base_path: PathBuf,
+    target: TargetPlatform,
+) -> Result<TranslationsReport, AlloyDownloadError> {
     let client = reqwest::Client::new();
 
-    let translations = client
+    let report = download_translations(&client, &base_path).await?;
+
+    let diff = download_diff(&client, target).await?;
+
+    std::fs::write(base_path.join(INSTALLER_FOLDER).join(SAVED_DIFF_NAME), diff)?;
+
+    Ok(report)
+}
+
+/// Downloads the latest diff made for a platform
+pub async fn download_diff(client: &reqwest::Client, target: TargetPlatform) -> Result<Vec<u8>, Error> {
+    Ok(client.get(diff_url(target)).send().await?.bytes().await?.to_vec())
+}
+
+/// Downloads Alloy's latest translations
+pub async fn fetch_translations(client: &reqwest::Client) -> Result<String, Error> {
+    client
         .get(TRANSLATIONS_FILE_URL)
         .send()
         .await?
-        .bytes()
-        .await?;
+        .error_for_status()?
+        .text()
+        .await
+}
+
+/// Downloads Alloy's translations and merges them into the game's
+pub async fn download_translations(
+    client: &reqwest::Client,
+    base_path: &Path,
+) -> Result<TranslationsReport, AlloyDownloadError> {
+    let translations = fetch_translations(client).await?;
+
+    Ok(merge_alloy_translations(base_path, &translations)?)
+}
+
+/// Builds the command applying `diff_path` with patch inside `work_dir`
+///
+/// On Windows patch.exe is the one downloaded into `install_dir`.
+fn patch_command(
+    install_dir: &Path,
+    work_dir: &Path,
+    diff_path: &Path,
+    target: TargetPlatform,
+    options: &[&str],
+) -> (Command, String) {
+    let patch_program = if cfg!(target_os = "windows") {
+        install_dir.join(INSTALLER_FOLDER).join("patch.exe")
+    } else {
+        PathBuf::from("patch")
+    };
+
+    let mut args = vec!["--ignore-whitespace".to_string()];
+    // patch converts line endings to our own unless told not to, which breaks other targets
+    if !target.is_host() {
+        args.push("--binary".to_string());
+    }
+    args.extend(options.iter().map(|option| option.to_string()));
+    args.extend([
+        "-p0".to_string(),
+        "-i".to_string(),
+        diff_path.display().to_string(),
+    ]);
 
-    std::fs::write(
-        base_path.join("translations").join("eng.translations"),
-        translations,
+    let diff_command = format!("{} {}", patch_program.display(), args.join(" "));
+
+    let mut command = Command::new(patch_program);
+    command.current_dir(work_dir);
+    command.args(args);
+
+    (command, diff_command)
+}
+
+/// Removes patch failures that are known to be harmless from its output
+fn ignore_known_failures(stdout: String) -> String {
+    // FIXME: This is a very bad hack to ignore the random error of failing to patch
+    // launcher.lua with one single print.
+    //
+    // Why does this happen? Idk, but it isn't *that* important
+    stdout.replace(
+        "patching file daisyMoon/launcher.lua
+Hunk #1 FAILED at 1.
+1 out of 1 hunk FAILED -- saving rejects to file daisyMoon/launcher.lua.rej",
+        "",
     )
-    .unwrap();
+}
 
-    #[cfg(target_os = "windows")]
-    let diff_url = LATEST_WINDOWS_DIFF_URL;
+/// Applies a diff inside `work_dir`, returning patch's output if any hunk failed
+pub async fn apply_diff(
+    install_dir: &Path,
+    work_dir: &Path,
+    diff_path: &Path,
+    target: TargetPlatform,
+) -> Result<(), String> {
+    let (mut command, _) = patch_command(install_dir, work_dir, diff_path, target, &[]);
 
-    #[cfg(not(target_os = "windows"))]
-    let diff_url = LATEST_LINUX_DIFF_URL;
+    let output = command
+        .output()
+        .await
+        .map_err(|e| format!("failed to run patch: {}", e))?;
 
-    let diff = client.get(diff_url).send().await?.bytes().await?;
+    let stdout = ignore_known_failures(String::from_utf8_lossy(&output.stdout).to_string());
 
-    std::fs::write(base_path.join(INSTALLER_FOLDER).join(SAVED_DIFF_NAME), diff).unwrap();
+    if stdout.contains("FAILED") {
+        return Err(format!(
+            "{}{}",
+            stdout,
+            String::from_utf8_lossy(&output.stderr)
+        ));
+    }
 
     Ok(())
 }
 
-/// Runs the patch command for the downloaded diff
-pub async fn patch_daisy_with_alloy(base_path: PathBuf) {
-    let diff_path = base_path.clone().join(INSTALLER_FOLDER).join(SAVED_DIFF_NAME);
+/// Applies a mod's diff inside `install_dir`, or takes it off with `reverse`
+///
+/// Nothing is written unless every hunk applies, patch's output is returned otherwise.
+pub async fn patch_strictly(
+    install_dir: &Path,
+    diff_path: &Path,
+    target: TargetPlatform,
+    reverse: bool,
+) -> Result<(), String> {
+    // --force never asks whether the diff was already applied, it just fails
+    let mut options = vec!["--force"];
+    if reverse {
+        options.push("-R");
+    }
 
-    #[cfg(target_os = "windows")]
-    let diff_command = format!(
-        ".\\{}\\patch.exe --ignore-whitespace -p0 -i .\\{}\\{}",
-        INSTALLER_FOLDER,
-        INSTALLER_FOLDER,
-        SAVED_DIFF_NAME
-    );
-
-    #[cfg(not(target_os = "windows"))]
-    let diff_command = format!(
-        r#"patch --ignore-whitespace -p0 < "{}""#,
-        diff_path.display()
-    );
-
-    cfg_if! {
-        if #[cfg(target_os = "windows")] {
-            let mut command = Command::new("cmd");
-            command.current_dir(base_path.clone());
-            command.args(["/C", &diff_command]);
+    for dry_run in [true, false] {
+        let mut run_options = options.clone();
+        if dry_run {
+            run_options.push("--dry-run");
         }
-        else {
-            let mut command = Command::new("sh");
-            command.current_dir(base_path.clone());
-            command.args(["-c", &diff_command]);
+
+        let (mut command, _) =
+            patch_command(install_dir, install_dir, diff_path, target, &run_options);
+        command.stdin(std::process::Stdio::null());
+
+        let output = command
+            .output()
+            .await
+            .map_err(|e| format!("failed to run patch: {}", e))?;
+
+        if !output.status.success() {
+            return Err(format!(
+                "{}{}",
+                String::from_utf8_lossy(&output.stdout),
+                String::from_utf8_lossy(&output.stderr)
+            ));
         }
     }
 
+    Ok(())
+}
+
+/// Runs the patch command for the downloaded diff
+pub async fn patch_daisy_with_alloy(base_path: PathBuf, target: TargetPlatform) {
+    let diff_path = base_path.clone().join(INSTALLER_FOLDER).join(SAVED_DIFF_NAME);
+
+    let (mut command, diff_command) =
+        patch_command(&base_path, &base_path, &diff_path, target, &[]);
+
     let output_res = command.output().await;
 
     if let Err(e) = &output_res {
@@ -91,18 +209,7 @@ pub async fn patch_daisy_with_alloy(base_path: PathBuf) {
 
     let output = output_res.unwrap();
 
-    let mut stdout = String::from_utf8(output.stdout).unwrap();
-
-    // FIXME: This is a very bad hack to ignore the random error of failing to patch
-    // launcher.lua with one single print.
-    //
-    // Why does this happen? Idk, but it isn't *that* important
-    stdout = stdout.replace(
-        "patching file daisyMoon/launcher.lua
-Hunk #1 FAILED at 1.
-1 out of 1 hunk FAILED -- saving rejects to file daisyMoon/launcher.lua.rej",
-        "",
-    );
+    let stdout = ignore_known_failures(String::from_utf8(output.stdout).unwrap());
 
     let stderr = String::from_utf8(output.stderr).unwrap();
 
@@ -134,54 +241,3 @@ Hunk #1 FAILED at 1.
         exit_or_windows(5);
     }
 }
-
-// Just in case, fix all the line endings for all files before patching
-pub fn fix_line_endings(base_path: PathBuf) {
-    let diff_path = base_path.clone().join(INSTALLER_FOLDER).join(SAVED_DIFF_NAME);
-
-    let mut alloy_patch = std::fs::read_to_string(diff_path.clone()).unwrap();
-
-    cfg_if! {
-        if #[cfg(target_os = "windows")] {
-            alloy_patch = unix2dos(&alloy_patch).to_string();
-        }
-        else {
-            alloy_patch = dos2unix(&alloy_patch).to_string();
-        }
-    }
-
-    std::fs::write(diff_path, alloy_patch).unwrap();
-
-    let base_daisy_path = base_path.clone().join("daisyMoon");
-
-    for entry_res in fs::read_dir(base_daisy_path).unwrap() {
-        if let Ok(entry) = entry_res {
-            if entry.file_name().to_str().unwrap().contains(".lua") {
-                let string_content_res = std::fs::read_to_string(entry.path());
-
-                if let Err(e) = &string_content_res {
-                    println!("Failed to read file {}: {}", entry.path().display(), e);
-                    exit_or_windows(3);
-                }
-
-                let mut string_content = string_content_res.unwrap();
-
-                cfg_if! {
-                    if #[cfg(target_os = "windows")] {
-                        string_content = unix2dos(&string_content).to_string();
-                    }
-                    else {
-                        string_content = dos2unix(&string_content).to_string();
-                    }
-                }
-
-                let write_res = std::fs::write(entry.path(), string_content);
-
-                if let Err(e) = write_res {
-                    println!("Failed to read file {}: {}", entry.path().display(), e);
-                    exit_or_windows(3);
-                }
-            }
-        }
-    }
-}
@@ -0,0 +1,317 @@
+//! Module related to the artwork of installs in the Steam library
+//!
+//! Steam looks for a non-Steam game's artwork in `userdata/<user>/config/grid`,
+//! named after the id of its shortcut. Images in a custom folder are used as they
+//! are, the others are made from the game's icon on a colour picked from the
+//! install's path, so every copy looks different, with the Alloy version below it.
+
+use std::path::{Path, PathBuf};
+
+use image::{
+    imageops::{self, FilterType},
+    Rgba, RgbaImage,
+};
+
+use super::{errors::ArtworkError, exe_icon::read_icon};
+
+/// A picture Steam shows for a game
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArtworkKind {
+    /// The cover in the library grid
+    Grid,
+    /// The wide tile in recent games
+    Wide,
+    /// The banner at the top of the game's page
+    Hero,
+    /// The logo drawn over the hero
+    Logo,
+    /// The small icon in the library list
+    Icon,
+}
+
+impl ArtworkKind {
+    pub const ALL: [ArtworkKind; 5] = [
+        ArtworkKind::Grid,
+        ArtworkKind::Wide,
+        ArtworkKind::Hero,
+        ArtworkKind::Logo,
+        ArtworkKind::Icon,
+    ];
+
+    /// The size Steam expects
+    pub fn size(&self) -> (u32, u32) {
+        match self {
+            ArtworkKind::Grid => (600, 900),
+            ArtworkKind::Wide => (920, 430),
+            ArtworkKind::Hero => (1920, 620),
+            ArtworkKind::Logo => (640, 360),
+            ArtworkKind::Icon => (256, 256),
+        }
+    }
+
+    /// The name Steam looks for in the grid folder
+    pub fn file_name(&self, appid: u32) -> String {
+        match self {
+            ArtworkKind::Grid => format!("{}p.png", appid),
+            ArtworkKind::Wide => format!("{}.png", appid),
+            ArtworkKind::Hero => format!("{}_hero.png", appid),
+            ArtworkKind::Logo => format!("{}_logo.png", appid),
+            ArtworkKind::Icon => format!("{}_icon.png", appid),
+        }
+    }
+
+    /// The name of the image replacing the generated one in a custom folder
+    pub fn custom_name(&self) -> &'static str {
+        match self {
+            ArtworkKind::Grid => "grid.png",
+            ArtworkKind::Wide => "wide.png",
+            ArtworkKind::Hero => "hero.png",
+            ArtworkKind::Logo => "logo.png",
+            ArtworkKind::Icon => "icon.png",
+        }
+    }
+}
+
+/// Rows of a 5x7 pixel glyph, the highest of the 5 bits being the left column
+fn glyph(c: char) -> [u8; 7] {
+    match c.to_ascii_uppercase() {
+        '0' => [0x0E, 0x11, 0x13, 0x15, 0x19, 0x11, 0x0E],
+        '1' => [0x04, 0x0C, 0x04, 0x04, 0x04, 0x04, 0x0E],
+        '2' => [0x0E, 0x11, 0x01, 0x02, 0x04, 0x08, 0x1F],
+        '3' => [0x1F, 0x02, 0x04, 0x02, 0x01, 0x11, 0x0E],
+        '4' => [0x02, 0x06, 0x0A, 0x12, 0x1F, 0x02, 0x02],
+        '5' => [0x1F, 0x10, 0x1E, 0x01, 0x01, 0x11, 0x0E],
+        '6' => [0x06, 0x08, 0x10, 0x1E, 0x11, 0x11, 0x0E],
+        '7' => [0x1F, 0x01, 0x02, 0x04, 0x08, 0x08, 0x08],
+        '8' => [0x0E, 0x11, 0x11, 0x0E, 0x11, 0x11, 0x0E],
+        '9' => [0x0E, 0x11, 0x11, 0x0F, 0x01, 0x02, 0x0C],
+        'A' => [0x0E, 0x11, 0x11, 0x1F, 0x11, 0x11, 0x11],
+        'B' => [0x1E, 0x11, 0x11, 0x1E, 0x11, 0x11, 0x1E],
+        'C' => [0x0E, 0x11, 0x10, 0x10, 0x10, 0x11, 0x0E],
+        'D' => [0x1C, 0x12, 0x11, 0x11, 0x11, 0x12, 0x1C],
+        'E' => [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x1F],
+        'F' => [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x10],
+        'G' => [0x0E, 0x11, 0x10, 0x17, 0x11, 0x11, 0x0F],
+        'H' => [0x11, 0x11, 0x11, 0x1F, 0x11, 0x11, 0x11],
+        'I' => [0x0E, 0x04, 0x04, 0x04, 0x04, 0x04, 0x0E],
+        'J' => [0x07, 0x02, 0x02, 0x02, 0x02, 0x12, 0x0C],
+        'K' => [0x11, 0x12, 0x14, 0x18, 0x14, 0x12, 0x11],
+        'L' => [0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x1F],
+        'M' => [0x11, 0x1B, 0x15, 0x15, 0x11, 0x11, 0x11],
+        'N' => [0x11, 0x11, 0x19, 0x15, 0x13, 0x11, 0x11],
+        'O' => [0x0E, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E],
+        'P' => [0x1E, 0x11, 0x11, 0x1E, 0x10, 0x10, 0x10],
+        'Q' => [0x0E, 0x11, 0x11, 0x11, 0x15, 0x12, 0x0D],
+        'R' => [0x1E, 0x11, 0x11, 0x1E, 0x14, 0x12, 0x11],
+        'S' => [0x0F, 0x10, 0x10, 0x0E, 0x01, 0x01, 0x1E],
+        'T' => [0x1F, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04],
+        'U' => [0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E],
+        'V' => [0x11, 0x11, 0x11, 0x11, 0x11, 0x0A, 0x04],
+        'W' => [0x11, 0x11, 0x11, 0x15, 0x15, 0x15, 0x0A],
+        'X' => [0x11, 0x11, 0x0A, 0x04, 0x0A, 0x11, 0x11],
+        'Y' => [0x11, 0x11, 0x11, 0x0A, 0x04, 0x04, 0x04],
+        'Z' => [0x1F, 0x01, 0x02, 0x04, 0x08, 0x10, 0x1F],
+        '.' => [0x00, 0x00, 0x00, 0x00, 0x00, 0x0C, 0x0C],
+        '-' => [0x00, 0x00, 0x00, 0x1F, 0x00, 0x00, 0x00],
+        '+' => [0x00, 0x04, 0x04, 0x1F, 0x04, 0x04, 0x00],
+        '_' => [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x1F],
+        _ => [0x00; 7],
+    }
+}
+
+/// Width of text drawn at `scale`, glyphs are 5 pixels wide with 1 between them
+fn text_width(text: &str, scale: u32) -> u32 {
+    (text.chars().count() as u32 * 6).saturating_sub(1) * scale
+}
+
+/// Draws text with a shadow, `x` and `y` being its top left corner
+fn draw_text(image: &mut RgbaImage, text: &str, x: u32, y: u32, scale: u32) {
+    let shadow = scale.div_ceil(3);
+
+    for (offset, color) in [
+        (shadow, Rgba([0, 0, 0, 160])),
+        (0, Rgba([255, 255, 255, 255])),
+    ] {
+        for (index, c) in text.chars().enumerate() {
+            let left = x + index as u32 * 6 * scale + offset;
+
+            for (row, bits) in glyph(c).iter().enumerate() {
+                for column in 0..5 {
+                    if bits & (0x10 >> column) == 0 {
+                        continue;
+                    }
+
+                    for dy in 0..scale {
+                        for dx in 0..scale {
+                            let px = left + column * scale + dx;
+                            let py = y + offset + row as u32 * scale + dy;
+
+                            if px < image.width() && py < image.height() {
+                                let under = *image.get_pixel(px, py);
+                                image.put_pixel(px, py, blend(under, color));
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn blend(under: Rgba<u8>, over: Rgba<u8>) -> Rgba<u8> {
+    let alpha = over[3] as u32;
+    let mix = |a: u8, b: u8| ((b as u32 * alpha + a as u32 * (255 - alpha)) / 255) as u8;
+
+    Rgba([
+        mix(under[0], over[0]),
+        mix(under[1], over[1]),
+        mix(under[2], over[2]),
+        under[3].max(over[3]),
+    ])
+}
+
+/// The colour of an install, picked from its path
+pub fn install_color(install_dir: &Path) -> [u8; 3] {
+    let hue = crc32fast::hash(install_dir.to_string_lossy().as_bytes()) % 360;
+
+    // Saturated but dark enough for white text, from hsv(hue, 0.6, 0.55)
+    let (value, chroma) = (0.55, 0.55 * 0.6);
+    let x = chroma * (1.0 - ((hue as f32 / 60.0) % 2.0 - 1.0).abs());
+    let (r, g, b) = match hue / 60 {
+        0 => (chroma, x, 0.0),
+        1 => (x, chroma, 0.0),
+        2 => (0.0, chroma, x),
+        3 => (0.0, x, chroma),
+        4 => (x, 0.0, chroma),
+        _ => (chroma, 0.0, x),
+    };
+    let m = value - chroma;
+
+    [r, g, b].map(|channel| ((channel + m) * 255.0) as u8)
+}
+
+/// A vertical gradient from the colour to a darker version of it
+fn background(width: u32, height: u32, color: [u8; 3]) -> RgbaImage {
+    RgbaImage::from_fn(width, height, |_, y| {
+        let shade = 1.0 - 0.6 * y as f32 / height as f32;
+        let [r, g, b] = color.map(|channel| (channel as f32 * shade) as u8);
+        Rgba([r, g, b, 255])
+    })
+}
+
+/// Draws the icon and the label centered in an image
+fn compose(image: &mut RgbaImage, icon: Option<&RgbaImage>, title: &str, label: Option<&str>) {
+    let (width, height) = image.dimensions();
+    let icon_size = width.min(height) * 11 / 20;
+
+    // Without the game's icon, its name takes its place
+    let title_scale = (width * 4 / 5 / text_width(title, 1).max(1)).clamp(1, height / 28);
+    let label_scale = label
+        .map(|label| (width * 3 / 5 / text_width(label, 1).max(1)).clamp(1, height / 40))
+        .unwrap_or(0);
+    let label_height = label_scale * 7;
+    let gap = label_height / 2;
+
+    let top_height = match icon {
+        Some(_) => icon_size,
+        None => title_scale * 7,
+    };
+    let mut y = (height.saturating_sub(top_height + gap + label_height)) / 2;
+
+    match icon {
+        Some(icon) => {
+            let icon = imageops::resize(icon, icon_size, icon_size, FilterType::Lanczos3);
+            imageops::overlay(image, &icon, ((width - icon_size) / 2) as i64, y as i64);
+        }
+        None => {
+            let x = width.saturating_sub(text_width(title, title_scale)) / 2;
+            draw_text(image, title, x, y, title_scale);
+        }
+    }
+    y += top_height + gap;
+
+    if let Some(label) = label {
+        let x = width.saturating_sub(text_width(label, label_scale)) / 2;
+        draw_text(image, label, x, y, label_scale);
+    }
+}
+
+/// Makes one of the pictures of an install
+pub fn render(
+    kind: ArtworkKind,
+    icon: Option<&RgbaImage>,
+    color: [u8; 3],
+    label: Option<&str>,
+) -> RgbaImage {
+    let (width, height) = kind.size();
+
+    match kind {
+        ArtworkKind::Grid | ArtworkKind::Wide => {
+            let mut image = background(width, height, color);
+            compose(&mut image, icon, "COBALT", label);
+            image
+        }
+        // The page already has the logo over the banner, so the banner stays plain
+        ArtworkKind::Hero => background(width, height, color),
+        ArtworkKind::Logo => {
+            let mut image = RgbaImage::new(width, height);
+            compose(&mut image, icon, "COBALT", label);
+            image
+        }
+        ArtworkKind::Icon => {
+            let mut image = match icon {
+                Some(icon) => imageops::resize(icon, width, height, FilterType::Lanczos3),
+                None => {
+                    let mut image = background(width, height, color);
+                    compose(&mut image, None, "C", None);
+                    image
+                }
+            };
+
+            // A corner in the install's colour tells copies apart in the list
+            let corner = width / 3;
+            let [r, g, b] = color;
+            for y in height - corner..height {
+                for x in width - corner..width {
+                    if (x - (width - corner)) + (y - (height - corner)) >= corner {
+                        image.put_pixel(x, y, Rgba([r, g, b, 255]));
+                    }
+                }
+            }
+
+            image
+        }
+    }
+}
+
+/// Writes every picture of an install into a grid folder, returns the path of the icon
+///
+/// Pictures found in `custom` are copied instead of being made.
+pub fn write_artwork(
+    grid_dir: &Path,
+    appid: u32,
+    install_dir: &Path,
+    label: Option<&str>,
+    custom: Option<&Path>,
+) -> Result<PathBuf, ArtworkError> {
+    std::fs::create_dir_all(grid_dir)?;
+
+    // Without a readable icon the game's name is drawn instead
+    let icon = read_icon(&install_dir.join("cobaltDM.exe")).unwrap_or(None);
+    let color = install_color(install_dir);
+
+    for kind in ArtworkKind::ALL {
+        let path = grid_dir.join(kind.file_name(appid));
+
+        if let Some(custom) = custom.map(|custom| custom.join(kind.custom_name())) {
+            if custom.is_file() {
+                std::fs::copy(custom, &path)?;
+                continue;
+            }
+        }
+
+        render(kind, icon.as_ref(), color, label).save(&path)?;
+    }
+
+    Ok(grid_dir.join(ArtworkKind::Icon.file_name(appid)))
+}
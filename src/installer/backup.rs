@@ -0,0 +1,421 @@
+//! Module related to backing up the files the installer changes
+//!
+//! Before daisyMoon, the translations or `steam_appid.txt` are written to,
+//! they are zipped into the installer folder so they can be put back.
+//! The installer's records of what was installed go along with them, so a
+//! restored install is described by what was recorded then.
+
+use std::{
+    fs::File,
+    io::{Read, Write},
+    path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, Local, Utc};
+use serde::{Deserialize, Serialize};
+use zip::{result::ZipError, write::FileOptions, CompressionMethod, ZipArchive, ZipWriter};
+
+use super::{
+    alloy::SAVED_DIFF_NAME,
+    errors::BackupError,
+    files::{list_files, path_key},
+    in_place::MARKER_FILENAME,
+    line_endings::LINE_ENDINGS_FILENAME,
+    metadata::{metadata_path, InstallMetadata, METADATA_FILENAME},
+    mod_patches::PATCHES_FOLDER,
+    translations,
+    update::PRISTINE_FOLDER,
+    INSTALLER_FOLDER,
+};
+
+/// Folder in the installer folder holding the backups
+const BACKUPS_FOLDER: &str = "backups";
+
+/// List of the backups, in the backups folder
+const INDEX_FILENAME: &str = "backups.ron";
+
+/// Paths of an install the installer changes, relative to the install
+pub const BACKED_UP_PATHS: &[&str] = &["daisyMoon", "translations", "steam_appid.txt"];
+
+/// What the installer recorded about an install, relative to the installer folder
+const INSTALLER_RECORDS: &[&str] = &[
+    METADATA_FILENAME,
+    SAVED_DIFF_NAME,
+    PRISTINE_FOLDER,
+    translations::INDEX_FILENAME,
+    MARKER_FILENAME,
+    LINE_ENDINGS_FILENAME,
+    PATCHES_FOLDER,
+];
+
+/// How many backups are kept, older ones are deleted except the very first
+pub const KEEP_BACKUPS: usize = 5;
+
+/// A backup of an install's files
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Backup {
+    /// When it was made, also its name
+    pub id: String,
+    /// When it was made
+    pub created_at: DateTime<Utc>,
+    /// What the installer was about to do
+    pub reason: String,
+    /// The backed up paths that existed
+    pub paths: Vec<String>,
+    /// The backed up paths that didn't exist, restoring removes them
+    pub missing: Vec<String>,
+    /// How many files it has
+    pub file_count: usize,
+    /// Size of the zip
+    pub size: u64,
+    /// Whether it has the installer's records too, older backups don't
+    #[serde(default)]
+    pub records: bool,
+}
+
+impl Backup {
+    /// Where the zip is in an install
+    pub fn path(&self, install_dir: &Path) -> PathBuf {
+        backups_dir(install_dir).join(format!("{}.zip", self.id))
+    }
+
+    /// One line description for listings
+    pub fn describe(&self) -> String {
+        format!(
+            "{}, {} ({} files)",
+            self.created_at
+                .with_timezone(&Local)
+                .format("%Y-%m-%d %H:%M:%S"),
+            self.reason,
+            self.file_count
+        )
+    }
+}
+
+fn backups_dir(install_dir: &Path) -> PathBuf {
+    install_dir.join(INSTALLER_FOLDER).join(BACKUPS_FOLDER)
+}
+
+/// Reads the backups of an install, oldest first
+pub fn read_index(install_dir: &Path) -> Result<Vec<Backup>, BackupError> {
+    let path = backups_dir(install_dir).join(INDEX_FILENAME);
+
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+
+    Ok(ron::from_str(&std::fs::read_to_string(path)?)?)
+}
+
+fn write_index(install_dir: &Path, index: &[Backup]) -> Result<(), BackupError> {
+    std::fs::create_dir_all(backups_dir(install_dir))?;
+
+    let as_string = ron::ser::to_string_pretty(index, ron::ser::PrettyConfig::default())?;
+    std::fs::write(backups_dir(install_dir).join(INDEX_FILENAME), as_string)?;
+
+    Ok(())
+}
+
+/// The files under a path of an install, relative to the install, `None` if it doesn't exist
+fn files_under(install_dir: &Path, relative: &Path) -> std::io::Result<Option<Vec<PathBuf>>> {
+    let path = install_dir.join(relative);
+
+    if path.is_dir() {
+        Ok(Some(
+            list_files(&path)?
+                .into_iter()
+                .map(|inner| relative.join(inner))
+                .collect(),
+        ))
+    } else if path.is_file() {
+        Ok(Some(vec![relative.to_path_buf()]))
+    } else {
+        Ok(None)
+    }
+}
+
+/// Zips the files the installer is about to change, then deletes backups past [`KEEP_BACKUPS`]
+pub fn create(install_dir: &Path, reason: &str) -> Result<Backup, BackupError> {
+    create_keeping(install_dir, reason, None)
+}
+
+/// Like [create], never deleting the backup `keep`, which is about to be restored
+pub fn create_keeping(
+    install_dir: &Path,
+    reason: &str,
+    keep: Option<&str>,
+) -> Result<Backup, BackupError> {
+    let mut index = read_index(install_dir)?;
+
+    let created_at = Utc::now();
+    let stamp = created_at.with_timezone(&Local).format("%Y%m%d-%H%M%S");
+    let mut id = stamp.to_string();
+    let mut n = 2;
+    while index.iter().any(|backup| backup.id == id) {
+        id = format!("{}-{}", stamp, n);
+        n += 1;
+    }
+
+    let mut backup = Backup {
+        id,
+        created_at,
+        reason: reason.to_string(),
+        paths: Vec::new(),
+        missing: Vec::new(),
+        file_count: 0,
+        size: 0,
+        records: true,
+    };
+
+    std::fs::create_dir_all(backups_dir(install_dir))?;
+
+    let zip_path = backup.path(install_dir);
+    let mut zip = ZipWriter::new(File::create(&zip_path)?);
+    let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+
+    for backed_up in BACKED_UP_PATHS {
+        let Some(files) = files_under(install_dir, Path::new(backed_up))? else {
+            backup.missing.push(backed_up.to_string());
+            continue;
+        };
+
+        for relative in files {
+            zip.start_file(path_key(&relative), options)?;
+            zip.write_all(&std::fs::read(install_dir.join(&relative))?)?;
+            backup.file_count += 1;
+        }
+
+        backup.paths.push(backed_up.to_string());
+    }
+
+    for record in INSTALLER_RECORDS {
+        let relative = Path::new(INSTALLER_FOLDER).join(record);
+
+        for relative in files_under(install_dir, &relative)?.unwrap_or_default() {
+            zip.start_file(path_key(&relative), options)?;
+            zip.write_all(&std::fs::read(install_dir.join(&relative))?)?;
+        }
+    }
+
+    zip.finish()?;
+    backup.size = std::fs::metadata(&zip_path)?.len();
+
+    index.push(backup.clone());
+
+    // The first backup is from before Alloy was ever installed, it is always kept
+    while index.len() > KEEP_BACKUPS.max(2) {
+        let Some(oldest) = index
+            .iter()
+            .skip(1)
+            .position(|old| Some(old.id.as_str()) != keep)
+        else {
+            break;
+        };
+
+        let old = index.remove(oldest + 1);
+        let _ = std::fs::remove_file(old.path(install_dir));
+    }
+
+    write_index(install_dir, &index)?;
+
+    Ok(backup)
+}
+
+/// Finds a backup of an install by its id, or enough of its start to be unique
+pub fn find(install_dir: &Path, id: &str) -> Result<Option<Backup>, BackupError> {
+    let index = read_index(install_dir)?;
+
+    if let Some(backup) = index.iter().find(|backup| backup.id == id) {
+        return Ok(Some(backup.clone()));
+    }
+
+    let mut matches = index.iter().filter(|backup| backup.id.starts_with(id));
+
+    match (matches.next(), matches.next()) {
+        (Some(backup), None) => Ok(Some(backup.clone())),
+        (Some(_), Some(_)) => Err(BackupError::Ambiguous { id: id.to_string() }),
+        (None, _) => Ok(None),
+    }
+}
+
+fn decode_metadata(as_string: &str) -> std::io::Result<InstallMetadata> {
+    ron::from_str(as_string).map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+}
+
+/// The mods of an install, as far as restoring cares
+fn installed_mods(metadata: Option<&InstallMetadata>) -> Vec<(String, String, bool, String)> {
+    metadata
+        .map(|metadata| {
+            metadata
+                .mods
+                .iter()
+                .map(|installed| {
+                    (
+                        installed.id.clone(),
+                        installed.version.clone(),
+                        installed.disabled,
+                        installed.content_hash(),
+                    )
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Checks a backup can be restored, without changing anything
+///
+/// The mods themselves aren't backed up, so they must be the ones the backup recorded.
+pub fn check_restorable(install_dir: &Path, backup: &Backup) -> Result<(), BackupError> {
+    if !backup.records {
+        return Err(BackupError::NoRecords);
+    }
+
+    let mut archive = ZipArchive::new(File::open(backup.path(install_dir))?)?;
+
+    let key = path_key(&Path::new(INSTALLER_FOLDER).join(METADATA_FILENAME));
+    let recorded = match archive.by_name(&key) {
+        Ok(mut file) => {
+            let mut as_string = String::new();
+            file.read_to_string(&mut as_string)?;
+            Some(decode_metadata(&as_string)?)
+        }
+        Err(ZipError::FileNotFound) => None,
+        Err(e) => return Err(e.into()),
+    };
+
+    let current = match std::fs::read_to_string(metadata_path(install_dir)) {
+        Ok(as_string) => Some(decode_metadata(&as_string)?),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+        Err(e) => return Err(e.into()),
+    };
+
+    if installed_mods(recorded.as_ref()) != installed_mods(current.as_ref()) {
+        return Err(BackupError::ModsChanged);
+    }
+
+    Ok(())
+}
+
+/// Puts the backed up paths and the installer's records back the way they were in a backup
+pub fn restore(install_dir: &Path, backup: &Backup) -> Result<(), BackupError> {
+    check_restorable(install_dir, backup)?;
+
+    let mut archive = ZipArchive::new(File::open(backup.path(install_dir))?)?;
+
+    let backed_up_paths = backup
+        .paths
+        .iter()
+        .chain(&backup.missing)
+        .filter(|backed_up| BACKED_UP_PATHS.contains(&backed_up.as_str()))
+        .map(|backed_up| install_dir.join(backed_up));
+    let records = INSTALLER_RECORDS
+        .iter()
+        .map(|record| install_dir.join(INSTALLER_FOLDER).join(record));
+
+    for path in backed_up_paths.chain(records) {
+        if path.is_dir() {
+            std::fs::remove_dir_all(&path)?;
+        } else if path.exists() {
+            std::fs::remove_file(&path)?;
+        }
+    }
+
+    for i in 0..archive.len() {
+        let mut file = archive.by_index(i)?;
+
+        let Some(relative) = file.enclosed_name().map(Path::to_path_buf) else {
+            continue;
+        };
+
+        let destination = install_dir.join(relative);
+        if let Some(parent) = destination.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        let mut contents = Vec::with_capacity(file.size() as usize);
+        file.read_to_end(&mut contents)?;
+        std::fs::write(destination, contents)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_metadata(install_dir: &Path, alloy_version: &str) {
+        let metadata = InstallMetadata {
+            alloy_version: alloy_version.to_string(),
+            ..Default::default()
+        };
+
+        std::fs::create_dir_all(install_dir.join(INSTALLER_FOLDER)).unwrap();
+        std::fs::write(
+            metadata_path(install_dir),
+            ron::to_string(&metadata).unwrap(),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn restoring_keeps_the_backup_being_restored() {
+        let install = tempfile::tempdir().unwrap();
+        std::fs::write(install.path().join("steam_appid.txt"), "first").unwrap();
+
+        let mut made = Vec::new();
+        for _ in 0..KEEP_BACKUPS {
+            made.push(create(install.path(), "test").unwrap());
+        }
+
+        let restoring = &made[1];
+        create_keeping(install.path(), "before restoring", Some(&restoring.id)).unwrap();
+
+        let index = read_index(install.path()).unwrap();
+        assert_eq!(index.len(), KEEP_BACKUPS);
+        assert!(index.iter().any(|backup| backup.id == restoring.id));
+        assert!(!index.iter().any(|backup| backup.id == made[2].id));
+
+        restore(install.path(), restoring).unwrap();
+    }
+
+    #[test]
+    fn restoring_puts_the_records_back() {
+        let install = tempfile::tempdir().unwrap();
+        write_metadata(install.path(), "0.0.3");
+        std::fs::write(install.path().join("steam_appid.txt"), "old").unwrap();
+
+        let backup = create(install.path(), "before updating Alloy").unwrap();
+
+        write_metadata(install.path(), "0.0.4");
+        std::fs::write(install.path().join("steam_appid.txt"), "new").unwrap();
+        std::fs::create_dir_all(install.path().join(INSTALLER_FOLDER).join(PRISTINE_FOLDER))
+            .unwrap();
+
+        restore(install.path(), &backup).unwrap();
+
+        let metadata = std::fs::read_to_string(metadata_path(install.path())).unwrap();
+        assert_eq!(decode_metadata(&metadata).unwrap().alloy_version, "0.0.3");
+        assert_eq!(
+            std::fs::read_to_string(install.path().join("steam_appid.txt")).unwrap(),
+            "old"
+        );
+        assert!(!install
+            .path()
+            .join(INSTALLER_FOLDER)
+            .join(PRISTINE_FOLDER)
+            .exists());
+    }
+
+    #[test]
+    fn refuses_older_backups() {
+        let install = tempfile::tempdir().unwrap();
+        let mut backup = create(install.path(), "test").unwrap();
+        backup.records = false;
+
+        assert!(matches!(
+            check_restorable(install.path(), &backup),
+            Err(BackupError::NoRecords)
+        ));
+    }
+}
@@ -0,0 +1,187 @@
+//! Module related to detecting compiled Lua in a daisyMoon before it is imported
+//!
+//! Alloy's diff only applies to decompiled daisyMoon, the files shipped with the game
+//! are compiled and make patch fail with confusing hunk errors.
+
+use std::{fs::File, io::Read, path::Path};
+
+use colored::Colorize;
+
+use super::files::{list_files, path_key};
+
+/// Header of regular (PUC-Rio) Lua bytecode
+const LUA_SIGNATURE: &[u8] = b"\x1bLua";
+
+/// Header of LuaJIT bytecode
+const LUAJIT_SIGNATURE: &[u8] = b"\x1bLJ";
+
+/// What a .lua file actually contains
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LuaFileKind {
+    /// Plain Lua source, what we want
+    Source,
+    /// Regular Lua bytecode with its version byte (e.g. 0x51 for 5.1)
+    LuaBytecode(u8),
+    /// LuaJIT bytecode with its format version
+    LuaJitBytecode(u8),
+}
+
+impl LuaFileKind {
+    /// Figures out the kind of a .lua file from its first few bytes
+    pub fn from_header(header: &[u8]) -> LuaFileKind {
+        if let Some(rest) = header.strip_prefix(LUA_SIGNATURE) {
+            LuaFileKind::LuaBytecode(rest.first().copied().unwrap_or(0))
+        } else if let Some(rest) = header.strip_prefix(LUAJIT_SIGNATURE) {
+            LuaFileKind::LuaJitBytecode(rest.first().copied().unwrap_or(0))
+        } else {
+            LuaFileKind::Source
+        }
+    }
+
+    /// Human readable explanation of what's in the file
+    pub fn describe(&self) -> String {
+        match self {
+            LuaFileKind::Source => "decompiled Lua source".to_string(),
+            LuaFileKind::LuaBytecode(version) => {
+                format!("compiled Lua {}.{} bytecode", version >> 4, version & 0x0f)
+            }
+            LuaFileKind::LuaJitBytecode(version) => {
+                format!("compiled LuaJIT bytecode (format {})", version)
+            }
+        }
+    }
+}
+
+/// Result of scanning every .lua file of a daisyMoon
+#[derive(Debug, Clone, Default)]
+pub struct BytecodeScan {
+    /// Compiled files, relative to daisyMoon, with what they contain
+    pub compiled: Vec<(String, LuaFileKind)>,
+    /// How many files were decompiled source
+    pub source_files: usize,
+}
+
+impl BytecodeScan {
+    /// Whether there are no compiled files at all
+    pub fn is_clean(&self) -> bool {
+        self.compiled.is_empty()
+    }
+
+    /// Whether some files are compiled and others aren't
+    pub fn is_mixed(&self) -> bool {
+        !self.compiled.is_empty() && self.source_files > 0
+    }
+
+    fn add(&mut self, path: String, header: &[u8]) {
+        match LuaFileKind::from_header(header) {
+            LuaFileKind::Source => self.source_files += 1,
+            kind => self.compiled.push((path, kind)),
+        }
+    }
+}
+
+/// Whether a path is a Lua file worth scanning
+fn is_lua_file(path: &str) -> bool {
+    path.to_lowercase().ends_with(".lua")
+}
+
+/// Scans every .lua file in a daisyMoon folder
+pub fn scan_folder(daisy_path: &Path) -> std::io::Result<BytecodeScan> {
+    let mut scan = BytecodeScan::default();
+
+    for relative in list_files(daisy_path)? {
+        let key = path_key(&relative);
+
+        if !is_lua_file(&key) {
+            continue;
+        }
+
+        let mut header = Vec::new();
+        File::open(daisy_path.join(&relative))?
+            .take(5)
+            .read_to_end(&mut header)?;
+
+        scan.add(key, &header);
+    }
+
+    Ok(scan)
+}
+
+/// Explains a scan with compiled files to the user
+pub fn print_scan(scan: &BytecodeScan) {
+    if scan.is_clean() {
+        return;
+    }
+
+    if scan.is_mixed() {
+        println!(
+            "{}",
+            format!(
+                "This daisyMoon is a mix: {} Lua files are compiled and {} are decompiled.",
+                scan.compiled.len(),
+                scan.source_files
+            )
+            .red()
+        );
+        println!("It was probably decompiled only partially, or merged with the game's files.");
+    } else {
+        println!(
+            "{}",
+            "This daisyMoon is compiled, it's probably the one that comes with the game.".red()
+        );
+    }
+
+    for (path, kind) in &scan.compiled {
+        println!("  {}: {}", path, kind.describe());
+    }
+
+    println!(
+        "Alloy can only patch decompiled Lua, please decompile these files or use the archive."
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tells_headers_apart() {
+        assert_eq!(
+            LuaFileKind::from_header(b"\x1bLuaQ\x00"),
+            LuaFileKind::LuaBytecode(0x51)
+        );
+        assert_eq!(
+            LuaFileKind::from_header(b"\x1bLJ\x02"),
+            LuaFileKind::LuaJitBytecode(2)
+        );
+        assert_eq!(LuaFileKind::from_header(b"local a"), LuaFileKind::Source);
+        assert_eq!(LuaFileKind::from_header(b""), LuaFileKind::Source);
+        assert_eq!(
+            LuaFileKind::LuaBytecode(0x51).describe(),
+            "compiled Lua 5.1 bytecode"
+        );
+    }
+
+    #[test]
+    fn finds_compiled_files_in_subfolders() {
+        let daisy = tempfile::tempdir().unwrap();
+        let nested = daisy.path().join("lib/manipulators");
+        std::fs::create_dir_all(&nested).unwrap();
+        std::fs::write(daisy.path().join("launcher.lua"), "print(\"launch\")\n").unwrap();
+        std::fs::write(nested.join("galaxyManipulators.LUA"), b"\x1bLJ\x02\x00").unwrap();
+        std::fs::write(nested.join("notes.txt"), b"\x1bLua").unwrap();
+
+        let scan = scan_folder(daisy.path()).unwrap();
+
+        assert_eq!(
+            scan.compiled,
+            vec![(
+                "lib/manipulators/galaxyManipulators.LUA".to_string(),
+                LuaFileKind::LuaJitBytecode(2)
+            )]
+        );
+        assert_eq!(scan.source_files, 1);
+        assert!(scan.is_mixed());
+        assert!(!scan.is_clean());
+    }
+}
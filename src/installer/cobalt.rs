@@ -1,10 +1,5 @@
 use std::path::PathBuf;
 
-use inquire::{
-    validator::{StringValidator, Validation},
-    CustomUserError,
-};
-
 pub const STEAM_RELATIVE_PATH: &str = "steamapps/common/Cobalt";
 
 macro_rules! return_if_some {
@@ -38,7 +33,13 @@ pub fn find_cobalt_path() -> Option<PathBuf> {
             return_if_some!(search_for_cobalt_in(format!("/Users/{}/Library/Application Support/Steam", username).into()));
         }
     }
-    return None;
+
+    // Cobalt might be in a library on another drive
+    for library in super::steam::library_folders() {
+        return_if_some!(search_for_cobalt_in(library));
+    }
+
+    None
 }
 
 /// Searches for cobalt in a possible steam directory and returns the path if it exists.
@@ -55,5 +56,5 @@ fn search_for_cobalt_in(steam_dir: PathBuf) -> Option<PathBuf> {
         return Some(steam_dir.join(STEAM_RELATIVE_PATH));
     }
 
-    return None;
+    None
 }
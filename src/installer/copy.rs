@@ -0,0 +1,754 @@
+//! Module related to copying the game into a new folder
+//!
+//! Copies are incremental: running again over an existing copy only copies
+//! files whose size or modification time changed, like rsync.
+//!
+//! To save space, files can also be reflinked, hardlinked or symlinked to the game.
+//! Files the installer writes to are always real copies, so the game itself is never modified.
+
+use std::{
+    fs::File,
+    io::{Read, Write},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Mutex, OnceLock,
+    },
+};
+
+use clap::ValueEnum;
+use filetime::FileTime;
+use indicatif::{ProgressBar, ProgressStyle};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use super::{
+    errors::CopyError,
+    files::{list_files, list_folders, path_key, sha256_file},
+    INSTALLER_FOLDER,
+};
+
+/// Size of the chunks files are copied in, the progress bar moves once per chunk
+const CHUNK_SIZE: usize = 256 * 1024;
+
+/// Copies get written here first, then renamed, so an interrupted copy is never mistaken for a good one
+const PARTIAL_SUFFIX: &str = ".alloy-partial";
+
+/// Most file systems keep modification times to the second, FAT only to two
+const MTIME_TOLERANCE_SECONDS: i64 = 2;
+
+/// Paths in an install the installer writes to, relative to the install
+pub const INSTALLER_WRITTEN_PATHS: &[&str] = &[
+    "daisyMoon",
+    "translations",
+    "steam_appid.txt",
+    "alloys",
+    INSTALLER_FOLDER,
+];
+
+/// How the files of the game end up in the copy
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+pub enum CopyStrategy {
+    /// Regular copies
+    Copy,
+    /// Copy-on-write clones, free on btrfs and XFS, regular copies elsewhere
+    #[default]
+    Reflink,
+    /// Hardlinks to the game, for every file the installer doesn't write to
+    Hardlink,
+    /// Symlinks to the game, for every file the installer doesn't write to
+    Symlink,
+}
+
+/// Whether the installer writes to a path, relative to the install
+pub fn is_installer_written(relative: &Path) -> bool {
+    let Some(first) = relative.components().next() else {
+        return false;
+    };
+
+    INSTALLER_WRITTEN_PATHS
+        .iter()
+        .any(|written| first.as_os_str() == *written)
+}
+
+/// A file that needs copying
+#[derive(Debug, Clone)]
+pub struct PlannedFile {
+    /// Path relative to the game folder
+    pub relative: PathBuf,
+    /// Size in bytes
+    pub size: u64,
+}
+
+/// What a copy will do, worked out before copying anything
+#[derive(Debug, Clone, Default)]
+pub struct CopyPlan {
+    /// Folders to create, relative to the game folder
+    pub folders: Vec<PathBuf>,
+    /// Files that are missing or changed in the destination
+    pub files: Vec<PlannedFile>,
+    /// Size of the whole game
+    pub total_bytes: u64,
+    /// Size of the files that need copying
+    pub bytes_to_copy: u64,
+    /// How many files are already up to date
+    pub unchanged: usize,
+}
+
+impl CopyPlan {
+    /// How much free space the copy needs with a strategy
+    pub fn bytes_needed(&self, strategy: CopyStrategy) -> u64 {
+        match strategy {
+            // Reflinks fall back to copies, so plan for the worst
+            CopyStrategy::Copy | CopyStrategy::Reflink => self.bytes_to_copy,
+            CopyStrategy::Hardlink | CopyStrategy::Symlink => self
+                .files
+                .iter()
+                .filter(|file| is_installer_written(&file.relative))
+                .map(|file| file.size)
+                .sum(),
+        }
+    }
+}
+
+/// What happened during a copy
+#[derive(Debug, Clone, Default)]
+pub struct CopyReport {
+    /// Files that couldn't be linked or cloned and were copied instead
+    pub fallbacks: usize,
+    /// Why linking or cloning failed, it isn't tried again for the rest of the copy
+    pub fallback_reason: Option<String>,
+}
+
+impl CopyReport {
+    /// The one warning to show when files were copied instead of linked or cloned
+    pub fn fallback_warning(&self, strategy: CopyStrategy) -> Option<String> {
+        let reason = self.fallback_reason.as_ref()?;
+
+        Some(format!(
+            "Files can't be {}ed here ({}), so {} were copied normally.",
+            format!("{:?}", strategy).to_lowercase(),
+            reason,
+            self.fallbacks
+        ))
+    }
+}
+
+/// How to copy
+#[derive(Debug, Clone)]
+pub struct GameCopyOptions {
+    /// Hash every copied file on both sides afterwards
+    pub verify: bool,
+    /// How many files are copied at once
+    pub threads: usize,
+    /// How the files end up in the copy
+    pub strategy: CopyStrategy,
+}
+
+impl Default for GameCopyOptions {
+    fn default() -> Self {
+        let threads = std::thread::available_parallelism()
+            .map(|n| n.get())
+            .unwrap_or(4)
+            .min(8);
+
+        Self {
+            verify: false,
+            threads,
+            strategy: CopyStrategy::default(),
+        }
+    }
+}
+
+/// Whether the destination file already matches the source
+fn is_up_to_date(source: &std::fs::Metadata, destination: &Path) -> bool {
+    let Ok(existing) = std::fs::metadata(destination) else {
+        return false;
+    };
+
+    let source_mtime = FileTime::from_last_modification_time(source).unix_seconds();
+    let existing_mtime = FileTime::from_last_modification_time(&existing).unix_seconds();
+
+    existing.len() == source.len()
+        && (source_mtime - existing_mtime).abs() <= MTIME_TOLERANCE_SECONDS
+}
+
+/// Works out what needs to be copied from `source` to `destination`
+pub fn plan_copy(source: &Path, destination: &Path) -> std::io::Result<CopyPlan> {
+    let mut plan = CopyPlan {
+        folders: list_folders(source)?,
+        ..Default::default()
+    };
+
+    for relative in list_files(source)? {
+        let metadata = std::fs::metadata(source.join(&relative))?;
+        plan.total_bytes += metadata.len();
+
+        if is_up_to_date(&metadata, &destination.join(&relative)) {
+            plan.unchanged += 1;
+            continue;
+        }
+
+        plan.bytes_to_copy += metadata.len();
+        plan.files.push(PlannedFile {
+            relative,
+            size: metadata.len(),
+        });
+    }
+
+    Ok(plan)
+}
+
+/// Makes sure the file system `destination` will be on has room for `bytes` more
+pub fn check_free_space(destination: &Path, bytes: u64) -> Result<(), CopyError> {
+    // The destination might not exist yet, ask about the closest folder that does
+    let existing = destination
+        .ancestors()
+        .find(|ancestor| ancestor.exists())
+        .unwrap_or(destination);
+
+    let available = fs4::available_space(existing)?;
+
+    if available < bytes {
+        return Err(CopyError::NotEnoughSpace {
+            needed: bytes,
+            available,
+        });
+    }
+
+    Ok(())
+}
+
+/// Where a file is written before being renamed into place
+fn partial_path(destination: &Path) -> PathBuf {
+    let mut partial_name = destination.as_os_str().to_owned();
+    partial_name.push(PARTIAL_SUFFIX);
+    PathBuf::from(partial_name)
+}
+
+/// Copies one file, keeping its permissions and modification time
+///
+/// The partial file is deleted when anything fails, so a failed copy leaves nothing behind.
+fn copy_file(
+    source: &Path,
+    destination: &Path,
+    verify: bool,
+    progress: &ProgressBar,
+) -> Result<(), CopyError> {
+    let partial = partial_path(destination);
+    let result = copy_through_partial(source, destination, &partial, verify, progress);
+
+    if result.is_err() {
+        let _ = std::fs::remove_file(&partial);
+    }
+
+    result
+}
+
+fn copy_through_partial(
+    source: &Path,
+    destination: &Path,
+    partial: &Path,
+    verify: bool,
+    progress: &ProgressBar,
+) -> Result<(), CopyError> {
+    let metadata = std::fs::metadata(source)?;
+
+    let mut reader = File::open(source)?;
+    let mut writer = File::create(partial)?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0u8; CHUNK_SIZE];
+
+    loop {
+        let read = reader.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+
+        writer.write_all(&buffer[..read])?;
+        if verify {
+            hasher.update(&buffer[..read]);
+        }
+        progress.inc(read as u64);
+    }
+
+    writer.flush()?;
+    drop(writer);
+
+    if verify && format!("{:x}", hasher.finalize()) != sha256_file(partial)? {
+        return Err(CopyError::Verify {
+            path: destination.display().to_string(),
+        });
+    }
+
+    std::fs::set_permissions(partial, metadata.permissions())?;
+    filetime::set_file_times(
+        partial,
+        FileTime::from_last_access_time(&metadata),
+        FileTime::from_last_modification_time(&metadata),
+    )?;
+
+    std::fs::rename(partial, destination)?;
+
+    Ok(())
+}
+
+/// Clones a file with the FICLONE ioctl, which only works on some file systems
+#[cfg(target_os = "linux")]
+fn reflink_file(source: &Path, destination: &Path) -> std::io::Result<()> {
+    use std::os::fd::AsRawFd;
+
+    let metadata = std::fs::metadata(source)?;
+    let partial = partial_path(destination);
+
+    let reader = File::open(source)?;
+    let writer = File::create(&partial)?;
+
+    // SAFETY: both file descriptors are open for as long as the call lasts
+    let result = unsafe { libc::ioctl(writer.as_raw_fd(), libc::FICLONE, reader.as_raw_fd()) };
+
+    if result != 0 {
+        let error = std::io::Error::last_os_error();
+        drop(writer);
+        let _ = std::fs::remove_file(&partial);
+        return Err(error);
+    }
+
+    drop(writer);
+
+    std::fs::set_permissions(&partial, metadata.permissions())?;
+    filetime::set_file_times(
+        &partial,
+        FileTime::from_last_access_time(&metadata),
+        FileTime::from_last_modification_time(&metadata),
+    )?;
+
+    std::fs::rename(&partial, destination)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn reflink_file(_source: &Path, _destination: &Path) -> std::io::Result<()> {
+    Err(std::io::ErrorKind::Unsupported.into())
+}
+
+/// Links a file into place, replacing whatever is there
+fn link_file(source: &Path, destination: &Path, strategy: CopyStrategy) -> std::io::Result<()> {
+    if strategy == CopyStrategy::Reflink {
+        return reflink_file(source, destination);
+    }
+
+    let partial = partial_path(destination);
+    let _ = std::fs::remove_file(&partial);
+
+    match strategy {
+        CopyStrategy::Hardlink => std::fs::hard_link(source, &partial)?,
+        CopyStrategy::Symlink => {
+            let target = std::fs::canonicalize(source)?;
+
+            #[cfg(unix)]
+            std::os::unix::fs::symlink(target, &partial)?;
+
+            #[cfg(windows)]
+            std::os::windows::fs::symlink_file(target, &partial)?;
+        }
+        _ => return Err(std::io::ErrorKind::Unsupported.into()),
+    }
+
+    std::fs::rename(&partial, destination)
+}
+
+/// Puts one file of the plan into the copy, returns whether it had to fall back to copying
+///
+/// Once linking fails, `link_failure` holds why and the rest of the files are copied straight away.
+fn place_file(
+    source: &Path,
+    destination: &Path,
+    relative: &Path,
+    options: &GameCopyOptions,
+    link_failure: &OnceLock<String>,
+    progress: &ProgressBar,
+) -> Result<bool, CopyError> {
+    let strategy = match options.strategy {
+        CopyStrategy::Hardlink | CopyStrategy::Symlink if is_installer_written(relative) => {
+            CopyStrategy::Copy
+        }
+        strategy => strategy,
+    };
+
+    if strategy != CopyStrategy::Copy && link_failure.get().is_none() {
+        match link_file(source, destination, strategy) {
+            Ok(()) => {
+                progress.inc(std::fs::metadata(source)?.len());
+                return Ok(false);
+            }
+            Err(e) => {
+                let _ = link_failure.set(e.to_string());
+            }
+        }
+    }
+
+    copy_file(source, destination, options.verify, progress)?;
+
+    Ok(strategy != CopyStrategy::Copy)
+}
+
+/// Whether a file might be shared with the game, through a symlink or hardlink
+fn may_be_linked(path: &Path) -> std::io::Result<bool> {
+    let metadata = std::fs::symlink_metadata(path)?;
+
+    if metadata.file_type().is_symlink() {
+        return Ok(true);
+    }
+
+    cfg_if::cfg_if! {
+        if #[cfg(unix)] {
+            use std::os::unix::fs::MetadataExt;
+            Ok(metadata.nlink() > 1)
+        }
+        else {
+            // The link count isn't available here, be safe
+            Ok(true)
+        }
+    }
+}
+
+/// Turns a file that may be linked to the game into a copy of its own, so writing to it is safe
+pub fn ensure_unlinked(path: &Path) -> std::io::Result<()> {
+    if !path.exists() || !may_be_linked(path)? {
+        return Ok(());
+    }
+
+    let partial = partial_path(path);
+    std::fs::copy(path, &partial)?;
+
+    // Rename over the link itself, never writing through it
+    std::fs::rename(&partial, path)
+}
+
+/// Runs [ensure_unlinked] on every file under a folder
+pub fn ensure_unlinked_tree(folder: &Path) -> std::io::Result<()> {
+    if !folder.is_dir() {
+        return Ok(());
+    }
+
+    for relative in list_files(folder)? {
+        ensure_unlinked(&folder.join(relative))?;
+    }
+
+    Ok(())
+}
+
+/// Makes sure nothing the installer writes to in an install is linked to the game
+pub fn ensure_installer_paths_unlinked(install_dir: &Path) -> std::io::Result<()> {
+    for written in INSTALLER_WRITTEN_PATHS {
+        let path = install_dir.join(written);
+
+        if path.is_dir() {
+            ensure_unlinked_tree(&path)?;
+        } else {
+            ensure_unlinked(&path)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Deletes what the installer wrote to an existing copy, so updating it starts from the game's files
+///
+/// The installer folder stays, it holds the backups.
+pub fn clear_installer_paths(install_dir: &Path) -> std::io::Result<()> {
+    for written in INSTALLER_WRITTEN_PATHS
+        .iter()
+        .filter(|written| **written != INSTALLER_FOLDER)
+    {
+        let path = install_dir.join(written);
+
+        if path.is_dir() {
+            std::fs::remove_dir_all(&path)?;
+        } else if path.symlink_metadata().is_ok() {
+            std::fs::remove_file(&path)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Copies everything in the plan, in parallel, with a progress bar
+pub fn copy_game(
+    source: &Path,
+    destination: &Path,
+    plan: &CopyPlan,
+    options: &GameCopyOptions,
+) -> Result<CopyReport, CopyError> {
+    std::fs::create_dir_all(destination)?;
+    for folder in &plan.folders {
+        std::fs::create_dir_all(destination.join(folder))?;
+    }
+
+    let progress = ProgressBar::new(plan.bytes_to_copy);
+    progress.set_style(
+        ProgressStyle::with_template(
+            "{spinner} [{bar:40}] {bytes}/{total_bytes} ({bytes_per_sec}, {eta} left) {msg}",
+        )
+        .expect("Invalid progress bar template, this should not happen")
+        .progress_chars("=> "),
+    );
+
+    let next_file = AtomicUsize::new(0);
+    let fallbacks = AtomicUsize::new(0);
+    let link_failure = OnceLock::new();
+    let failed = AtomicBool::new(false);
+    let first_error: Mutex<Option<CopyError>> = Mutex::new(None);
+
+    std::thread::scope(|scope| {
+        for _ in 0..options.threads.max(1) {
+            scope.spawn(|| {
+                while !failed.load(Ordering::Relaxed) {
+                    let Some(file) = plan.files.get(next_file.fetch_add(1, Ordering::Relaxed))
+                    else {
+                        return;
+                    };
+
+                    progress.set_message(path_key(&file.relative));
+
+                    let result = place_file(
+                        &source.join(&file.relative),
+                        &destination.join(&file.relative),
+                        &file.relative,
+                        options,
+                        &link_failure,
+                        &progress,
+                    );
+
+                    match result {
+                        Ok(true) => {
+                            fallbacks.fetch_add(1, Ordering::Relaxed);
+                        }
+                        Ok(false) => {}
+                        Err(e) => {
+                            failed.store(true, Ordering::Relaxed);
+                            first_error.lock().unwrap().get_or_insert(e);
+                        }
+                    }
+                }
+            });
+        }
+    });
+
+    progress.finish_and_clear();
+
+    match first_error.into_inner().unwrap() {
+        Some(e) => Err(e),
+        None => Ok(CopyReport {
+            fallbacks: fallbacks.into_inner(),
+            fallback_reason: link_failure.into_inner(),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write(path: &Path, contents: &str) {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, contents).unwrap();
+    }
+
+    /// A game folder with a file the installer writes to and one it doesn't
+    fn game() -> tempfile::TempDir {
+        let game = tempfile::tempdir().unwrap();
+        write(&game.path().join("daisyMoon/main.lua"), "vanilla");
+        write(&game.path().join("data/level.dat"), "level");
+        game
+    }
+
+    fn copy(game: &Path, destination: &Path, options: &GameCopyOptions) -> CopyReport {
+        let plan = plan_copy(game, destination).unwrap();
+        copy_game(game, destination, &plan, options).unwrap()
+    }
+
+    #[test]
+    fn unchanged_files_are_skipped() {
+        let game = game();
+        let destination = tempfile::tempdir().unwrap();
+        copy(game.path(), destination.path(), &GameCopyOptions::default());
+
+        let plan = plan_copy(game.path(), destination.path()).unwrap();
+
+        assert!(plan.files.is_empty());
+        assert_eq!(plan.unchanged, 2);
+        assert_eq!(plan.bytes_to_copy, 0);
+    }
+
+    #[test]
+    fn changed_files_are_copied_again() {
+        let game = game();
+        let destination = tempfile::tempdir().unwrap();
+        copy(game.path(), destination.path(), &GameCopyOptions::default());
+
+        write(&game.path().join("data/level.dat"), "a longer level");
+        let plan = plan_copy(game.path(), destination.path()).unwrap();
+
+        assert_eq!(plan.files.len(), 1);
+        assert_eq!(plan.files[0].relative, Path::new("data/level.dat"));
+
+        copy_game(
+            game.path(),
+            destination.path(),
+            &plan,
+            &GameCopyOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            std::fs::read_to_string(destination.path().join("data/level.dat")).unwrap(),
+            "a longer level"
+        );
+    }
+
+    #[test]
+    fn failed_copies_leave_nothing_behind() {
+        let source = tempfile::tempdir().unwrap();
+        let destination = tempfile::tempdir().unwrap();
+        let target = destination.path().join("level.dat");
+
+        // Opening a folder works, reading it doesn't
+        let result = copy_file(source.path(), &target, false, &ProgressBar::hidden());
+
+        assert!(result.is_err());
+        assert!(!target.exists());
+        assert!(!partial_path(&target).exists());
+    }
+
+    #[test]
+    fn updating_a_copy_takes_installer_paths_from_the_game() {
+        let game = game();
+        let destination = tempfile::tempdir().unwrap();
+        copy(game.path(), destination.path(), &GameCopyOptions::default());
+
+        // Patched to the same size and time, so only clearing tells it apart
+        let main = destination.path().join("daisyMoon/main.lua");
+        std::fs::write(&main, "patched").unwrap();
+        filetime::set_file_mtime(
+            &main,
+            FileTime::from_last_modification_time(
+                &std::fs::metadata(game.path().join("daisyMoon/main.lua")).unwrap(),
+            ),
+        )
+        .unwrap();
+        write(&destination.path().join("daisyMoon/added.lua"), "added");
+        write(&destination.path().join("alloys/mod.lua"), "mod");
+        write(
+            &destination.path().join(INSTALLER_FOLDER).join("backup.zip"),
+            "backup",
+        );
+
+        clear_installer_paths(destination.path()).unwrap();
+        let plan = plan_copy(game.path(), destination.path()).unwrap();
+
+        assert_eq!(plan.unchanged, 1);
+        assert_eq!(plan.files.len(), 1);
+
+        copy_game(
+            game.path(),
+            destination.path(),
+            &plan,
+            &GameCopyOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(std::fs::read_to_string(&main).unwrap(), "vanilla");
+        assert!(!destination.path().join("daisyMoon/added.lua").exists());
+        assert!(!destination.path().join("alloys").exists());
+        assert!(destination
+            .path()
+            .join(INSTALLER_FOLDER)
+            .join("backup.zip")
+            .exists());
+    }
+
+    #[cfg(unix)]
+    fn links(path: &Path) -> u64 {
+        use std::os::unix::fs::MetadataExt;
+        std::fs::metadata(path).unwrap().nlink()
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn hardlinked_files_are_unlinked_before_writing() {
+        let game = game();
+        let install = tempfile::tempdir().unwrap();
+        let original = game.path().join("daisyMoon/main.lua");
+        let linked = install.path().join("main.lua");
+        std::fs::hard_link(&original, &linked).unwrap();
+
+        ensure_unlinked(&linked).unwrap();
+        std::fs::write(&linked, "patched").unwrap();
+
+        assert_eq!(links(&linked), 1);
+        assert_eq!(std::fs::read_to_string(&original).unwrap(), "vanilla");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinked_files_are_unlinked_before_writing() {
+        let game = game();
+        let install = tempfile::tempdir().unwrap();
+        let original = game.path().join("daisyMoon/main.lua");
+        let linked = install.path().join("main.lua");
+        std::os::unix::fs::symlink(&original, &linked).unwrap();
+
+        ensure_unlinked(&linked).unwrap();
+        std::fs::write(&linked, "patched").unwrap();
+
+        assert!(!linked.symlink_metadata().unwrap().file_type().is_symlink());
+        assert_eq!(std::fs::read_to_string(&original).unwrap(), "vanilla");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn only_installer_paths_are_unlinked() {
+        let game = game();
+        let install = tempfile::tempdir().unwrap();
+        write(&game.path().join("steam_appid.txt"), "1");
+        std::fs::create_dir_all(install.path().join("daisyMoon")).unwrap();
+        std::fs::create_dir_all(install.path().join("data")).unwrap();
+        for relative in ["daisyMoon/main.lua", "data/level.dat"] {
+            std::fs::hard_link(game.path().join(relative), install.path().join(relative)).unwrap();
+        }
+        std::os::unix::fs::symlink(
+            game.path().join("steam_appid.txt"),
+            install.path().join("steam_appid.txt"),
+        )
+        .unwrap();
+
+        ensure_installer_paths_unlinked(install.path()).unwrap();
+
+        assert_eq!(links(&install.path().join("daisyMoon/main.lua")), 1);
+        assert!(!install
+            .path()
+            .join("steam_appid.txt")
+            .symlink_metadata()
+            .unwrap()
+            .file_type()
+            .is_symlink());
+        assert_eq!(links(&install.path().join("data/level.dat")), 2);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn hardlinked_copies_copy_installer_paths() {
+        let game = game();
+        let destination = tempfile::tempdir().unwrap();
+        let options = GameCopyOptions {
+            strategy: CopyStrategy::Hardlink,
+            ..Default::default()
+        };
+
+        let report = copy(game.path(), destination.path(), &options);
+
+        assert_eq!(report.fallbacks, 0);
+        assert!(report.fallback_warning(CopyStrategy::Hardlink).is_none());
+        assert_eq!(links(&destination.path().join("data/level.dat")), 2);
+        assert_eq!(links(&destination.path().join("daisyMoon/main.lua")), 1);
+    }
+}
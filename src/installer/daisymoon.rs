@@ -0,0 +1,313 @@
+//! Module related to checking a daisyMoon folder against known releases
+
+use std::{collections::BTreeMap, path::Path};
+
+use colored::Colorize;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    alloy::ALLOY_VERSION,
+    files::{list_files, path_key, sha256_hex},
+};
+
+/// Fingerprints of known daisyMoon releases, see the file itself for the format
+const KNOWN_RELEASES: &str = include_str!("../../data/daisymoon_fingerprints.ron");
+
+/// A file every usable daisyMoon has, even ones we don't know about
+pub const REQUIRED_FILE: &str = "lib/manipulators/galaxyManipulators.lua";
+
+/// Relative path -> sha256 of every file in a daisyMoon folder
+pub type Fingerprint = BTreeMap<String, String>;
+
+/// A known release of daisyMoon
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DaisyMoonRelease {
+    /// Human readable name of the release
+    pub label: String,
+    /// Steam build id of the Cobalt version this daisyMoon came from
+    pub cobalt_build: String,
+    /// Alloy versions whose diff was made against this daisyMoon
+    pub alloy_versions: Vec<String>,
+    /// The expected files and their hashes
+    pub files: Fingerprint,
+}
+
+/// How a daisyMoon folder compares to the closest known release
+#[derive(Debug, Clone)]
+pub struct FingerprintReport {
+    /// The closest known release, if any files matched at all
+    pub release: Option<DaisyMoonRelease>,
+    /// Files the release has but the folder doesn't
+    pub missing: Vec<String>,
+    /// Files the folder has but the release doesn't
+    pub unexpected: Vec<String>,
+    /// Files both have, but with different contents
+    pub modified: Vec<String>,
+}
+
+impl FingerprintReport {
+    /// Whether the folder is exactly a known release
+    pub fn is_exact(&self) -> bool {
+        self.release.is_some()
+            && self.missing.is_empty()
+            && self.unexpected.is_empty()
+            && self.modified.is_empty()
+    }
+}
+
+/// Returns every known daisyMoon release
+pub fn known_releases() -> Vec<DaisyMoonRelease> {
+    ron::from_str(KNOWN_RELEASES)
+        .expect("Failed to decode daisyMoon fingerprints, this should not happen")
+}
+
+/// Hashes a file's contents the way fingerprints expect, ignoring line endings
+pub fn hash_contents(bytes: &[u8]) -> String {
+    let mut normalized = Vec::with_capacity(bytes.len());
+
+    for (i, byte) in bytes.iter().enumerate() {
+        if *byte == b'\r' && bytes.get(i + 1) == Some(&b'\n') {
+            continue;
+        }
+        normalized.push(*byte);
+    }
+
+    sha256_hex(&normalized)
+}
+
+/// Where a zip entry goes inside daisyMoon
+///
+/// Fixes zips downloaded from the wrong folders, which have `cobalt/` or `daisyMoon/` in front.
+pub fn zip_entry_path(name: &Path) -> String {
+    path_key(name)
+        .replace("cobalt/", "")
+        .replace("daisyMoon/", "")
+}
+
+/// Computes the fingerprint of a daisyMoon folder
+pub fn fingerprint_folder(daisy_path: &Path) -> std::io::Result<Fingerprint> {
+    let mut fingerprint = Fingerprint::new();
+
+    for relative in list_files(daisy_path)? {
+        let bytes = std::fs::read(daisy_path.join(&relative))?;
+        fingerprint.insert(path_key(&relative), hash_contents(&bytes));
+    }
+
+    Ok(fingerprint)
+}
+
+/// Compares a fingerprint to the given releases and reports on the closest one
+pub fn identify(fingerprint: &Fingerprint, releases: &[DaisyMoonRelease]) -> FingerprintReport {
+    let matching_files = |release: &DaisyMoonRelease| {
+        release
+            .files
+            .iter()
+            .filter(|(path, hash)| fingerprint.get(*path) == Some(hash))
+            .count()
+    };
+
+    let closest = releases
+        .iter()
+        .map(|release| (matching_files(release), release))
+        .filter(|(matches, _)| *matches > 0)
+        .max_by_key(|(matches, _)| *matches)
+        .map(|(_, release)| release.clone());
+
+    let Some(release) = closest else {
+        return FingerprintReport {
+            release: None,
+            missing: Vec::new(),
+            unexpected: Vec::new(),
+            modified: Vec::new(),
+        };
+    };
+
+    let missing = release
+        .files
+        .keys()
+        .filter(|path| !fingerprint.contains_key(*path))
+        .cloned()
+        .collect();
+
+    let unexpected = fingerprint
+        .keys()
+        .filter(|path| !release.files.contains_key(*path))
+        .cloned()
+        .collect();
+
+    let modified = release
+        .files
+        .iter()
+        .filter(|(path, hash)| fingerprint.get(*path).is_some_and(|h| h != *hash))
+        .map(|(path, _)| path.clone())
+        .collect();
+
+    FingerprintReport {
+        release: Some(release),
+        missing,
+        unexpected,
+        modified,
+    }
+}
+
+/// Prints a report to the user, warning about version mismatches with the game and Alloy
+pub fn print_report(report: &FingerprintReport, game_build: Option<&str>) {
+    let Some(release) = &report.release else {
+        println!(
+            "{}",
+            "This daisyMoon doesn't match any release I know of, I can't check it's complete."
+                .yellow()
+        );
+        return;
+    };
+
+    if report.is_exact() {
+        println!(
+            "This is daisyMoon for {} (build {}), complete.",
+            release.label, release.cobalt_build
+        );
+    } else {
+        println!(
+            "This looks like daisyMoon for {} (build {}), but it differs:",
+            release.label, release.cobalt_build
+        );
+
+        for path in &report.missing {
+            println!("  missing:    {}", path);
+        }
+        for path in &report.modified {
+            println!("  modified:   {}", path);
+        }
+        for path in &report.unexpected {
+            println!("  unexpected: {}", path);
+        }
+    }
+
+    if let Some(game_build) = game_build {
+        if game_build != release.cobalt_build {
+            println!(
+                "{}",
+                format!(
+                    "Warning: your game is build {}, but this daisyMoon is from build {}.",
+                    game_build, release.cobalt_build
+                )
+                .yellow()
+            );
+        }
+    }
+
+    if !release.alloy_versions.iter().any(|v| v == ALLOY_VERSION) {
+        println!(
+            "{}",
+            format!(
+                "Warning: Alloy {} wasn't made for this daisyMoon, patching may fail.",
+                ALLOY_VERSION
+            )
+            .yellow()
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A made up release in the same format as the shipped fingerprints
+    const FIXTURE: &str = r#"[
+        (
+            label: "Fixture",
+            cobalt_build: "100",
+            alloy_versions: ["0.0.0"],
+            files: {
+                "launcher.lua": "89e5998a0931c57690fe75aee57b2ce7930dca2b9756034899cd82c9cb3d6575",
+                "lib/manipulators/galaxyManipulators.lua": "f263628c23b8d4c92fdc8275553b0a3afe6828205ce12ddaad7a9678c0f0823f",
+            },
+        ),
+    ]"#;
+
+    fn fixture_releases() -> Vec<DaisyMoonRelease> {
+        ron::from_str(FIXTURE).unwrap()
+    }
+
+    fn daisymoon_folder(files: &[(&str, &str)]) -> tempfile::TempDir {
+        let folder = tempfile::tempdir().unwrap();
+        for (path, contents) in files {
+            let path = folder.path().join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, contents).unwrap();
+        }
+        folder
+    }
+
+    #[test]
+    fn shipped_fingerprints_decode() {
+        known_releases();
+    }
+
+    #[test]
+    fn complete_folder_is_exact() {
+        // CRLF line endings shouldn't matter
+        let folder = daisymoon_folder(&[
+            ("launcher.lua", "print(\"launch\")\r\n"),
+            (REQUIRED_FILE, "return galaxy\n"),
+        ]);
+
+        let report = identify(
+            &fingerprint_folder(folder.path()).unwrap(),
+            &fixture_releases(),
+        );
+
+        assert!(report.is_exact());
+        assert_eq!(report.release.unwrap().cobalt_build, "100");
+    }
+
+    #[test]
+    fn incomplete_folder_lists_the_differences() {
+        let folder = daisymoon_folder(&[
+            (REQUIRED_FILE, "return galaxy\n"),
+            ("extra.lua", "-- not in the release\n"),
+        ]);
+
+        let report = identify(
+            &fingerprint_folder(folder.path()).unwrap(),
+            &fixture_releases(),
+        );
+
+        assert!(!report.is_exact());
+        assert_eq!(report.release.unwrap().label, "Fixture");
+        assert_eq!(report.missing, vec!["launcher.lua".to_string()]);
+        assert_eq!(report.unexpected, vec!["extra.lua".to_string()]);
+        assert!(report.modified.is_empty());
+    }
+
+    #[test]
+    fn modified_files_are_reported() {
+        let folder = daisymoon_folder(&[
+            ("launcher.lua", "print(\"launch\")\n"),
+            (REQUIRED_FILE, "return nil\n"),
+        ]);
+
+        let report = identify(
+            &fingerprint_folder(folder.path()).unwrap(),
+            &fixture_releases(),
+        );
+
+        assert!(!report.is_exact());
+        assert!(report.missing.is_empty());
+        assert_eq!(report.modified, vec![REQUIRED_FILE.to_string()]);
+    }
+
+    #[test]
+    fn unknown_folder_has_no_release() {
+        let folder = daisymoon_folder(&[(REQUIRED_FILE, "return something_else\n")]);
+
+        let report = identify(
+            &fingerprint_folder(folder.path()).unwrap(),
+            &fixture_releases(),
+        );
+
+        assert!(report.release.is_none());
+        assert!(!report.is_exact());
+        assert!(report.missing.is_empty());
+    }
+}
@@ -0,0 +1,273 @@
+//! Module related to the places a daisyMoon can be fetched from
+//!
+//! Every source puts the files into a staging folder, which gets checked
+//! before anything is copied into the install.
+
+use std::{
+    fs::File,
+    io::{Cursor, Read, Seek},
+    path::{Path, PathBuf},
+};
+
+use fs_extra::dir::CopyOptions;
+use futures::future::BoxFuture;
+
+use super::{
+    daisymoon::zip_entry_path, errors::SourceError, google_drive::GoogleDriveSource,
+    user_data::user_data_dir,
+};
+
+/// Folder in the user data folder sources are fetched into before being checked
+const STAGING_FOLDER: &str = "staging";
+
+/// Somewhere a daisyMoon can be fetched from
+pub trait DaisyMoonSource: Send + Sync {
+    /// Where the daisyMoon comes from, for messages and the library
+    fn describe(&self) -> String;
+
+    /// Puts the daisyMoon files directly into `destination`, which exists and is empty
+    fn fetch<'a>(&'a self, destination: &'a Path) -> BoxFuture<'a, Result<(), SourceError>>;
+}
+
+/// A daisyMoon folder on disk
+pub struct FolderSource {
+    pub path: PathBuf,
+}
+
+impl DaisyMoonSource for FolderSource {
+    fn describe(&self) -> String {
+        self.path.display().to_string()
+    }
+
+    fn fetch<'a>(&'a self, destination: &'a Path) -> BoxFuture<'a, Result<(), SourceError>> {
+        Box::pin(async move {
+            let options = CopyOptions {
+                copy_inside: true,
+                content_only: true,
+                ..Default::default()
+            };
+            fs_extra::dir::copy(&self.path, destination, &options)?;
+
+            Ok(())
+        })
+    }
+}
+
+/// A zipped daisyMoon on disk
+pub struct ArchiveSource {
+    pub path: PathBuf,
+}
+
+impl DaisyMoonSource for ArchiveSource {
+    fn describe(&self) -> String {
+        self.path.display().to_string()
+    }
+
+    fn fetch<'a>(&'a self, destination: &'a Path) -> BoxFuture<'a, Result<(), SourceError>> {
+        Box::pin(async move { extract_zip(File::open(&self.path)?, destination) })
+    }
+}
+
+/// A zipped daisyMoon served over HTTP
+pub struct HttpSource {
+    pub url: String,
+}
+
+impl DaisyMoonSource for HttpSource {
+    fn describe(&self) -> String {
+        self.url.clone()
+    }
+
+    fn fetch<'a>(&'a self, destination: &'a Path) -> BoxFuture<'a, Result<(), SourceError>> {
+        Box::pin(async move {
+            let response = reqwest::get(&self.url).await?;
+
+            if !response.status().is_success() {
+                return Err(SourceError::Status {
+                    url: self.url.clone(),
+                    status: response.status().as_u16(),
+                });
+            }
+
+            let bytes = response.bytes().await?;
+
+            extract_zip(Cursor::new(bytes), destination)
+        })
+    }
+}
+
+/// Extracts a zipped daisyMoon into a folder
+pub fn extract_zip<R: Read + Seek>(reader: R, destination: &Path) -> Result<(), SourceError> {
+    let mut archive = zip::ZipArchive::new(reader)?;
+
+    for i in 0..archive.len() {
+        let mut file = archive.by_index(i)?;
+
+        if file.is_dir() {
+            continue;
+        }
+
+        let Some(name) = file.enclosed_name().map(zip_entry_path) else {
+            continue;
+        };
+
+        let outpath = destination.join(name);
+
+        std::fs::create_dir_all(outpath.parent().unwrap())?;
+
+        let mut outfile = File::create(&outpath)?;
+        std::io::copy(&mut file, &mut outfile)?;
+    }
+
+    Ok(())
+}
+
+/// Picks the source for what the user typed: a folder, a zip, a URL or a Google Drive folder link
+///
+/// Returns `None` if it's none of those.
+pub fn source_from_input(
+    input: &str,
+    drive_api_key: Option<String>,
+) -> Option<Box<dyn DaisyMoonSource>> {
+    let input = input.trim();
+
+    if input.starts_with("http://") || input.starts_with("https://") {
+        if let Some(folder_id) = GoogleDriveSource::folder_id_from_url(input) {
+            return Some(Box::new(GoogleDriveSource::new(
+                folder_id,
+                drive_api_key.unwrap_or_default(),
+            )));
+        }
+
+        return Some(Box::new(HttpSource {
+            url: input.to_string(),
+        }));
+    }
+
+    let path = PathBuf::from(input);
+
+    if path.is_dir() {
+        return Some(Box::new(FolderSource { path }));
+    }
+
+    if path.is_file() && path.extension().is_some_and(|extension| extension == "zip") {
+        return Some(Box::new(ArchiveSource { path }));
+    }
+
+    None
+}
+
+/// Fetches a source into a fresh staging folder and returns its path
+pub async fn fetch_to_staging(source: &dyn DaisyMoonSource) -> Result<PathBuf, SourceError> {
+    let staging = user_data_dir().join(STAGING_FOLDER);
+
+    if staging.exists() {
+        std::fs::remove_dir_all(&staging)?;
+    }
+    std::fs::create_dir_all(&staging)?;
+
+    source.fetch(&staging).await?;
+
+    Ok(staging)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use mockito::Server;
+    use zip::{write::FileOptions, ZipWriter};
+
+    use super::*;
+
+    /// A zip of daisyMoon the way the archive has it, inside a `daisyMoon/` folder
+    fn daisymoon_zip() -> Vec<u8> {
+        let mut bytes = Vec::new();
+        {
+            let mut zip = ZipWriter::new(Cursor::new(&mut bytes));
+            zip.add_directory("daisyMoon/lib/", FileOptions::default())
+                .unwrap();
+            zip.start_file("daisyMoon/lib/game.lua", FileOptions::default())
+                .unwrap();
+            zip.write_all(b"game").unwrap();
+            zip.start_file("daisyMoon/launcher.lua", FileOptions::default())
+                .unwrap();
+            zip.write_all(b"launcher").unwrap();
+            zip.finish().unwrap();
+        }
+        bytes
+    }
+
+    fn assert_extracted(destination: &Path) {
+        let read = |path: &str| std::fs::read_to_string(destination.join(path)).unwrap();
+        assert_eq!(read("lib/game.lua"), "game");
+        assert_eq!(read("launcher.lua"), "launcher");
+    }
+
+    #[tokio::test]
+    async fn fetches_zips_over_http() {
+        let mut server = Server::new_async().await;
+        server
+            .mock("GET", "/daisyMoon.zip")
+            .with_body(daisymoon_zip())
+            .create_async()
+            .await;
+
+        let destination = tempfile::tempdir().unwrap();
+        let source = HttpSource {
+            url: format!("{}/daisyMoon.zip", server.url()),
+        };
+        source.fetch(destination.path()).await.unwrap();
+
+        assert_extracted(destination.path());
+    }
+
+    #[tokio::test]
+    async fn reports_http_errors() {
+        let mut server = Server::new_async().await;
+        server
+            .mock("GET", "/missing.zip")
+            .with_status(404)
+            .create_async()
+            .await;
+
+        let destination = tempfile::tempdir().unwrap();
+        let source = HttpSource {
+            url: format!("{}/missing.zip", server.url()),
+        };
+
+        assert!(matches!(
+            source.fetch(destination.path()).await,
+            Err(SourceError::Status { status: 404, .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn extracts_archives() {
+        let folder = tempfile::tempdir().unwrap();
+        let path = folder.path().join("daisyMoon.zip");
+        std::fs::write(&path, daisymoon_zip()).unwrap();
+
+        let destination = tempfile::tempdir().unwrap();
+        ArchiveSource { path }
+            .fetch(destination.path())
+            .await
+            .unwrap();
+
+        assert_extracted(destination.path());
+    }
+
+    #[test]
+    fn picks_sources_from_input() {
+        let drive = source_from_input("https://drive.google.com/drive/folders/abc", None).unwrap();
+        assert_eq!(
+            drive.describe(),
+            "https://drive.google.com/drive/folders/abc"
+        );
+
+        let http = source_from_input(" https://example.com/daisyMoon.zip ", None).unwrap();
+        assert_eq!(http.describe(), "https://example.com/daisyMoon.zip");
+
+        assert!(source_from_input("/does/not/exist", None).is_none());
+    }
+}
@@ -0,0 +1,218 @@
+//! Module related to launching installs from the Linux applications menu
+//!
+//! Every install can get a freedesktop entry in `~/.local/share/applications`,
+//! which runs the game with the runner found when it was made. Entries record
+//! the install they start, so they can be found again when it's renamed or removed.
+
+use std::path::{Path, PathBuf};
+
+use super::{
+    errors::DesktopError,
+    exe_icon::read_icon,
+    runner::{self, LaunchCommand, Runner},
+    steam::read_app_id,
+    INSTALLER_FOLDER,
+};
+
+/// Key of the entry holding the path of the install it starts
+const INSTALL_KEY: &str = "X-Alloy-Install";
+
+/// The icon taken from the game, in the installer folder
+const ICON_FILENAME: &str = "icon.png";
+
+/// Where entries go
+pub fn applications_dir() -> Option<PathBuf> {
+    dirs::data_dir().map(|data| data.join("applications"))
+}
+
+/// A file name that stays the same for an install and differs between installs
+fn entry_name(install_dir: &Path) -> String {
+    let folder: String = install_dir
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default()
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_lowercase()
+            } else {
+                '-'
+            }
+        })
+        .collect();
+    let hash = crc32fast::hash(install_dir.to_string_lossy().as_bytes());
+
+    format!("alloy-{}-{:08x}.desktop", folder, hash)
+}
+
+/// Quotes an argument of `Exec` the way the desktop entry spec asks
+fn quote_exec_arg(arg: &str) -> String {
+    const RESERVED: &[char] = &[
+        ' ', '\t', '\n', '"', '\'', '\\', '>', '<', '~', '|', '&', ';', '$', '*', '?', '#', '(',
+        ')', '`', '=',
+    ];
+
+    let arg = arg.replace('%', "%%");
+
+    if !arg.is_empty() && !arg.contains(RESERVED) {
+        return arg;
+    }
+
+    let mut quoted = String::from("\"");
+    for c in arg.chars() {
+        if matches!(c, '"' | '`' | '$' | '\\') {
+            quoted.push('\\');
+        }
+        quoted.push(c);
+    }
+    quoted.push('"');
+
+    quoted
+}
+
+/// The `Exec` line of a command, environment variables being set through `env`
+pub fn exec_line(command: &LaunchCommand) -> String {
+    let mut parts: Vec<String> = Vec::new();
+
+    if !command.env.is_empty() {
+        parts.push("env".to_string());
+        parts.extend(
+            command
+                .env
+                .iter()
+                .map(|(key, value)| quote_exec_arg(&format!("{}={}", key, value))),
+        );
+    }
+
+    parts.push(quote_exec_arg(&command.program.display().to_string()));
+    parts.extend(command.args.iter().map(|arg| quote_exec_arg(arg)));
+
+    // Values of the file escape backslashes once more
+    parts.join(" ").replace('\\', "\\\\")
+}
+
+/// The contents of the entry of an install
+pub fn desktop_entry(
+    name: &str,
+    install_dir: &Path,
+    command: &LaunchCommand,
+    icon: Option<&Path>,
+) -> String {
+    let mut entry = String::from("[Desktop Entry]\n");
+    entry.push_str("Type=Application\n");
+    entry.push_str(&format!("Name={}\n", name));
+    entry.push_str("Comment=Cobalt with Alloy\n");
+    entry.push_str(&format!("Exec={}\n", exec_line(command)));
+    entry.push_str(&format!("Path={}\n", command.working_dir.display()));
+    if let Some(icon) = icon {
+        entry.push_str(&format!("Icon={}\n", icon.display()));
+    }
+    entry.push_str("Terminal=false\n");
+    entry.push_str("Categories=Game;\n");
+    entry.push_str(&format!("{}={}\n", INSTALL_KEY, install_dir.display()));
+
+    entry
+}
+
+/// Writes the entry of an install, replacing the one it had, returns its path
+pub fn write_entry(
+    install_dir: &Path,
+    name: &str,
+    runner: &Runner,
+) -> Result<PathBuf, DesktopError> {
+    let applications = applications_dir().ok_or(DesktopError::NoDataDir)?;
+    std::fs::create_dir_all(&applications)?;
+
+    let installer_dir = install_dir.join(INSTALLER_FOLDER);
+    let icon = match read_icon(&install_dir.join("cobaltDM.exe"))? {
+        Some(icon) => {
+            let path = installer_dir.join(ICON_FILENAME);
+            icon.save(&path)
+                .map_err(|e| DesktopError::Artwork { source: e.into() })?;
+            Some(path)
+        }
+        None => None,
+    };
+
+    if let Runner::Proton { .. } = runner {
+        std::fs::create_dir_all(installer_dir.join(runner::COMPAT_DATA_FOLDER))?;
+    }
+
+    let appid = read_app_id(install_dir);
+    let command = runner::launch_command(runner, install_dir, appid.as_deref(), &[]);
+    let path = applications.join(entry_name(install_dir));
+
+    remove_entries(install_dir)?;
+    std::fs::write(
+        &path,
+        desktop_entry(name, install_dir, &command, icon.as_deref()),
+    )?;
+
+    Ok(path)
+}
+
+/// The entries starting an install
+pub fn find_entries(install_dir: &Path) -> Vec<PathBuf> {
+    let Some(Ok(entries)) = applications_dir().map(std::fs::read_dir) else {
+        return Vec::new();
+    };
+
+    let line = format!("{}={}", INSTALL_KEY, install_dir.display());
+
+    entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|extension| extension == "desktop")
+        })
+        .filter(|path| {
+            std::fs::read_to_string(path)
+                .map(|contents| contents.lines().any(|found| found == line))
+                .unwrap_or(false)
+        })
+        .collect()
+}
+
+/// Removes the entries starting an install, returns how many there were
+pub fn remove_entries(install_dir: &Path) -> std::io::Result<usize> {
+    let entries = find_entries(install_dir);
+
+    for entry in &entries {
+        std::fs::remove_file(entry)?;
+    }
+
+    Ok(entries.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quotes_the_exec_line() {
+        let command = LaunchCommand {
+            program: PathBuf::from("/games/Cobalt Alloy/proton"),
+            args: vec!["run".to_string(), "50%$".to_string()],
+            env: vec![("SteamAppId".to_string(), "357340".to_string())],
+            working_dir: PathBuf::from("/games/Cobalt Alloy"),
+        };
+
+        assert_eq!(
+            exec_line(&command),
+            r#"env "SteamAppId=357340" "/games/Cobalt Alloy/proton" run "50%%\\$""#
+        );
+    }
+
+    #[test]
+    fn leaves_plain_commands_unquoted() {
+        let command = LaunchCommand {
+            program: PathBuf::from("/games/Cobalt/cobaltDM.exe"),
+            args: Vec::new(),
+            env: Vec::new(),
+            working_dir: PathBuf::from("/games/Cobalt"),
+        };
+
+        assert_eq!(exec_line(&command), "/games/Cobalt/cobaltDM.exe");
+    }
+}
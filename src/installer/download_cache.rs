@@ -0,0 +1,91 @@
+//! Module related to the per-user cache of downloaded files
+//!
+//! Files whose hash is known beforehand are kept by their sha256, so they're
+//! only downloaded once for every install and checked every time they're used.
+//! That is mod packages, from mod indexes and locks. Alloy's diff and
+//! translations follow the latest Alloy and have no known hash, so they're
+//! always downloaded; imported daisyMoons are kept in their own library.
+
+use std::path::PathBuf;
+
+use super::{errors::DownloadError, files::sha256_hex, user_data::user_data_dir};
+
+/// Folder in the user data folder downloads are kept in
+const CACHE_FOLDER: &str = "downloads";
+
+/// Where the file with some sha256 is kept
+fn cached_path(sha256: &str) -> PathBuf {
+    user_data_dir()
+        .join(CACHE_FOLDER)
+        .join(sha256.to_lowercase())
+}
+
+/// Gets the contents of a URL or a local path, which must have the given sha256
+///
+/// The cached copy is used when there's one, otherwise the file is fetched and cached.
+pub async fn fetch_verified(location: &str, sha256: &str) -> Result<Vec<u8>, DownloadError> {
+    let cached = cached_path(sha256);
+
+    if let Ok(bytes) = std::fs::read(&cached) {
+        if sha256_hex(&bytes).eq_ignore_ascii_case(sha256) {
+            return Ok(bytes);
+        }
+    }
+
+    let bytes = if location.starts_with("http://") || location.starts_with("https://") {
+        let response = reqwest::get(location).await?;
+
+        if !response.status().is_success() {
+            return Err(DownloadError::Status {
+                url: location.to_string(),
+                status: response.status().as_u16(),
+            });
+        }
+
+        response.bytes().await?.to_vec()
+    } else {
+        std::fs::read(location)?
+    };
+
+    let found = sha256_hex(&bytes);
+    if !found.eq_ignore_ascii_case(sha256) {
+        return Err(DownloadError::HashMismatch {
+            location: location.to_string(),
+            expected: sha256.to_string(),
+            found,
+        });
+    }
+
+    std::fs::create_dir_all(cached.parent().unwrap())?;
+    std::fs::write(&cached, &bytes)?;
+
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn refuses_files_with_another_hash() {
+        let folder = tempfile::tempdir().unwrap();
+        let path = folder.path().join("package.zip");
+        std::fs::write(&path, "not what the index says").unwrap();
+
+        let expected = sha256_hex(b"what the index says");
+        let result = fetch_verified(&path.display().to_string(), &expected).await;
+
+        match result {
+            Err(DownloadError::HashMismatch {
+                expected: reported,
+                found,
+                ..
+            }) => {
+                assert_eq!(reported, expected);
+                assert_eq!(found, sha256_hex(b"not what the index says"));
+            }
+            other => panic!("expected a hash mismatch, got {:?}", other.map(|_| ())),
+        }
+        assert!(!cached_path(&expected).exists());
+    }
+}
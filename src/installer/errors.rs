@@ -1 +1,212 @@
+//! Error types for the parts of the installer that can recover from failure
 
+use custom_error::custom_error;
+
+use super::platform::TargetPlatform;
+
+custom_error! {
+/// Errors from the per-user daisyMoon library
+pub LibraryError
+    Io{source: std::io::Error} = "{source}",
+    Copy{source: fs_extra::error::Error} = "failed to copy daisyMoon: {source}",
+    Decode{source: ron::error::SpannedError} = "failed to read the library index: {source}",
+    Encode{source: ron::Error} = "failed to write the library index: {source}",
+    NotFound{id: String} = "no stored daisyMoon matches {id}",
+    Ambiguous{id: String} = "more than one stored daisyMoon matches {id}, use more of the hash",
+}
+
+custom_error! {
+/// Errors from fetching a daisyMoon from one of its sources
+pub SourceError
+    Io{source: std::io::Error} = "{source}",
+    Copy{source: fs_extra::error::Error} = "failed to copy daisyMoon: {source}",
+    Zip{source: zip::result::ZipError} = "failed to read the zip: {source}",
+    Http{source: reqwest::Error} = "failed to download: {source}",
+    Status{url: String, status: u16} = "{url} answered with HTTP {status}",
+    Json{source: serde_json::Error} = "failed to read the response: {source}",
+    Drive{message: String} = "Google Drive error: {message}",
+}
+
+custom_error! {
+/// Errors from copying the game
+pub CopyError
+    Io{source: std::io::Error} = "{source}",
+    NotEnoughSpace{needed: u64, available: u64} = "not enough free space, {needed} bytes are needed but only {available} are free",
+    Verify{path: String} = "{path} is different after copying",
+}
+
+custom_error! {
+/// Errors from the per-user registry of installs
+pub RegistryError
+    Io{source: std::io::Error} = "{source}",
+    Decode{source: ron::error::SpannedError} = "failed to read the registry: {source}",
+    Encode{source: ron::Error} = "failed to write the registry: {source}",
+    NotFound{query: String} = "no install matches {query}",
+    Ambiguous{query: String} = "more than one install matches {query}, use its path",
+    Metadata{path: String, reason: String} = "the metadata of {path} can't be read, it may be from a newer installer: {reason}",
+    AlreadyExists{path: String} = "{path} already exists",
+    Refused{reason: String} = "{reason}",
+}
+
+custom_error! {
+/// Errors from updating Alloy in an install
+pub UpdateError
+    Io{source: std::io::Error} = "{source}",
+    NoPristine = "this install was made before the installer kept its vanilla files, reinstall it once to be able to update",
+    Patch{output: String} = "the new diff doesn't apply to vanilla daisyMoon:\n{output}",
+}
+
+custom_error! {
+/// Errors from backing up and restoring an install's files
+pub BackupError
+    Io{source: std::io::Error} = "{source}",
+    Zip{source: zip::result::ZipError} = "failed to read the backup: {source}",
+    Decode{source: ron::error::SpannedError} = "failed to read the list of backups: {source}",
+    Encode{source: ron::Error} = "failed to write the list of backups: {source}",
+    Ambiguous{id: String} = "more than one backup matches {id}",
+    NoRecords = "it was made by an older installer, which didn't keep what was installed along with it",
+    ModsChanged = "the installed mods changed since it was made, put them back the way they were first",
+}
+
+custom_error! {
+/// Errors from reading or writing Steam's VDF files
+pub VdfError
+    UnexpectedEnd = "the file ends too early",
+    UnknownType{kind: u8, offset: usize} = "unknown value type {kind} at byte {offset}",
+    Syntax{line: usize} = "unexpected text on line {line}",
+}
+
+custom_error! {
+/// Errors from merging Alloy's translations into the game's
+pub TranslationsError
+    Io{source: std::io::Error} = "{source}",
+    Decode{source: ron::error::SpannedError} = "failed to read what was merged before: {source}",
+    Encode{source: ron::Error} = "failed to record what was merged: {source}",
+}
+
+custom_error! {
+/// Errors from downloading Alloy's files into an install
+pub AlloyDownloadError
+    Io{source: std::io::Error} = "{source}",
+    Download{source: reqwest::Error} = "failed to download: {source}",
+    Translations{source: TranslationsError} = "failed to merge Alloy's translations into the game's: {source}",
+}
+
+custom_error! {
+/// Errors from making Steam library artwork
+pub ArtworkError
+    Io{source: std::io::Error} = "{source}",
+    Image{source: image::ImageError} = "{source}",
+}
+
+custom_error! {
+/// Errors from adding an install to the Linux applications menu
+pub DesktopError
+    Io{source: std::io::Error} = "{source}",
+    Artwork{source: ArtworkError} = "failed to get the game's icon: {source}",
+    NoDataDir = "couldn't find where applications go",
+    NoRunner = "there's nothing to run Windows games with, install Proton through Steam or Wine",
+}
+
+custom_error! {
+/// Errors from starting the game of an install
+pub LaunchError
+    Io{source: std::io::Error} = "{source}",
+    NoRunner{target: TargetPlatform} = "there's nothing to run a {target} game with here, install Proton through Steam or Wine",
+    NotNative{target: TargetPlatform} = "this is a {target} install, it can't run natively here",
+    NoSteam = "Proton needs Steam, which doesn't seem to be installed",
+    NoProton = "couldn't find Proton in your Steam libraries",
+    ProtonNotFound{query: String} = "no Proton in your Steam libraries matches {query}",
+    WineNotFound = "couldn't find Wine, install it or give its path with --wine",
+}
+
+custom_error! {
+/// Errors from adding an install to Steam
+pub ShortcutError
+    Io{source: std::io::Error} = "{source}",
+    Vdf{source: VdfError} = "failed to read Steam's files: {source}",
+    NoSteam = "Steam doesn't seem to be installed, or you never logged in",
+    NoUser{user: String} = "there's no Steam user {user}",
+}
+
+custom_error! {
+/// Errors from getting files through the download cache
+pub DownloadError
+    Io{source: std::io::Error} = "{source}",
+    Http{source: reqwest::Error} = "failed to download: {source}",
+    Status{url: String, status: u16} = "{url} answered with HTTP {status}",
+    HashMismatch{location: String, expected: String, found: String} = "{location} has sha256 {found}, {expected} was expected",
+}
+
+custom_error! {
+/// Errors from installing and removing mods
+pub ModError
+    Io{source: std::io::Error} = "{source}",
+    Zip{source: zip::result::ZipError} = "failed to zip or unzip the package: {source}",
+    Http{source: reqwest::Error} = "failed to download the package: {source}",
+    Status{url: String, status: u16} = "{url} answered with HTTP {status}",
+    Download{source: DownloadError} = "{source}",
+    Decode{source: ron::error::SpannedError} = "failed to read alloy_mod.ron: {source}",
+    NotAPackage{input: String} = "{input} isn't a folder, a zip or a URL",
+    NoManifest = "the package has no alloy_mod.ron",
+    BadId{id: String} = "\"{id}\" can't be a mod id, use letters, digits, - and _",
+    BadVersion{version: String, reason: String} = "\"{version}\" isn't a semver version or requirement: {reason}",
+    BadPath{path: String} = "the package lists {path}, which is outside of it",
+    BadPatch{patch: String, path: String} = "{patch} changes {path}, mod patches can only change daisyMoon",
+    Patch{id: String, name: String, output: String} = "{name} from {id} doesn't apply:\n{output}",
+    Unpatch{id: String, name: String, output: String} = "{name} from {id} can't be taken off:\n{output}",
+    MissingFile{path: String} = "the package lists {path} but doesn't have it",
+    Unsupported{id: String, alloy_version: String, supported: String} = "{id} supports Alloy {supported}, this install has Alloy {alloy_version}",
+    NotInstalled{id: String} = "no mod {id} is installed",
+    NoProfile{name: String} = "there's no mod profile {name}",
+    Needed{id: String, needed_by: String} = "{id} is needed by {needed_by}, remove those first",
+    IndexMismatch{location: String, expected: String, found: String} = "{location} is {found}, but the index lists it as {expected}",
+    NotEmpty{path: String} = "{path} already exists and isn't empty",
+    NoPristine = "the install didn't keep daisyMoon as it was installed, reinstall it first",
+}
+
+custom_error! {
+/// Errors from reading mod indexes
+pub ModIndexError
+    Io{source: std::io::Error} = "{source}",
+    Http{source: reqwest::Error} = "failed to download the index: {source}",
+    Status{url: String, status: u16} = "{url} answered with HTTP {status}",
+    Json{source: serde_json::Error} = "failed to read the index: {source}",
+    Ron{source: ron::error::SpannedError} = "failed to read the index: {source}",
+}
+
+custom_error! {
+/// Why mods can't be installed together
+pub PlanProblem
+    Duplicate{id: String} = "{id} was given more than once",
+    Unsupported{id: String, version: String, alloy_version: String} = "{id} {version} doesn't work with Alloy {alloy_version}",
+    Missing{id: String, dependency: String, requirement: String} = "{id} needs {dependency} {requirement}, which isn't installed, given or in your mod indexes",
+    WrongVersion{id: String, dependency: String, requirement: String, found: String} = "{id} needs {dependency} {requirement}, but it would be {found}",
+    Conflict{id: String, other: String, version: String} = "{id} can't be installed with {other} {version}",
+    File{path: String, id: String, other: String} = "{id} and {other} both have alloys/{path}",
+    Disabled{id: String, dependency: String} = "{id} needs {dependency}, which would be disabled",
+    Hunk{path: String, line: usize, id: String, other: String} = "{id} and {other} both patch {path} around line {line}",
+}
+
+custom_error! {
+/// Errors from reading and writing `alloy.lock` files
+pub LockError
+    Io{source: std::io::Error} = "{source}",
+    Decode{source: ron::error::SpannedError} = "failed to read the lock: {source}",
+    Unrecorded{what: String, fix: String} = "the install didn't record {what}, {fix} first",
+}
+
+custom_error! {
+/// Why an install can't be made exactly as a lock describes it
+pub LockProblem
+    AlloyVersion{locked: String, available: String} = "the lock is for Alloy {locked}, this installer installs Alloy {available}",
+    Target{locked: TargetPlatform, asked: TargetPlatform} = "the lock is for {locked}, but --target asked for {asked}",
+    Download{what: String, reason: String} = "couldn't download {what}: {reason}",
+    Changed{what: String, expected: String, found: String} = "{what} has sha256 {found} now, the lock has {expected}",
+    DaisyMoon{expected: String, found: String} = "the daisyMoon given hashes to {found}, the lock needs {expected}",
+    ModUnavailable{id: String, version: String} = "{id} {version} isn't in your mod indexes, and the lock doesn't say where to get it",
+    ModFetch{id: String, version: String, reason: String} = "couldn't get {id} {version}: {reason}",
+    ModMismatch{id: String, version: String, found: String} = "the package for {id} {version} is {found}",
+    ModChanged{id: String, version: String, expected: String, found: String} = "{id} {version} hashes to {found}, the lock has {expected}",
+    Plan{problem: PlanProblem} = "{problem}",
+}
@@ -0,0 +1,229 @@
+//! Module related to getting the icon out of the game's executable
+//!
+//! Windows executables keep their icons as resources: an icon group lists the
+//! sizes available, and each size is its own resource holding a BMP or a PNG.
+//! The largest size is put back together as an `.ico` file and decoded from there.
+
+use std::path::Path;
+
+use image::{ImageFormat, RgbaImage};
+
+use super::errors::ArtworkError;
+
+/// Resource type of a single icon image
+const RT_ICON: u32 = 3;
+/// Resource type of an icon group
+const RT_GROUP_ICON: u32 = 14;
+/// Index of the resource table in the data directories
+const RESOURCE_DIRECTORY: usize = 2;
+/// Resource trees are type, name then language, anything deeper is broken or looping
+const RESOURCE_DEPTH: usize = 3;
+
+fn u16_at(bytes: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(
+        bytes.get(offset..offset + 2)?.try_into().ok()?,
+    ))
+}
+
+fn u32_at(bytes: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(
+        bytes.get(offset..offset + 4)?.try_into().ok()?,
+    ))
+}
+
+/// The parts of a PE file needed to read its resources
+struct PeFile<'a> {
+    bytes: &'a [u8],
+    /// Virtual address, virtual size and file offset of every section
+    sections: Vec<(u32, u32, u32)>,
+    /// File offset of the resource table
+    resources: usize,
+}
+
+impl<'a> PeFile<'a> {
+    fn parse(bytes: &'a [u8]) -> Option<PeFile<'a>> {
+        if bytes.get(..2)? != b"MZ" {
+            return None;
+        }
+
+        let pe = u32_at(bytes, 0x3C)? as usize;
+        if bytes.get(pe..pe + 4)? != b"PE\0\0" {
+            return None;
+        }
+
+        let coff = pe + 4;
+        let section_count = u16_at(bytes, coff + 2)? as usize;
+        let optional_size = u16_at(bytes, coff + 16)? as usize;
+        let optional = coff + 20;
+
+        // The data directories start later in 64 bit executables
+        let directories = match u16_at(bytes, optional)? {
+            0x10B => optional + 96,
+            0x20B => optional + 112,
+            _ => return None,
+        };
+        let resources_rva = u32_at(bytes, directories + RESOURCE_DIRECTORY * 8)?;
+
+        let sections = (0..section_count)
+            .map(|index| {
+                let header = optional + optional_size + index * 40;
+                Some((
+                    u32_at(bytes, header + 12)?,
+                    u32_at(bytes, header + 8)?.max(u32_at(bytes, header + 16)?),
+                    u32_at(bytes, header + 20)?,
+                ))
+            })
+            .collect::<Option<Vec<_>>>()?;
+
+        let mut file = PeFile {
+            bytes,
+            sections,
+            resources: 0,
+        };
+        file.resources = file.offset_of(resources_rva)?;
+
+        Some(file)
+    }
+
+    /// Turns a virtual address into a file offset
+    fn offset_of(&self, rva: u32) -> Option<usize> {
+        self.sections
+            .iter()
+            .find(|(address, size, _)| {
+                rva >= *address && address.checked_add(*size).is_some_and(|end| rva < end)
+            })
+            .and_then(|(address, _, offset)| (rva - address).checked_add(*offset))
+            .map(|offset| offset as usize)
+    }
+
+    /// The id and target of every entry of a resource directory
+    fn entries(&self, directory: usize) -> Vec<(u32, u32)> {
+        let named = u16_at(self.bytes, directory + 12).unwrap_or(0) as usize;
+        let ids = u16_at(self.bytes, directory + 14).unwrap_or(0) as usize;
+
+        (0..named + ids)
+            .filter_map(|index| {
+                let entry = directory + 16 + index * 8;
+                Some((u32_at(self.bytes, entry)?, u32_at(self.bytes, entry + 4)?))
+            })
+            .collect()
+    }
+
+    /// The first piece of data under a resource directory entry, following subdirectories
+    fn first_data(&self, mut target: u32) -> Option<&'a [u8]> {
+        // Subdirectories have the high bit set, data entries don't
+        for _ in 0..RESOURCE_DEPTH {
+            if target & 0x8000_0000 == 0 {
+                break;
+            }
+
+            let directory = self.resources + (target & 0x7FFF_FFFF) as usize;
+            target = self.entries(directory).first()?.1;
+        }
+
+        if target & 0x8000_0000 != 0 {
+            return None;
+        }
+
+        let entry = self.resources + target as usize;
+        let offset = self.offset_of(u32_at(self.bytes, entry)?)?;
+        let size = u32_at(self.bytes, entry + 4)? as usize;
+
+        self.bytes.get(offset..offset.checked_add(size)?)
+    }
+
+    /// Every resource of a type, by id
+    fn resources_of_type(&self, kind: u32) -> Vec<(u32, &'a [u8])> {
+        let Some((_, directory)) = self
+            .entries(self.resources)
+            .into_iter()
+            .find(|(id, _)| *id == kind)
+        else {
+            return Vec::new();
+        };
+
+        if directory & 0x8000_0000 == 0 {
+            return Vec::new();
+        }
+
+        self.entries(self.resources + (directory & 0x7FFF_FFFF) as usize)
+            .into_iter()
+            .filter_map(|(id, target)| Some((id, self.first_data(target)?)))
+            .collect()
+    }
+}
+
+/// The largest icon of an executable, as the bytes of an `.ico` file
+pub fn extract_ico(exe: &[u8]) -> Option<Vec<u8>> {
+    let pe = PeFile::parse(exe)?;
+    let group = pe.resources_of_type(RT_GROUP_ICON).first()?.1;
+    let icons = pe.resources_of_type(RT_ICON);
+
+    // An entry is 14 bytes, after 6 bytes of header; 0 means 256 pixels
+    let count = u16_at(group, 4)? as usize;
+    let (entry, data) = (0..count)
+        .filter_map(|index| {
+            let entry = group.get(6 + index * 14..6 + (index + 1) * 14)?;
+            let id = u16_at(entry, 12)? as u32;
+            let data = icons.iter().find(|(found, _)| *found == id)?.1;
+            Some((entry, data))
+        })
+        .max_by_key(|(entry, _)| {
+            let width = if entry[0] == 0 { 256 } else { entry[0] as u32 };
+            (width, u16_at(entry, 6).unwrap_or(0))
+        })?;
+
+    let mut ico = Vec::with_capacity(22 + data.len());
+    ico.extend_from_slice(&[0, 0, 1, 0, 1, 0]);
+    ico.extend_from_slice(&entry[..8]);
+    ico.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    ico.extend_from_slice(&22u32.to_le_bytes());
+    ico.extend_from_slice(data);
+
+    Some(ico)
+}
+
+/// Reads the largest icon of an executable, `None` if it has none
+pub fn read_icon(exe: &Path) -> Result<Option<RgbaImage>, ArtworkError> {
+    let Some(ico) = extract_ico(&std::fs::read(exe)?) else {
+        return Ok(None);
+    };
+
+    let icon = image::load_from_memory_with_format(&ico, ImageFormat::Ico)?;
+
+    Ok(Some(icon.to_rgba8()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stops_at_looping_resource_directories() {
+        // One directory with a single id entry pointing back at itself
+        let mut bytes = vec![0u8; 24];
+        bytes[14..16].copy_from_slice(&1u16.to_le_bytes());
+        bytes[20..24].copy_from_slice(&0x8000_0000u32.to_le_bytes());
+
+        let pe = PeFile {
+            bytes: &bytes,
+            sections: Vec::new(),
+            resources: 0,
+        };
+
+        assert_eq!(pe.first_data(0x8000_0000), None);
+    }
+
+    #[test]
+    fn ignores_sections_past_the_address_space() {
+        let pe = PeFile {
+            bytes: &[],
+            sections: vec![(0xFFFF_F000, 0x2000, 0x10), (0x1000, 0x100, 0xFFFF_FFFF)],
+            resources: 0,
+        };
+
+        assert_eq!(pe.offset_of(0xFFFF_F800), None);
+        assert_eq!(pe.offset_of(0x1010), None);
+        assert_eq!(pe.offset_of(0x1000), Some(0xFFFF_FFFF));
+    }
+}
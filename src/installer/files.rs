@@ -0,0 +1,71 @@
+//! Helpers for walking and hashing folders
+
+use std::path::{Path, PathBuf};
+
+use sha2::{Digest, Sha256};
+
+/// Lists every file under `root`, recursively, as paths relative to `root`.
+///
+/// The result is sorted so it can be compared between runs.
+pub fn list_files(root: &Path) -> std::io::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let mut folders = vec![PathBuf::new()];
+
+    while let Some(relative_folder) = folders.pop() {
+        for entry in std::fs::read_dir(root.join(&relative_folder))? {
+            let entry = entry?;
+            let relative = relative_folder.join(entry.file_name());
+
+            if entry.file_type()?.is_dir() {
+                folders.push(relative);
+            } else {
+                files.push(relative);
+            }
+        }
+    }
+
+    files.sort();
+
+    Ok(files)
+}
+
+/// Lists every folder under `root`, recursively, as paths relative to `root`
+pub fn list_folders(root: &Path) -> std::io::Result<Vec<PathBuf>> {
+    let mut found = Vec::new();
+    let mut folders = vec![PathBuf::new()];
+
+    while let Some(relative_folder) = folders.pop() {
+        for entry in std::fs::read_dir(root.join(&relative_folder))? {
+            let entry = entry?;
+
+            if entry.file_type()?.is_dir() {
+                let relative = relative_folder.join(entry.file_name());
+                found.push(relative.clone());
+                folders.push(relative);
+            }
+        }
+    }
+
+    found.sort();
+
+    Ok(found)
+}
+
+/// Turns a relative path into the forward-slash form used as a key in metadata files
+pub fn path_key(relative: &Path) -> String {
+    relative
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Returns the lowercase hex sha256 of some bytes
+pub fn sha256_hex(bytes: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(bytes))
+}
+
+/// Returns the lowercase hex sha256 of a file
+pub fn sha256_file(path: &Path) -> std::io::Result<String> {
+    Ok(sha256_hex(&std::fs::read(path)?))
+}
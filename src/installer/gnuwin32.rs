@@ -35,5 +35,4 @@ pub async fn get_win32_patch(base_path: PathBuf) -> Result<(), reqwest::Error> {
 
     println!("Downloaded patch.zip did not have patch.exe, what?");
     exit_or_windows(55);
-    unreachable!();
 }
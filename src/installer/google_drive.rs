@@ -1,145 +1,328 @@
-//! This doesn't work for the time being.
+//! Module related to fetching daisyMoon from a Google Drive folder
+//!
+//! If you're in a browser - one click -> downloads as a zip
+//! If you're an app - manually crawl the folder and all its subfolders
 
-/*use std::{path::PathBuf, str::Bytes};
+use std::path::{Path, PathBuf};
 
-pub use serde::{Deserialize, Serialize};
+use futures::{future::BoxFuture, StreamExt};
+use reqwest::{header, Response};
+use serde::{Deserialize, Serialize};
+use tokio::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use super::{daisymoon_source::DaisyMoonSource, errors::SourceError};
 
 const MIMETYPE_FOLDER: &str = "application/vnd.google-apps.folder";
-const DAISYMOON_FOLDER_ID: &str = "10Tw1c530qnA5l3P6u1jRyzI9fa3sEWz5";
 
-/// You need to include this file when compiling, or you'll get problems
-///
-/// This translates to the repo root /google-drive-api-key.txt
-const API_KEY: &str = include_str!("../../google-drive-api-key.txt");
+/// The daisyMoon folder of the Cobalt Archive
+pub const ARCHIVE_DAISYMOON_FOLDER_ID: &str = "10Tw1c530qnA5l3P6u1jRyzI9fa3sEWz5";
+
+/// Where the Drive API lives, can be swapped out for a stand-in server
+const DRIVE_API_BASE: &str = "https://www.googleapis.com/drive/v3";
+
+/// Time between two requests so we don't get rate limited by Google
+const REQUEST_INTERVAL: Duration = Duration::from_millis(101);
+
+/// How many files are downloaded at once
+const PARALLEL_DOWNLOADS: usize = 4;
+
+/// How many times a rate limited or failed request is retried
+const MAX_RETRIES: u32 = 5;
+
+/// Spaces requests out so we stay under Google's rate limits, without blocking the runtime
+struct RateLimiter {
+    interval: Duration,
+    next: Mutex<Instant>,
+}
+
+impl RateLimiter {
+    fn new(interval: Duration) -> RateLimiter {
+        RateLimiter {
+            interval,
+            next: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Waits for the next free slot
+    async fn wait(&self) {
+        let slot = {
+            let mut next = self.next.lock().await;
+            let slot = (*next).max(Instant::now());
+            *next = slot + self.interval;
+            slot
+        };
 
-/// Time to wait in between requests so we don't get rate limited by Google
-const REQUEST_WAIT_MS: u128 = 101;
+        tokio::time::sleep_until(slot).await;
+    }
+}
 
-/// How large is this???
-const DOWNLOAD_REQUEST_WAIT_MS: u128 = 2001;
+/// A daisyMoon folder shared on Google Drive
+pub struct GoogleDriveSource {
+    pub folder_id: String,
+    api_key: String,
+    api_base: String,
+    limiter: RateLimiter,
+}
 
-// The hardest part of writing this installer is the f&#king google drive api
-//
-// If you're in a browser - one click -> downloads as a zip
-// If you're an app - manually crawl the folder and all its subfolders
+impl GoogleDriveSource {
+    /// Creates a source for a Drive folder, the API key is needed to crawl it
+    pub fn new(folder_id: String, api_key: String) -> GoogleDriveSource {
+        GoogleDriveSource {
+            folder_id,
+            api_key,
+            api_base: DRIVE_API_BASE.to_string(),
+            limiter: RateLimiter::new(REQUEST_INTERVAL),
+        }
+    }
 
-/// Downloads the daisymoon folder into base_path/daisyMoon
-pub async fn get_daisymoon_folder(base_path: PathBuf) {
-    let root = index_folder(DAISYMOON_FOLDER_ID.to_string()).await.unwrap();
+    /// Uses another server instead of the Drive API, e.g. a local stand-in
+    pub fn with_api_base(mut self, api_base: &str) -> GoogleDriveSource {
+        self.api_base = api_base.trim_end_matches('/').to_string();
+        self
+    }
 
-    let mut files = Vec::new();
-    let mut folders = Vec::new();
+    /// Gets the folder id out of a `https://drive.google.com/drive/folders/<id>` link
+    pub fn folder_id_from_url(url: &str) -> Option<String> {
+        if !url.contains("drive.google.com") {
+            return None;
+        }
 
-    // See which ones are folder
-    for maybe_file in root.files {
-        if maybe_file.is_folder() {
-            folders.push(("daisyMoon".to_string(), maybe_file));
+        let after = url.split("/folders/").nth(1)?;
+        let id: String = after
+            .chars()
+            .take_while(|c| c.is_ascii_alphanumeric() || *c == '-' || *c == '_')
+            .collect();
+
+        if id.is_empty() {
+            None
         } else {
-            files.push(("daisyMoon".to_string(), maybe_file));
+            Some(id)
         }
     }
 
-    // Index each folder for further files
-    let mut last_request = std::time::Instant::now();
-    while folders.len() != 0 {
-        let (folder_path, folder) = folders.remove(0);
+    /// Sends a rate limited GET, retrying when Google asks us to slow down
+    async fn get(&self, url: &str, query: &[(&str, &str)]) -> Result<Response, SourceError> {
+        let client = reqwest::Client::new();
+        let mut retries = 0;
+
+        loop {
+            self.limiter.wait().await;
+
+            let response = client
+                .get(url)
+                .query(&[("key", self.api_key.as_str())])
+                .query(query)
+                .send()
+                .await?;
 
-        let true_folder_path = folder_path.to_string() + "/" + &folder.name;
+            let status = response.status();
+            let should_retry = status.as_u16() == 429 || status.is_server_error();
+
+            if should_retry && retries < MAX_RETRIES {
+                retries += 1;
+                tokio::time::sleep(REQUEST_INTERVAL * 2u32.pow(retries) * 5).await;
+                continue;
+            }
 
-        // Wait a bit to not get rate limited
-        while last_request.elapsed().as_millis() <= REQUEST_WAIT_MS {
-            std::thread::sleep(std::time::Duration::from_millis(1));
+            if !status.is_success() {
+                let body = response.text().await.unwrap_or_default();
+                let message = serde_json::from_str::<DriveErrorResponse>(&body)
+                    .map(|error| error.error.message)
+                    .unwrap_or_else(|_| format!("{} answered with HTTP {}", url, status));
+
+                return Err(SourceError::Drive { message });
+            }
+
+            return Ok(response);
         }
+    }
 
-        let folder_index = index_folder(folder.id).await.unwrap();
-        last_request = std::time::Instant::now();
+    /// Lists the direct children of a folder
+    async fn list_folder(&self, id: &str) -> Result<Vec<DriveFile>, SourceError> {
+        let url = format!("{}/files", self.api_base);
+        let q = format!("'{}' in parents and trashed = false", id);
+
+        let mut files = Vec::new();
+        let mut page_token = String::new();
+
+        loop {
+            let mut query = vec![
+                ("q", q.as_str()),
+                ("pageSize", "1000"),
+                ("fields", "nextPageToken, files(id, name, mimeType)"),
+            ];
+            if !page_token.is_empty() {
+                query.push(("pageToken", page_token.as_str()));
+            }
+
+            let body = self.get(&url, &query).await?.text().await?;
+            let response: DriveListResponse = serde_json::from_str(&body)?;
+            files.extend(response.files);
 
-        for maybe_file in folder_index.files {
-            if maybe_file.is_folder() {
-                folders.push((true_folder_path.clone(), maybe_file));
-            } else {
-                files.push((true_folder_path.clone(), maybe_file));
+            match response.next_page_token {
+                Some(token) => page_token = token,
+                None => return Ok(files),
             }
         }
     }
 
-    drop(folders);
+    /// Lists every file under the folder, with the folder it's in relative to the root
+    async fn crawl(&self) -> Result<Vec<(PathBuf, DriveFile)>, SourceError> {
+        let mut files = Vec::new();
+        let mut folders = vec![(PathBuf::new(), self.folder_id.clone())];
+
+        while let Some((folder_path, folder_id)) = folders.pop() {
+            for file in self.list_folder(&folder_id).await? {
+                // Checked before anything is downloaded
+                let name = file.safe_name()?;
+
+                if file.is_folder() {
+                    folders.push((folder_path.join(name), file.id));
+                } else {
+                    files.push((folder_path.clone(), file));
+                }
+            }
+        }
 
-    println!("Fetched list of files ({})", files.len());
-    println!("Downloading each file.. (this may take a bit)");
+        Ok(files)
+    }
 
-    while files.len() != 0 {
-        let (file_path, file) = files.remove(0);
+    /// Downloads a file, getting past the "can't scan this for viruses" page of large files
+    async fn download(&self, id: &str) -> Result<Vec<u8>, SourceError> {
+        let url = format!("{}/files/{}", self.api_base, id);
+        let query = [("alt", "media"), ("acknowledgeAbuse", "true")];
 
-        let file_path = base_path.clone().join(file_path).join(file.name.clone());
+        let response = self.get(&url, &query).await?;
 
-        // Wait a bit to not get rate limited
-        while last_request.elapsed().as_millis() <= DOWNLOAD_REQUEST_WAIT_MS {
-            std::thread::sleep(std::time::Duration::from_millis(1));
+        if !is_html(&response) {
+            return Ok(response.bytes().await?.to_vec());
         }
-        let file_data = download_file(file.id).await.unwrap();
-        last_request = std::time::Instant::now();
 
-        std::fs::create_dir_all(file_path.parent().unwrap()).unwrap();
-        std::fs::write(file_path, file_data).unwrap();
+        // Large files get a warning page instead, with a token to confirm the download
+        let cookie_token = response
+            .headers()
+            .get_all(header::SET_COOKIE)
+            .iter()
+            .filter_map(|cookie| cookie.to_str().ok())
+            .find_map(|cookie| {
+                let rest = cookie.strip_prefix("download_warning")?;
+                let value = rest.split_once('=')?.1;
+                Some(value.split(';').next()?.to_string())
+            });
+
+        let page = response.text().await?;
+
+        let Some(token) = cookie_token.or_else(|| find_confirm_token(&page)) else {
+            return Err(SourceError::Drive {
+                message: format!("file {} needs a confirmation I don't understand", id),
+            });
+        };
+
+        let mut query = query.to_vec();
+        query.push(("confirm", token.as_str()));
+
+        let response = self.get(&url, &query).await?;
+
+        if is_html(&response) {
+            return Err(SourceError::Drive {
+                message: format!("file {} still isn't downloadable after confirming", id),
+            });
+        }
 
-        println!("Downloaded {}, {} left", file.name, files.len());
+        Ok(response.bytes().await?.to_vec())
     }
 }
 
-/// Indexes a google drive folder
-pub async fn index_folder(id: String) -> Result<DriveListResponse, reqwest::Error> {
-    let url = format!(
-        "https://www.googleapis.com/drive/v3/files?q='{}'%20in%20parents&key={}",
-        id, API_KEY
-    );
-
-    let reqwest_client = reqwest::Client::new();
-    let response = reqwest_client.get(url).send().await?;
+impl DaisyMoonSource for GoogleDriveSource {
+    fn describe(&self) -> String {
+        format!("https://drive.google.com/drive/folders/{}", self.folder_id)
+    }
 
-    let is_error = response.status().is_client_error();
+    fn fetch<'a>(&'a self, destination: &'a Path) -> BoxFuture<'a, Result<(), SourceError>> {
+        Box::pin(async move {
+            if self.api_key.is_empty() {
+                return Err(SourceError::Drive {
+                    message: "an API key is needed to download folders".to_string(),
+                });
+            }
 
-    let json_body = response.text().await?;
+            let files = self.crawl().await?;
+            let total = files.len();
 
-    if is_error {
-        println!("An error occurred: {}", json_body);
-    }
+            println!("Fetched list of files ({})", total);
+            println!("Downloading each file.. (this may take a bit)");
 
-    let deserialized_response: DriveListResponse = serde_json::from_str(&json_body).unwrap();
+            let mut downloads = futures::stream::iter(files)
+                .map(|(folder_path, file)| async move {
+                    let bytes = self.download(&file.id).await?;
 
-    Ok(deserialized_response)
-}
+                    let file_path = destination.join(folder_path).join(file.safe_name()?);
+                    tokio::fs::create_dir_all(file_path.parent().unwrap()).await?;
+                    tokio::fs::write(file_path, bytes).await?;
 
-/// Downloads a file by id and returns its buffer
-pub async fn download_file(id: String) -> Result<Vec<u8>, reqwest::Error> {
-    let url = format!(
-        "https://www.googleapis.com/drive/v3/files/{}?acknowledgeAbuse=true&alt=media&key={}",
-        id, API_KEY
-    );
+                    Ok::<String, SourceError>(file.name)
+                })
+                .buffer_unordered(PARALLEL_DOWNLOADS);
 
-    let reqwest_client = reqwest::Client::new();
-    let response = reqwest_client.get(url).send().await?;
+            let mut done = 0;
+            while let Some(result) = downloads.next().await {
+                let name = result?;
+                done += 1;
+                println!("Downloaded {}, {} left", name, total - done);
+            }
 
-    if response.status().is_client_error() {
-        println!("An error occurred: {}", response.text().await.unwrap());
-        panic!("");
+            Ok(())
+        })
     }
+}
 
-    let bytes = response.bytes().await.unwrap().to_vec();
+/// Whether a response is an HTML page rather than the file we asked for
+fn is_html(response: &Response) -> bool {
+    response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("text/html"))
+}
 
-    Ok(bytes)
+/// Finds the confirmation token in Drive's download warning page
+fn find_confirm_token(page: &str) -> Option<String> {
+    let start = page
+        .find("confirm=")
+        .map(|i| i + "confirm=".len())
+        .or_else(|| {
+            let i = page.find("name=\"confirm\"")?;
+            let value = page[i..].find("value=\"")?;
+            Some(i + value + "value=\"".len())
+        })?;
+
+    let token: String = page[start..]
+        .chars()
+        .take_while(|c| c.is_ascii_alphanumeric() || *c == '-' || *c == '_')
+        .collect();
+
+    if token.is_empty() {
+        None
+    } else {
+        Some(token)
+    }
 }
 
 #[derive(Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct DriveListResponse {
+    #[serde(default)]
     files: Vec<DriveFile>,
+    next_page_token: Option<String>,
 }
 
 #[derive(Clone, PartialEq, Eq, Default, Serialize, Deserialize, Debug)]
-#[allow(non_snake_case)]
+#[serde(rename_all = "camelCase")]
 pub struct DriveFile {
-    pub kind: String,
-    pub mimeType: String,
+    pub mime_type: String,
     pub id: String,
     pub name: String,
 }
@@ -147,6 +330,177 @@ pub struct DriveFile {
 impl DriveFile {
     /// Returns whether or not this file is a folder
     fn is_folder(&self) -> bool {
-        self.mimeType.eq(MIMETYPE_FOLDER)
+        self.mime_type.eq(MIMETYPE_FOLDER)
+    }
+
+    /// The name, made safe to use as a single path component
+    ///
+    /// Names that would point at the folder itself or its parent are refused.
+    fn safe_name(&self) -> Result<String, SourceError> {
+        let name = self.name.replace(['/', '\\'], "_");
+
+        if matches!(name.as_str(), "" | "." | "..") {
+            return Err(SourceError::Drive {
+                message: format!("\"{}\" can't be used as a file name", self.name),
+            });
+        }
+
+        Ok(name)
     }
-}*/
+}
+
+#[derive(Deserialize)]
+struct DriveErrorResponse {
+    error: DriveErrorBody,
+}
+
+#[derive(Deserialize)]
+struct DriveErrorBody {
+    message: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use mockito::{Matcher, Server};
+
+    use super::*;
+
+    fn in_folder(id: &str) -> Matcher {
+        Matcher::UrlEncoded(
+            "q".into(),
+            format!("'{}' in parents and trashed = false", id),
+        )
+    }
+
+    #[tokio::test]
+    async fn crawls_pages_and_subfolders_and_confirms_large_files() {
+        let mut server = Server::new_async().await;
+
+        // Requests matching several mocks go to the one not hit yet
+        let root = server
+            .mock("GET", "/files")
+            .match_query(in_folder("root"))
+            .with_body(
+                r#"{"files": [
+                    {"id": "lib", "name": "lib", "mimeType": "application/vnd.google-apps.folder"},
+                    {"id": "launcher", "name": "launcher.lua", "mimeType": "text/plain"}
+                ], "nextPageToken": "page2"}"#,
+            )
+            .create_async()
+            .await;
+        let root_page2 = server
+            .mock("GET", "/files")
+            .match_query(Matcher::AllOf(vec![
+                in_folder("root"),
+                Matcher::UrlEncoded("pageToken".into(), "page2".into()),
+            ]))
+            .with_body(r#"{"files": [{"id": "big", "name": "big.lua", "mimeType": "text/plain"}]}"#)
+            .create_async()
+            .await;
+        server
+            .mock("GET", "/files")
+            .match_query(in_folder("lib"))
+            .with_body(
+                r#"{"files": [{"id": "galaxy", "name": "galaxy.lua", "mimeType": "text/plain"}]}"#,
+            )
+            .create_async()
+            .await;
+
+        for (id, body) in [("launcher", "launcher"), ("galaxy", "galaxy")] {
+            server
+                .mock("GET", format!("/files/{}", id).as_str())
+                .match_query(Matcher::UrlEncoded("alt".into(), "media".into()))
+                .with_body(body)
+                .create_async()
+                .await;
+        }
+        server
+            .mock("GET", "/files/big")
+            .match_query(Matcher::UrlEncoded("alt".into(), "media".into()))
+            .with_header("content-type", "text/html; charset=utf-8")
+            .with_body(r#"<a href="/uc?export=download&amp;confirm=t0k_en&amp;id=big">Download anyway</a>"#)
+            .create_async()
+            .await;
+        let confirmed = server
+            .mock("GET", "/files/big")
+            .match_query(Matcher::UrlEncoded("confirm".into(), "t0k_en".into()))
+            .with_body("big")
+            .create_async()
+            .await;
+
+        let destination = tempfile::tempdir().unwrap();
+        let source = GoogleDriveSource::new("root".to_string(), "key".to_string())
+            .with_api_base(&server.url());
+        source.fetch(destination.path()).await.unwrap();
+
+        let read = |path: &str| std::fs::read_to_string(destination.path().join(path)).unwrap();
+        assert_eq!(read("launcher.lua"), "launcher");
+        assert_eq!(read("lib/galaxy.lua"), "galaxy");
+        assert_eq!(read("big.lua"), "big");
+
+        root.assert_async().await;
+        root_page2.assert_async().await;
+        confirmed.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn refuses_names_leaving_the_folder() {
+        let mut server = Server::new_async().await;
+        server
+            .mock("GET", "/files")
+            .match_query(in_folder("root"))
+            .with_body(r#"{"files": [{"id": "up", "name": "..", "mimeType": "application/vnd.google-apps.folder"}]}"#)
+            .create_async()
+            .await;
+
+        let destination = tempfile::tempdir().unwrap();
+        let source = GoogleDriveSource::new("root".to_string(), "key".to_string())
+            .with_api_base(&server.url());
+
+        assert!(matches!(
+            source.fetch(destination.path()).await,
+            Err(SourceError::Drive { .. })
+        ));
+    }
+
+    #[test]
+    fn safe_names() {
+        let file = |name: &str| DriveFile {
+            name: name.to_string(),
+            ..Default::default()
+        };
+
+        assert_eq!(file("a/b\\c.lua").safe_name().unwrap(), "a_b_c.lua");
+        for name in ["", ".", ".."] {
+            assert!(file(name).safe_name().is_err());
+        }
+    }
+
+    #[test]
+    fn finds_confirm_tokens() {
+        assert_eq!(
+            find_confirm_token(r#"href="/uc?export=download&confirm=ab-C_1&id=x""#).as_deref(),
+            Some("ab-C_1")
+        );
+        assert_eq!(
+            find_confirm_token(r#"<input type="hidden" name="confirm" value="t">"#).as_deref(),
+            Some("t")
+        );
+        assert_eq!(find_confirm_token("<html></html>"), None);
+    }
+
+    #[test]
+    fn folder_ids_from_links() {
+        assert_eq!(
+            GoogleDriveSource::folder_id_from_url(
+                "https://drive.google.com/drive/folders/1a-B_c?usp=sharing"
+            )
+            .as_deref(),
+            Some("1a-B_c")
+        );
+        assert_eq!(
+            GoogleDriveSource::folder_id_from_url("https://example.com/folders/x"),
+            None
+        );
+    }
+}
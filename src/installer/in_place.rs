@@ -0,0 +1,233 @@
+//! Module related to installs made straight into the game folder Steam manages
+//!
+//! Steam puts its own files back when it verifies or updates the game, which
+//! undoes parts of Alloy. Such installs get a marker recording the hash of
+//! every file the installer wrote, so `verify` can tell what was reverted and
+//! `repair` can put it back from the pristine copies `update` keeps.
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    bytecode::LuaFileKind,
+    files::{list_files, path_key, sha256_file},
+    steam::read_build_id,
+    translations::TRANSLATIONS_FOLDER,
+    INSTALLER_FOLDER,
+};
+
+/// The marker, in the installer folder
+pub const MARKER_FILENAME: &str = "in_place.ron";
+
+/// Files outside of daisyMoon and the translations the installer writes
+const WRITTEN_FILES: &[&str] = &["steam_appid.txt"];
+
+/// Left in installs made into the game folder Steam manages
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InPlaceMarker {
+    /// Steam build id of the game when Alloy was installed
+    pub steam_build: Option<String>,
+    /// When Alloy was installed
+    pub installed_at: DateTime<Utc>,
+    /// Path, relative to the install, and the sha256 of every file the installer wrote
+    pub files: BTreeMap<String, String>,
+}
+
+/// What happened to a file the installer wrote
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileState {
+    /// Steam deleted it
+    Missing,
+    /// It is back to the game's own version
+    Reverted,
+    /// It changed into something else, most likely by hand
+    Edited,
+}
+
+impl FileState {
+    /// Whether `repair` puts the file back
+    pub fn is_repairable(&self) -> bool {
+        matches!(self, FileState::Missing | FileState::Reverted)
+    }
+}
+
+fn marker_path(install_dir: &Path) -> PathBuf {
+    install_dir.join(INSTALLER_FOLDER).join(MARKER_FILENAME)
+}
+
+/// Every file the installer wrote, relative to the install
+fn written_files(install_dir: &Path) -> std::io::Result<Vec<String>> {
+    let mut written: Vec<String> = list_files(&install_dir.join("daisyMoon"))?
+        .iter()
+        .map(|relative| path_key(&Path::new("daisyMoon").join(relative)))
+        .collect();
+
+    // Alloy's keys are merged into every language
+    let translations = install_dir.join(TRANSLATIONS_FOLDER);
+    if translations.is_dir() {
+        written.extend(
+            list_files(&translations)?
+                .iter()
+                .filter(|relative| {
+                    relative
+                        .extension()
+                        .is_some_and(|ext| ext == "translations")
+                })
+                .map(|relative| path_key(&Path::new(TRANSLATIONS_FOLDER).join(relative))),
+        );
+    }
+
+    written.extend(
+        WRITTEN_FILES
+            .iter()
+            .filter(|relative| install_dir.join(relative).is_file())
+            .map(|relative| relative.to_string()),
+    );
+
+    Ok(written)
+}
+
+/// Records every file the installer wrote, call once the install is done
+pub fn write_marker(install_dir: &Path) -> std::io::Result<InPlaceMarker> {
+    let mut marker = InPlaceMarker {
+        steam_build: read_build_id(install_dir),
+        installed_at: Utc::now(),
+        files: BTreeMap::new(),
+    };
+
+    for relative in written_files(install_dir)? {
+        let hash = sha256_file(&install_dir.join(&relative))?;
+        marker.files.insert(relative, hash);
+    }
+
+    save_marker(install_dir, &marker)?;
+
+    Ok(marker)
+}
+
+/// Records the files the installer wrote again, after a command changed them
+///
+/// Does nothing for installs that aren't in-place.
+pub fn refresh_marker(install_dir: &Path) -> std::io::Result<()> {
+    let Some(mut marker) = read_marker(install_dir)? else {
+        return Ok(());
+    };
+
+    marker.files.clear();
+    for relative in written_files(install_dir)? {
+        let hash = sha256_file(&install_dir.join(&relative))?;
+        marker.files.insert(relative, hash);
+    }
+
+    save_marker(install_dir, &marker)
+}
+
+/// Writes a marker back after some of its files were repaired
+pub fn save_marker(install_dir: &Path, marker: &InPlaceMarker) -> std::io::Result<()> {
+    let as_string = ron::ser::to_string_pretty(marker, ron::ser::PrettyConfig::default())
+        .expect("Failed to encode in-place marker, this should not happen");
+
+    std::fs::write(marker_path(install_dir), as_string)
+}
+
+/// Reads the marker of an install, `None` if it isn't an in-place install
+pub fn read_marker(install_dir: &Path) -> std::io::Result<Option<InPlaceMarker>> {
+    let path = marker_path(install_dir);
+
+    if !path.exists() {
+        return Ok(None);
+    }
+
+    ron::from_str(&std::fs::read_to_string(path)?)
+        .map(Some)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+}
+
+/// Finds the files that aren't the way the installer left them
+///
+/// `vanilla` is where the game's own version of daisyMoon was kept, if it was.
+pub fn check(
+    install_dir: &Path,
+    marker: &InPlaceMarker,
+    vanilla: Option<&Path>,
+) -> std::io::Result<BTreeMap<String, FileState>> {
+    let mut changed = BTreeMap::new();
+
+    for (relative, hash) in &marker.files {
+        let path = install_dir.join(relative);
+
+        if !path.is_file() {
+            changed.insert(relative.clone(), FileState::Missing);
+            continue;
+        }
+
+        let current = sha256_file(&path)?;
+        if current == *hash {
+            continue;
+        }
+
+        // Steam only ships compiled daisyMoon, a compiled file is always Steam's
+        let compiled = std::fs::read(&path)
+            .map(|bytes| LuaFileKind::from_header(&bytes) != LuaFileKind::Source)
+            .unwrap_or(false);
+
+        let vanilla_hash = vanilla
+            .map(|vanilla| vanilla.join(relative))
+            .filter(|path| path.is_file())
+            .map(|path| sha256_file(&path))
+            .transpose()?;
+
+        // Only Alloy touches the other files, so the game has its own version of them
+        let outside_daisymoon = !relative.starts_with("daisyMoon/");
+
+        let state = if compiled || outside_daisymoon || vanilla_hash.as_ref() == Some(&current) {
+            FileState::Reverted
+        } else {
+            FileState::Edited
+        };
+
+        changed.insert(relative.clone(), state);
+    }
+
+    Ok(changed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn refreshing_records_the_files_as_they_are() {
+        let install = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(install.path().join(INSTALLER_FOLDER)).unwrap();
+        std::fs::create_dir_all(install.path().join("daisyMoon")).unwrap();
+        std::fs::write(install.path().join("daisyMoon/main.lua"), "alloy").unwrap();
+
+        let installed = write_marker(install.path()).unwrap();
+
+        std::fs::write(install.path().join("daisyMoon/main.lua"), "updated").unwrap();
+        std::fs::write(install.path().join("steam_appid.txt"), "1").unwrap();
+        refresh_marker(install.path()).unwrap();
+
+        let refreshed = read_marker(install.path()).unwrap().unwrap();
+        assert_eq!(refreshed.installed_at, installed.installed_at);
+        assert_ne!(
+            refreshed.files["daisyMoon/main.lua"],
+            installed.files["daisyMoon/main.lua"]
+        );
+        assert!(refreshed.files.contains_key("steam_appid.txt"));
+    }
+
+    #[test]
+    fn refreshing_leaves_copies_alone() {
+        let install = tempfile::tempdir().unwrap();
+        refresh_marker(install.path()).unwrap();
+
+        assert!(!marker_path(install.path()).exists());
+    }
+}
@@ -25,7 +25,7 @@ impl InquireGamePathValidator {
             ));
         }
 
-        return Ok(Validation::Valid);
+        Ok(Validation::Valid)
     }
 }
 
@@ -47,7 +47,7 @@ impl InquirePathDoesntExistValidator {
             return Ok(Validation::Valid);
         }
 
-        return Ok(Validation::Invalid("That path already exists".into()));
+        Ok(Validation::Invalid("That path already exists".into()))
     }
 }
 
@@ -69,7 +69,7 @@ impl InquirePathExistsValidator {
             return Ok(Validation::Valid);
         }
 
-        return Ok(Validation::Invalid("That path doesn't exist".into()));
+        Ok(Validation::Invalid("That path doesn't exist".into()))
     }
 }
 
@@ -79,6 +79,34 @@ impl StringValidator for InquirePathExistsValidator {
     }
 }
 
+/// Enquire validator to ensure a daisyMoon source is a URL or a path that exists
+#[derive(Clone)]
+pub struct InquireDaisyMoonSourceValidator {}
+
+impl InquireDaisyMoonSourceValidator {
+    fn validate_source(&self, source: String) -> Result<Validation, CustomUserError> {
+        if source.starts_with("http://") || source.starts_with("https://") {
+            return Ok(Validation::Valid);
+        }
+
+        let pathbuf: PathBuf = source.into();
+
+        if pathbuf.exists() {
+            return Ok(Validation::Valid);
+        }
+
+        Ok(Validation::Invalid(
+            "That path doesn't exist, and isn't a URL".into(),
+        ))
+    }
+}
+
+impl StringValidator for InquireDaisyMoonSourceValidator {
+    fn validate(&self, input: &str) -> Result<Validation, CustomUserError> {
+        self.validate_source(input.trim().to_string())
+    }
+}
+
 #[derive(Clone, Default)]
 /// Provides autocomplete for files
 ///
@@ -0,0 +1,228 @@
+//! Module related to the per-user library of previously imported daisyMoon folders
+//!
+//! Every imported daisyMoon is kept once, keyed by a hash of its contents,
+//! so later installs don't need the user to find it again.
+
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Local, Utc};
+use fs_extra::dir::CopyOptions;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    daisymoon::{Fingerprint, FingerprintReport},
+    errors::LibraryError,
+    files::sha256_hex,
+    user_data::user_data_dir,
+};
+
+/// Folder in the user data folder holding the stored daisyMoon trees
+const LIBRARY_FOLDER: &str = "daisymoon";
+
+/// Index of every stored daisyMoon, inside the library folder
+const INDEX_FILENAME: &str = "sources.ron";
+
+/// A daisyMoon kept in the library
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StoredDaisyMoon {
+    /// Hash of the contents, also the name of its folder in the library
+    pub hash: String,
+    /// Name of the detected release, if it was recognized
+    #[serde(default)]
+    pub label: Option<String>,
+    /// Steam build id of the detected release, if it was recognized
+    #[serde(default)]
+    pub cobalt_build: Option<String>,
+    /// When it was first imported
+    pub imported_at: DateTime<Utc>,
+    /// The folder or zip it was imported from
+    pub imported_from: String,
+    /// How many files it has
+    pub file_count: usize,
+}
+
+impl StoredDaisyMoon {
+    /// The start of the hash, enough to tell entries apart
+    pub fn short_hash(&self) -> &str {
+        &self.hash[..12]
+    }
+
+    /// Where the stored tree is
+    pub fn path(&self) -> PathBuf {
+        library_dir().join(&self.hash)
+    }
+
+    /// One line description for prompts and listings
+    pub fn describe(&self) -> String {
+        let imported = self
+            .imported_at
+            .with_timezone(&Local)
+            .format("%Y-%m-%d %H:%M");
+
+        match &self.label {
+            Some(label) => format!(
+                "daisyMoon for {} imported on {} ({})",
+                label,
+                imported,
+                self.short_hash()
+            ),
+            None => format!(
+                "daisyMoon imported on {} from {} ({})",
+                imported,
+                self.imported_from,
+                self.short_hash()
+            ),
+        }
+    }
+}
+
+/// Returns the folder holding the library
+pub fn library_dir() -> PathBuf {
+    user_data_dir().join(LIBRARY_FOLDER)
+}
+
+/// Computes the content hash of a daisyMoon from its fingerprint
+pub fn content_hash(fingerprint: &Fingerprint) -> String {
+    let listing: String = fingerprint
+        .iter()
+        .map(|(path, hash)| format!("{} {}\n", hash, path))
+        .collect();
+
+    sha256_hex(listing.as_bytes())
+}
+
+/// Reads the library index, empty if nothing was stored yet
+pub fn read_index() -> Result<Vec<StoredDaisyMoon>, LibraryError> {
+    let path = library_dir().join(INDEX_FILENAME);
+
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+
+    Ok(ron::from_str(&std::fs::read_to_string(path)?)?)
+}
+
+fn write_index(index: &[StoredDaisyMoon]) -> Result<(), LibraryError> {
+    std::fs::create_dir_all(library_dir())?;
+
+    let as_string = ron::ser::to_string_pretty(index, ron::ser::PrettyConfig::default())?;
+    std::fs::write(library_dir().join(INDEX_FILENAME), as_string)?;
+
+    Ok(())
+}
+
+/// Stores a daisyMoon folder in the library, unless the same contents are already there
+pub fn store(
+    daisy_path: &Path,
+    fingerprint: &Fingerprint,
+    report: &FingerprintReport,
+    imported_from: &str,
+) -> Result<StoredDaisyMoon, LibraryError> {
+    let hash = content_hash(fingerprint);
+    let mut index = read_index()?;
+
+    if let Some(existing) = index.iter().find(|stored| stored.hash == hash) {
+        return Ok(existing.clone());
+    }
+
+    let stored = StoredDaisyMoon {
+        hash,
+        label: report.release.as_ref().map(|release| release.label.clone()),
+        cobalt_build: report
+            .release
+            .as_ref()
+            .map(|release| release.cobalt_build.clone()),
+        imported_at: Utc::now(),
+        imported_from: imported_from.to_string(),
+        file_count: fingerprint.len(),
+    };
+
+    std::fs::create_dir_all(stored.path())?;
+
+    let options = CopyOptions {
+        copy_inside: true,
+        content_only: true,
+        overwrite: true, // A previous store might have been interrupted
+        ..Default::default()
+    };
+    fs_extra::dir::copy(daisy_path, stored.path(), &options)?;
+
+    index.push(stored.clone());
+    write_index(&index)?;
+
+    Ok(stored)
+}
+
+/// Finds a stored daisyMoon by the start of its hash
+pub fn find(id: &str) -> Result<StoredDaisyMoon, LibraryError> {
+    let index = read_index()?;
+    let mut matches = index.iter().filter(|stored| stored.hash.starts_with(id));
+
+    match (matches.next(), matches.next()) {
+        (Some(stored), None) => Ok(stored.clone()),
+        (Some(_), Some(_)) => Err(LibraryError::Ambiguous { id: id.to_string() }),
+        (None, _) => Err(LibraryError::NotFound { id: id.to_string() }),
+    }
+}
+
+/// Removes a stored daisyMoon by the start of its hash
+pub fn remove(id: &str) -> Result<StoredDaisyMoon, LibraryError> {
+    let stored = find(id)?;
+
+    if stored.path().exists() {
+        std::fs::remove_dir_all(stored.path())?;
+    }
+
+    let mut index = read_index()?;
+    index.retain(|other| other.hash != stored.hash);
+    write_index(&index)?;
+
+    Ok(stored)
+}
+
+/// Copies a stored daisyMoon into a daisyMoon folder
+pub fn copy_into(stored: &StoredDaisyMoon, daisy_path: &Path) -> Result<(), LibraryError> {
+    std::fs::create_dir_all(daisy_path)?;
+
+    let options = CopyOptions {
+        copy_inside: true,
+        content_only: true,
+        overwrite: true,
+        ..Default::default()
+    };
+    fs_extra::dir::copy(stored.path(), daisy_path, &options)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ENTRY: &str = r#"(
+        hash: "0123456789abcdef0123456789abcdef",
+        imported_at: "2024-01-01T00:00:00Z",
+        imported_from: "daisyMoon.zip",
+        file_count: 2,
+    )"#;
+
+    #[test]
+    fn entries_without_a_release_still_decode() {
+        let stored: StoredDaisyMoon = ron::from_str(ENTRY).unwrap();
+
+        assert!(stored.label.is_none());
+        assert!(stored
+            .describe()
+            .contains("from daisyMoon.zip (0123456789ab)"));
+    }
+
+    #[test]
+    fn describes_the_detected_release() {
+        let mut stored: StoredDaisyMoon = ron::from_str(ENTRY).unwrap();
+        stored.label = Some("Cobalt 1.0 (Steam)".to_string());
+
+        assert!(stored
+            .describe()
+            .starts_with("daisyMoon for Cobalt 1.0 (Steam) imported on"));
+    }
+}
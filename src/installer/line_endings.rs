@@ -0,0 +1,485 @@
+//! Module related to making the files Alloy's diff touches match the diff's line endings
+//!
+//! Everything works on bytes, so Lua files that aren't valid UTF-8 (e.g. Latin-1) are fine.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::{Component, Path},
+};
+
+use serde::{Deserialize, Serialize};
+
+use super::{alloy::SAVED_DIFF_NAME, files::sha256_hex, INSTALLER_FOLDER};
+
+/// Where the original line endings of every touched file are recorded
+pub const LINE_ENDINGS_FILENAME: &str = "line_endings.ron";
+
+const UTF8_BOM: &[u8] = b"\xef\xbb\xbf";
+const UTF16_LE_BOM: &[u8] = b"\xff\xfe";
+const UTF16_BE_BOM: &[u8] = b"\xfe\xff";
+
+/// The line endings a file uses
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LineEnding {
+    /// `\n` only
+    Lf,
+    /// `\r\n` only
+    Crlf,
+    /// Both, which can't be put back by converting
+    Mixed,
+    /// No line breaks at all
+    None,
+}
+
+impl LineEnding {
+    /// Detects the line endings of some bytes
+    pub fn detect(bytes: &[u8]) -> LineEnding {
+        let mut lf = false;
+        let mut crlf = false;
+
+        for (i, byte) in bytes.iter().enumerate() {
+            if *byte == b'\n' {
+                if i > 0 && bytes[i - 1] == b'\r' {
+                    crlf = true;
+                } else {
+                    lf = true;
+                }
+            }
+        }
+
+        match (lf, crlf) {
+            (true, true) => LineEnding::Mixed,
+            (true, false) => LineEnding::Lf,
+            (false, true) => LineEnding::Crlf,
+            (false, false) => LineEnding::None,
+        }
+    }
+
+    /// Converts every line ending in `bytes` to this one, `Mixed` and `None` leave them alone
+    pub fn apply(&self, bytes: &[u8]) -> Vec<u8> {
+        let mut converted = Vec::with_capacity(bytes.len() + bytes.len() / 32);
+
+        for (i, byte) in bytes.iter().enumerate() {
+            match (self, byte) {
+                (LineEnding::Lf, b'\r') if bytes.get(i + 1) == Some(&b'\n') => continue,
+                (LineEnding::Crlf, b'\n') if i == 0 || bytes[i - 1] != b'\r' => {
+                    converted.push(b'\r');
+                }
+                _ => {}
+            }
+            converted.push(*byte);
+        }
+
+        converted
+    }
+}
+
+/// What a file looked like before it was normalized
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OriginalFormat {
+    /// The line endings it had
+    pub line_ending: LineEnding,
+    /// Whether it started with a UTF-8 byte order mark, which was stripped for patching
+    pub bom: bool,
+    /// Hash of the normalized contents, to tell whether patch changed the file
+    pub normalized_hash: String,
+}
+
+/// Files normalized before patching, so they can be put back afterwards
+pub struct NormalizedFiles {
+    /// Path relative to the install -> how it looked
+    pub formats: BTreeMap<String, OriginalFormat>,
+    /// Original contents, so files patch didn't change can be put back byte for byte
+    originals: BTreeMap<String, Vec<u8>>,
+}
+
+/// A file of a unified diff, with the hunks changing it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiffFile {
+    /// Path on the `---` line, `/dev/null` when the diff creates the file
+    pub old: String,
+    /// Path on the `+++` line, `/dev/null` when the diff deletes the file
+    pub new: String,
+    /// First line and line count of every hunk, as numbered before patching
+    pub hunks: Vec<(usize, usize)>,
+}
+
+/// The `start,count` of one side of a hunk header, the count being 1 when left out
+fn hunk_range(range: &str) -> Option<(usize, usize)> {
+    let mut numbers = range.split(',').map(str::parse::<usize>);
+    let start = numbers.next()?.ok()?;
+    let count = match numbers.next() {
+        Some(count) => count.ok()?,
+        None => 1,
+    };
+
+    Some((start, count))
+}
+
+/// Reads the files and hunks of a unified diff
+///
+/// Headers are only looked for between hunks, as a removed `-- comment` line
+/// of Lua looks just like one inside them.
+pub fn parse_diff(diff: &[u8]) -> Vec<DiffFile> {
+    let lines: Vec<String> = diff
+        .split(|byte| *byte == b'\n')
+        .map(|line| String::from_utf8_lossy(line.strip_suffix(b"\r").unwrap_or(line)).to_string())
+        .collect();
+
+    // The path ends at a tab, which is followed by a timestamp
+    let path = |rest: &str| rest.split('\t').next().unwrap_or(rest).trim().to_string();
+
+    let mut files: Vec<DiffFile> = Vec::new();
+    let mut old_left: usize = 0;
+    let mut new_left: usize = 0;
+    let mut i = 0;
+
+    while i < lines.len() {
+        let line = &lines[i];
+        i += 1;
+
+        if old_left > 0 || new_left > 0 {
+            match line.chars().next() {
+                Some('-') => old_left = old_left.saturating_sub(1),
+                Some('+') => new_left = new_left.saturating_sub(1),
+                Some('\\') => {}
+                // Context, some tools drop the space of empty lines
+                _ => {
+                    old_left = old_left.saturating_sub(1);
+                    new_left = new_left.saturating_sub(1);
+                }
+            }
+            continue;
+        }
+
+        if let Some(old) = line.strip_prefix("--- ") {
+            if let Some(new) = lines.get(i).and_then(|next| next.strip_prefix("+++ ")) {
+                files.push(DiffFile {
+                    old: path(old),
+                    new: path(new),
+                    hunks: Vec::new(),
+                });
+                i += 1;
+            }
+        } else if let Some(rest) = line.strip_prefix("@@ -") {
+            let mut ranges = rest.split(' ');
+            let old = ranges.next().and_then(hunk_range);
+            let new = ranges
+                .next()
+                .and_then(|range| range.strip_prefix('+'))
+                .and_then(hunk_range);
+
+            if let (Some(file), Some(old), Some(new)) = (files.last_mut(), old, new) {
+                file.hunks.push(old);
+                old_left = old.1;
+                new_left = new.1;
+            }
+        }
+    }
+
+    files
+}
+
+/// Lists the files a unified diff touches, relative to where patch runs with `-p0`
+pub fn diff_targets(diff: &[u8]) -> BTreeSet<String> {
+    parse_diff(diff)
+        .into_iter()
+        .flat_map(|file| [file.old, file.new])
+        .filter(|path| !path.is_empty() && path != "/dev/null")
+        .collect()
+}
+
+/// Whether a path from a diff is plainly relative, so it can't leave the folder the diff is applied in
+pub fn stays_inside(target: &str) -> bool {
+    Path::new(target)
+        .components()
+        .all(|component| matches!(component, Component::Normal(_)))
+}
+
+/// Gives `bytes` the line endings and UTF-8 byte order mark of `like`
+pub fn in_format_of(bytes: &[u8], like: &[u8]) -> Vec<u8> {
+    let contents = bytes.strip_prefix(UTF8_BOM).unwrap_or(bytes);
+    let (bom, like) = match like.strip_prefix(UTF8_BOM) {
+        Some(rest) => (UTF8_BOM, rest),
+        None => (&[][..], like),
+    };
+
+    let mut formatted = bom.to_vec();
+    formatted.extend(LineEnding::detect(like).apply(contents));
+    formatted
+}
+
+/// A diff as `normalize_for_patch` saves it, with `target` line endings and no byte order mark
+pub fn normalize_diff(diff: &[u8], target: LineEnding) -> Vec<u8> {
+    target.apply(diff.strip_prefix(UTF8_BOM).unwrap_or(diff))
+}
+
+/// Converts the diff and every existing file it touches to `target` line endings,
+/// stripping UTF-8 byte order marks, and records how they looked before
+pub fn normalize_for_patch(
+    base_path: &Path,
+    target: LineEnding,
+) -> std::io::Result<NormalizedFiles> {
+    let diff_path = base_path.join(INSTALLER_FOLDER).join(SAVED_DIFF_NAME);
+
+    let diff = normalize_diff(&std::fs::read(&diff_path)?, target);
+
+    if let Some(outside) = diff_targets(&diff)
+        .into_iter()
+        .find(|path| !stays_inside(path))
+    {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("the diff changes {}, which is outside the install", outside),
+        ));
+    }
+
+    std::fs::write(&diff_path, &diff)?;
+
+    let mut normalized = NormalizedFiles {
+        formats: BTreeMap::new(),
+        originals: BTreeMap::new(),
+    };
+
+    for relative in diff_targets(&diff) {
+        let path = base_path.join(&relative);
+
+        if !path.is_file() {
+            continue; // Files the diff creates
+        }
+
+        let original = std::fs::read(&path)?;
+
+        if original.starts_with(UTF16_LE_BOM) || original.starts_with(UTF16_BE_BOM) {
+            println!("{} is UTF-16, leaving it alone.", relative);
+            continue;
+        }
+
+        let bom = original.starts_with(UTF8_BOM);
+        let contents = if bom {
+            &original[UTF8_BOM.len()..]
+        } else {
+            &original[..]
+        };
+
+        let converted = target.apply(contents);
+
+        normalized.formats.insert(
+            relative.clone(),
+            OriginalFormat {
+                line_ending: LineEnding::detect(contents),
+                bom,
+                normalized_hash: sha256_hex(&converted),
+            },
+        );
+
+        if converted != original {
+            std::fs::write(&path, converted)?;
+        }
+
+        normalized.originals.insert(relative, original);
+    }
+
+    let as_string =
+        ron::ser::to_string_pretty(&normalized.formats, ron::ser::PrettyConfig::default())
+            .expect("Failed to encode line endings, this should not happen");
+    std::fs::write(
+        base_path.join(INSTALLER_FOLDER).join(LINE_ENDINGS_FILENAME),
+        as_string,
+    )?;
+
+    Ok(normalized)
+}
+
+/// Puts byte order marks back after patching, and with `restore_line_endings`, the original line endings too
+///
+/// Files patch didn't change become byte-identical to what they were.
+pub fn restore_after_patch(
+    base_path: &Path,
+    normalized: &NormalizedFiles,
+    restore_line_endings: bool,
+) -> std::io::Result<()> {
+    for (relative, format) in &normalized.formats {
+        let path = base_path.join(relative);
+
+        if !path.is_file() {
+            continue;
+        }
+
+        let patched = std::fs::read(&path)?;
+
+        if restore_line_endings && sha256_hex(&patched) == format.normalized_hash {
+            std::fs::write(&path, &normalized.originals[relative])?;
+            continue;
+        }
+
+        if !format.bom && !restore_line_endings {
+            continue;
+        }
+
+        let mut restored = Vec::with_capacity(patched.len() + UTF8_BOM.len());
+
+        if format.bom {
+            restored.extend_from_slice(UTF8_BOM);
+        }
+
+        if restore_line_endings {
+            restored.extend(format.line_ending.apply(&patched));
+        } else {
+            restored.extend(patched);
+        }
+
+        std::fs::write(&path, restored)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::installer::merge::unified_diff;
+
+    #[test]
+    fn removed_lua_comments_arent_file_headers() {
+        let old = b"-- header\nlocal a = 1\nreturn a\n";
+        let new = b"local a = 1\nreturn a\n";
+        let diff = unified_diff("daisyMoon/a.lua", Some(old), Some(new));
+
+        assert!(String::from_utf8_lossy(&diff).contains("\n--- header\n"));
+        assert_eq!(
+            diff_targets(&diff),
+            BTreeSet::from(["daisyMoon/a.lua".to_string()])
+        );
+    }
+
+    #[test]
+    fn takes_the_format_of_another_file() {
+        let like = b"\xef\xbb\xbfa\r\nb\r\n";
+
+        assert_eq!(
+            in_format_of(b"a\nb\nc\n", like),
+            b"\xef\xbb\xbfa\r\nb\r\nc\r\n"
+        );
+        assert_eq!(in_format_of(b"\xef\xbb\xbfa\r\n", b"a\n"), b"a\n");
+    }
+
+    #[test]
+    fn reads_every_file_and_hunk() {
+        let diff = b"--- daisyMoon/a.lua\t2024-01-01\r\n\
++++ daisyMoon/a.lua\t2024-01-01\r\n\
+@@ -1,2 +1,2 @@\r\n\
+-a\r\n\
++b\r\n\
+ c\r\n\
+@@ -10 +10,0 @@\r\n\
+--- old comment\r\n\
+--- /dev/null\r\n\
++++ daisyMoon/new.lua\r\n\
+@@ -0,0 +1 @@\r\n\
++++ not a header\r\n\
+\\ No newline at end of file\r\n";
+
+        assert_eq!(
+            parse_diff(diff),
+            vec![
+                DiffFile {
+                    old: "daisyMoon/a.lua".to_string(),
+                    new: "daisyMoon/a.lua".to_string(),
+                    hunks: vec![(1, 2), (10, 1)],
+                },
+                DiffFile {
+                    old: "/dev/null".to_string(),
+                    new: "daisyMoon/new.lua".to_string(),
+                    hunks: vec![(0, 0)],
+                },
+            ]
+        );
+        assert_eq!(
+            diff_targets(diff),
+            BTreeSet::from([
+                "daisyMoon/a.lua".to_string(),
+                "daisyMoon/new.lua".to_string()
+            ])
+        );
+    }
+
+    /// An install whose diff touches a BOM + CRLF, an LF and a mixed file
+    fn install_with_formats() -> (tempfile::TempDir, Vec<(&'static str, Vec<u8>)>) {
+        let install = tempfile::tempdir().unwrap();
+        let files = vec![
+            (
+                "daisyMoon/bom_crlf.lua",
+                b"\xef\xbb\xbfa = 1\r\nb = 2\r\n".to_vec(),
+            ),
+            ("daisyMoon/lf.lua", b"a = 1\nb = 2\n".to_vec()),
+            ("daisyMoon/mixed.lua", b"a = 1\r\nb = 2\nc = 3\r\n".to_vec()),
+        ];
+
+        std::fs::create_dir_all(install.path().join("daisyMoon")).unwrap();
+        std::fs::create_dir_all(install.path().join(INSTALLER_FOLDER)).unwrap();
+
+        let mut diff = Vec::new();
+        for (path, contents) in &files {
+            std::fs::write(install.path().join(path), contents).unwrap();
+            diff.extend(unified_diff(path, Some(b"a = 1\n"), Some(b"a = 2\n")));
+        }
+        std::fs::write(
+            install.path().join(INSTALLER_FOLDER).join(SAVED_DIFF_NAME),
+            diff,
+        )
+        .unwrap();
+
+        (install, files)
+    }
+
+    #[test]
+    fn unchanged_files_come_back_byte_for_byte() {
+        let (install, files) = install_with_formats();
+
+        let normalized = normalize_for_patch(install.path(), LineEnding::Lf).unwrap();
+        for (path, _) in &files {
+            let bytes = std::fs::read(install.path().join(path)).unwrap();
+            assert!(!bytes.starts_with(UTF8_BOM), "{}", path);
+            assert_eq!(LineEnding::detect(&bytes), LineEnding::Lf, "{}", path);
+        }
+
+        restore_after_patch(install.path(), &normalized, true).unwrap();
+        for (path, contents) in &files {
+            assert_eq!(&std::fs::read(install.path().join(path)).unwrap(), contents);
+        }
+    }
+
+    #[test]
+    fn patched_files_get_their_format_back() {
+        let (install, _) = install_with_formats();
+
+        let normalized = normalize_for_patch(install.path(), LineEnding::Lf).unwrap();
+        std::fs::write(
+            install.path().join("daisyMoon/bom_crlf.lua"),
+            b"a = 2\nb = 2\n",
+        )
+        .unwrap();
+        restore_after_patch(install.path(), &normalized, true).unwrap();
+
+        assert_eq!(
+            std::fs::read(install.path().join("daisyMoon/bom_crlf.lua")).unwrap(),
+            b"\xef\xbb\xbfa = 2\r\nb = 2\r\n"
+        );
+    }
+
+    #[test]
+    fn diffs_leaving_the_install_are_refused() {
+        let install = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(install.path().join(INSTALLER_FOLDER)).unwrap();
+        std::fs::write(
+            install.path().join(INSTALLER_FOLDER).join(SAVED_DIFF_NAME),
+            unified_diff("../outside.lua", Some(b"a\n"), Some(b"b\n")),
+        )
+        .unwrap();
+
+        assert!(normalize_for_patch(install.path(), LineEnding::Lf).is_err());
+        assert!(!stays_inside("/etc/passwd"));
+        assert!(stays_inside("daisyMoon/a.lua"));
+    }
+}
@@ -0,0 +1,347 @@
+//! Module related to `alloy.lock`, the exact state of an install to make it again elsewhere
+//!
+//! A lock pins Alloy's diff and translations by their sha256, the daisyMoon
+//! they were applied to by its content hash, like the daisyMoon library, and
+//! every mod by its version and a hash of what it installed. Everything that
+//! can be is checked before an install from a lock changes anything.
+
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use super::{
+    alloy::{download_diff, fetch_translations, ALLOY_VERSION},
+    download_cache::fetch_verified,
+    errors::{LockError, LockProblem, ModError},
+    files::{sha256_file, sha256_hex},
+    line_endings::normalize_diff,
+    metadata::InstallMetadata,
+    mod_index::{fetch_indexed, find_versions, LoadedIndex},
+    mods::{unpack, ModPackage},
+    platform::TargetPlatform,
+    INSTALLER_FOLDER,
+};
+
+/// The name locks are written with by default
+pub const LOCK_FILENAME: &str = "alloy.lock";
+
+/// Everything needed to make an install again, the contents of `alloy.lock`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AlloyLock {
+    pub alloy_version: String,
+    pub target: TargetPlatform,
+    /// sha256 of Alloy's diff, as saved in the installer folder
+    pub diff_sha256: String,
+    /// sha256 of Alloy's translations
+    pub translations_sha256: String,
+    /// Content hash of daisyMoon before Alloy, as the daisyMoon library lists it
+    pub daisymoon_hash: String,
+    /// Every mod, in the order their patches are stacked on daisyMoon
+    pub mods: Vec<LockedMod>,
+}
+
+/// A mod of a lock
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LockedMod {
+    pub id: String,
+    pub version: String,
+    /// Hash of the files and patches the mod installed
+    pub sha256: String,
+    #[serde(default)]
+    pub disabled: bool,
+    /// Where the package was, when a mod index had this version
+    #[serde(default)]
+    pub url: Option<String>,
+    /// The sha256 of that package
+    #[serde(default)]
+    pub package_sha256: Option<String>,
+}
+
+/// Reads a lock
+pub fn read_lock(path: &Path) -> Result<AlloyLock, LockError> {
+    Ok(ron::from_str(&std::fs::read_to_string(path)?)?)
+}
+
+/// Writes a lock
+pub fn write_lock(path: &Path, lock: &AlloyLock) -> Result<(), LockError> {
+    let as_string = ron::ser::to_string_pretty(lock, ron::ser::PrettyConfig::default())
+        .expect("Failed to encode a lock, this should not happen");
+    std::fs::write(path, as_string)?;
+
+    Ok(())
+}
+
+/// Describes an install as a lock, finding where its mods are in the indexes
+pub fn from_install(
+    install_dir: &Path,
+    metadata: &InstallMetadata,
+    indexes: &[LoadedIndex],
+) -> Result<AlloyLock, LockError> {
+    let translations_sha256 =
+        metadata
+            .translations_sha256
+            .clone()
+            .ok_or_else(|| LockError::Unrecorded {
+                what: "which translations of Alloy it merged".to_string(),
+                fix: "update or reinstall it".to_string(),
+            })?;
+    let daisymoon_hash = metadata
+        .daisymoon_hash
+        .clone()
+        .ok_or_else(|| LockError::Unrecorded {
+            what: "which daisyMoon it was made from".to_string(),
+            fix: "reinstall it".to_string(),
+        })?;
+
+    let diff_path = install_dir.join(INSTALLER_FOLDER).join(&metadata.diff_file);
+    if !diff_path.is_file() {
+        return Err(LockError::Unrecorded {
+            what: "Alloy's diff".to_string(),
+            fix: "update or reinstall it".to_string(),
+        });
+    }
+    let diff_sha256 = sha256_file(&diff_path)?;
+
+    // Mods without patches keep their place after the ones with
+    let mut installed: Vec<_> = metadata.mods.iter().collect();
+    installed.sort_by_key(|installed| {
+        metadata
+            .patch_stack
+            .iter()
+            .position(|applied| applied.id == installed.id)
+            .unwrap_or(usize::MAX)
+    });
+
+    let mods = installed
+        .into_iter()
+        .map(|installed| {
+            let found = find_versions(indexes, &installed.id)
+                .into_iter()
+                .find(|found| found.version.version == installed.version);
+
+            LockedMod {
+                id: installed.id.clone(),
+                version: installed.version.clone(),
+                sha256: installed.content_hash(),
+                disabled: installed.disabled,
+                url: found.map(|found| found.index.package_location(found.version)),
+                package_sha256: found.map(|found| found.version.sha256.clone()),
+            }
+        })
+        .collect();
+
+    Ok(AlloyLock {
+        alloy_version: metadata.alloy_version.clone(),
+        target: metadata.target,
+        diff_sha256,
+        translations_sha256,
+        daisymoon_hash,
+        mods,
+    })
+}
+
+/// Checks this installer downloads the Alloy a lock has
+///
+/// `target` is the one asked for, if any.
+pub async fn check_alloy(lock: &AlloyLock, target: Option<TargetPlatform>) -> Vec<LockProblem> {
+    let mut problems = Vec::new();
+
+    if lock.alloy_version != ALLOY_VERSION {
+        problems.push(LockProblem::AlloyVersion {
+            locked: lock.alloy_version.clone(),
+            available: ALLOY_VERSION.to_string(),
+        });
+    }
+
+    if let Some(asked) = target.filter(|asked| *asked != lock.target) {
+        problems.push(LockProblem::Target {
+            locked: lock.target,
+            asked,
+        });
+    }
+
+    let client = reqwest::Client::new();
+
+    match download_diff(&client, lock.target).await {
+        Ok(diff) => {
+            let found = sha256_hex(&normalize_diff(&diff, lock.target.line_ending()));
+            if found != lock.diff_sha256 {
+                problems.push(LockProblem::Changed {
+                    what: "Alloy's diff".to_string(),
+                    expected: lock.diff_sha256.clone(),
+                    found,
+                });
+            }
+        }
+        Err(e) => problems.push(LockProblem::Download {
+            what: "Alloy's diff".to_string(),
+            reason: e.to_string(),
+        }),
+    }
+
+    match fetch_translations(&client).await {
+        Ok(translations) => {
+            let found = sha256_hex(translations.as_bytes());
+            if found != lock.translations_sha256 {
+                problems.push(LockProblem::Changed {
+                    what: "Alloy's translations".to_string(),
+                    expected: lock.translations_sha256.clone(),
+                    found,
+                });
+            }
+        }
+        Err(e) => problems.push(LockProblem::Download {
+            what: "Alloy's translations".to_string(),
+            reason: e.to_string(),
+        }),
+    }
+
+    problems
+}
+
+/// Gets the package of a locked mod, from where the lock says or the mod indexes
+async fn fetch_locked(
+    locked: &LockedMod,
+    indexes: &[LoadedIndex],
+) -> Result<ModPackage, LockProblem> {
+    let fetch_failed = |e: ModError| LockProblem::ModFetch {
+        id: locked.id.clone(),
+        version: locked.version.clone(),
+        reason: e.to_string(),
+    };
+
+    let package = match (&locked.url, &locked.package_sha256) {
+        (Some(url), Some(sha256)) => {
+            let bytes = fetch_verified(url, sha256)
+                .await
+                .map_err(|e| fetch_failed(e.into()))?;
+            unpack(&bytes, url).map_err(fetch_failed)?
+        }
+        _ => {
+            let found = find_versions(indexes, &locked.id)
+                .into_iter()
+                .find(|found| found.version.version == locked.version)
+                .ok_or_else(|| LockProblem::ModUnavailable {
+                    id: locked.id.clone(),
+                    version: locked.version.clone(),
+                })?;
+            fetch_indexed(found).await.map_err(fetch_failed)?
+        }
+    };
+
+    let manifest = &package.manifest;
+    if manifest.id != locked.id || manifest.version != locked.version {
+        return Err(LockProblem::ModMismatch {
+            id: locked.id.clone(),
+            version: locked.version.clone(),
+            found: format!("{} {}", manifest.id, manifest.version),
+        });
+    }
+
+    let found = package.content_hash().map_err(fetch_failed)?;
+    if found != locked.sha256 {
+        return Err(LockProblem::ModChanged {
+            id: locked.id.clone(),
+            version: locked.version.clone(),
+            expected: locked.sha256.clone(),
+            found,
+        });
+    }
+
+    Ok(package)
+}
+
+/// Gets the package of every mod of a lock, in order, checking each is what was locked
+pub async fn fetch_mods(
+    lock: &AlloyLock,
+    indexes: &[LoadedIndex],
+) -> Result<Vec<ModPackage>, Vec<LockProblem>> {
+    let mut packages = Vec::new();
+    let mut problems = Vec::new();
+
+    for locked in &lock.mods {
+        match fetch_locked(locked, indexes).await {
+            Ok(package) => packages.push(package),
+            Err(problem) => problems.push(problem),
+        }
+    }
+
+    match problems.is_empty() {
+        true => Ok(packages),
+        false => Err(problems),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::installer::{mod_patches::AppliedPatch, mods::InstalledMod};
+
+    fn installed(id: &str, patched: bool) -> InstalledMod {
+        InstalledMod {
+            id: id.to_string(),
+            name: id.to_string(),
+            version: "1.0.0".to_string(),
+            author: "someone".to_string(),
+            files: Default::default(),
+            depends: Default::default(),
+            conflicts: Default::default(),
+            disabled: false,
+            patches: match patched {
+                true => vec![patch(id)],
+                false => Vec::new(),
+            },
+            patch_regions: Vec::new(),
+        }
+    }
+
+    fn patch(id: &str) -> AppliedPatch {
+        AppliedPatch {
+            id: id.to_string(),
+            name: "change.diff".to_string(),
+            path: format!("{}/mod_patches/{}/change.diff", INSTALLER_FOLDER, id),
+            sha256: id.to_string(),
+        }
+    }
+
+    #[test]
+    fn locks_mods_in_patch_stack_order() {
+        let install = tempfile::tempdir().unwrap();
+        let metadata = InstallMetadata {
+            translations_sha256: Some("translations".to_string()),
+            daisymoon_hash: Some("daisymoon".to_string()),
+            mods: vec![
+                installed("first", true),
+                installed("plain", false),
+                installed("second", true),
+            ],
+            patch_stack: vec![patch("second"), patch("first")],
+            ..Default::default()
+        };
+        std::fs::create_dir_all(install.path().join(INSTALLER_FOLDER)).unwrap();
+        std::fs::write(
+            install
+                .path()
+                .join(INSTALLER_FOLDER)
+                .join(&metadata.diff_file),
+            "diff",
+        )
+        .unwrap();
+
+        let lock = from_install(install.path(), &metadata, &[]).unwrap();
+        let ids: Vec<&str> = lock.mods.iter().map(|locked| locked.id.as_str()).collect();
+
+        assert_eq!(ids, vec!["second", "first", "plain"]);
+    }
+
+    #[test]
+    fn needs_the_translations_recorded() {
+        let install = tempfile::tempdir().unwrap();
+        let metadata = InstallMetadata::default();
+
+        assert!(matches!(
+            from_install(install.path(), &metadata, &[]),
+            Err(LockError::Unrecorded { .. })
+        ));
+    }
+}
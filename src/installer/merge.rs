@@ -0,0 +1,461 @@
+//! Module related to merging text files three ways, like `diff3 -m`
+//!
+//! Lines are compared without their line endings, so a file whose line
+//! endings were put back after patching still merges cleanly. Two versions of a
+//! file can also be written as a unified diff, like `diff -u`.
+
+use super::line_endings::LineEnding;
+
+/// The result of merging one file
+#[derive(Debug, Clone)]
+pub struct MergedFile {
+    /// The merged contents, with conflict markers where both sides changed the same lines
+    pub contents: Vec<u8>,
+    /// How many conflicting regions there are
+    pub conflicts: usize,
+}
+
+/// Labels written on the conflict markers
+pub struct MergeLabels<'a> {
+    /// The side with the user's changes
+    pub ours: &'a str,
+    /// The side with the new version
+    pub theirs: &'a str,
+}
+
+/// Splits a file into lines without their line endings, and whether it ended with one
+fn split_lines(bytes: &[u8]) -> (Vec<&[u8]>, bool) {
+    if bytes.is_empty() {
+        return (Vec::new(), false);
+    }
+
+    let mut lines: Vec<&[u8]> = bytes
+        .split(|byte| *byte == b'\n')
+        .map(|line| line.strip_suffix(b"\r").unwrap_or(line))
+        .collect();
+
+    let trailing = bytes.ends_with(b"\n");
+    if trailing {
+        lines.pop();
+    }
+
+    (lines, trailing)
+}
+
+/// Finds a longest common subsequence of `a` and `b` with Myers' algorithm,
+/// as pairs of (index in `a`, index in `b`)
+fn matching_lines(a: &[&[u8]], b: &[&[u8]]) -> Vec<(usize, usize)> {
+    // Common starts and ends are cheap and usually most of the file
+    let prefix = a.iter().zip(b).take_while(|(x, y)| x == y).count();
+    let suffix = a[prefix..]
+        .iter()
+        .rev()
+        .zip(b[prefix..].iter().rev())
+        .take_while(|(x, y)| x == y)
+        .count();
+
+    let a_middle = &a[prefix..a.len() - suffix];
+    let b_middle = &b[prefix..b.len() - suffix];
+
+    let mut pairs: Vec<(usize, usize)> = (0..prefix).map(|i| (i, i)).collect();
+    pairs.extend(
+        myers(a_middle, b_middle)
+            .into_iter()
+            .map(|(x, y)| (x + prefix, y + prefix)),
+    );
+    pairs.extend((0..suffix).map(|i| (a.len() - suffix + i, b.len() - suffix + i)));
+
+    pairs
+}
+
+fn myers(a: &[&[u8]], b: &[&[u8]]) -> Vec<(usize, usize)> {
+    let n = a.len() as isize;
+    let m = b.len() as isize;
+    let max = n + m;
+
+    if max == 0 {
+        return Vec::new();
+    }
+
+    let offset = max + 1;
+    let mut v = vec![0isize; 2 * max as usize + 3];
+    // v as it was before each round, only the part that round can read
+    let mut trace: Vec<Vec<isize>> = Vec::new();
+
+    'rounds: for d in 0..=max {
+        trace.push(v[(offset - d - 1) as usize..=(offset + d + 1) as usize].to_vec());
+
+        for k in (-d..=d).step_by(2) {
+            let index = (offset + k) as usize;
+            let mut x = if k == -d || (k != d && v[index - 1] < v[index + 1]) {
+                v[index + 1]
+            } else {
+                v[index - 1] + 1
+            };
+            let mut y = x - k;
+
+            while x < n && y < m && a[x as usize] == b[y as usize] {
+                x += 1;
+                y += 1;
+            }
+
+            v[index] = x;
+
+            if x >= n && y >= m {
+                break 'rounds;
+            }
+        }
+    }
+
+    let mut pairs = Vec::new();
+    let (mut x, mut y) = (n, m);
+
+    for d in (0..trace.len() as isize).rev() {
+        let round = &trace[d as usize];
+        let at = |k: isize| round[(k + d + 1) as usize];
+
+        let k = x - y;
+        let previous_k = if k == -d || (k != d && at(k - 1) < at(k + 1)) {
+            k + 1
+        } else {
+            k - 1
+        };
+        let previous_x = at(previous_k);
+        let previous_y = previous_x - previous_k;
+
+        while x > previous_x && y > previous_y {
+            x -= 1;
+            y -= 1;
+            pairs.push((x as usize, y as usize));
+        }
+
+        if d > 0 {
+            x = previous_x;
+            y = previous_y;
+        }
+    }
+
+    pairs.reverse();
+    pairs
+}
+
+/// For every line of `base`, the line of `other` it matches, if any
+fn match_map(base: &[&[u8]], other: &[&[u8]]) -> Vec<Option<usize>> {
+    let mut map = vec![None; base.len()];
+
+    for (base_index, other_index) in matching_lines(base, other) {
+        map[base_index] = Some(other_index);
+    }
+
+    map
+}
+
+/// Merges the changes from `base` to `ours` and from `base` to `theirs`.
+///
+/// The result uses the line endings of `ours`.
+pub fn merge3(base: &[u8], ours: &[u8], theirs: &[u8], labels: &MergeLabels) -> MergedFile {
+    let (base_lines, _) = split_lines(base);
+    let (our_lines, our_trailing) = split_lines(ours);
+    let (their_lines, their_trailing) = split_lines(theirs);
+
+    let ours_map = match_map(&base_lines, &our_lines);
+    let theirs_map = match_map(&base_lines, &their_lines);
+
+    let mut lines: Vec<&[u8]> = Vec::new();
+    let mut conflicts = 0;
+
+    let marker_start = format!("<<<<<<< {}", labels.ours).into_bytes();
+    let marker_middle = b"=======".to_vec();
+    let marker_end = format!(">>>>>>> {}", labels.theirs).into_bytes();
+
+    let (mut o, mut a, mut b) = (0, 0, 0);
+
+    loop {
+        // The next base line both sides kept
+        let stable = (o..base_lines.len())
+            .find_map(|i| Some((i, ours_map[i]?, theirs_map[i]?)))
+            .unwrap_or((base_lines.len(), our_lines.len(), their_lines.len()));
+
+        if stable == (o, a, b) {
+            if o == base_lines.len() {
+                break;
+            }
+
+            lines.push(base_lines[o]);
+            (o, a, b) = (o + 1, a + 1, b + 1);
+            continue;
+        }
+
+        let (o_end, a_end, b_end) = stable;
+        let base_chunk = &base_lines[o..o_end];
+        let our_chunk = &our_lines[a..a_end];
+        let their_chunk = &their_lines[b..b_end];
+
+        if our_chunk == base_chunk || our_chunk == their_chunk {
+            lines.extend_from_slice(their_chunk);
+        } else if their_chunk == base_chunk {
+            lines.extend_from_slice(our_chunk);
+        } else {
+            conflicts += 1;
+            lines.push(&marker_start);
+            lines.extend_from_slice(our_chunk);
+            lines.push(&marker_middle);
+            lines.extend_from_slice(their_chunk);
+            lines.push(&marker_end);
+        }
+
+        (o, a, b) = stable;
+    }
+
+    let line_ending: &[u8] = match LineEnding::detect(ours) {
+        LineEnding::Crlf => b"\r\n",
+        _ => b"\n",
+    };
+
+    let mut contents = lines.join(line_ending);
+    if !lines.is_empty() && (our_trailing || their_trailing) {
+        contents.extend_from_slice(line_ending);
+    }
+
+    MergedFile {
+        contents,
+        conflicts,
+    }
+}
+
+/// Lines of context around every change in a unified diff
+const DIFF_CONTEXT: usize = 3;
+
+/// A line of a diff, as its index in the old or new lines
+#[derive(Debug, Clone, Copy)]
+enum Edit {
+    Same(usize),
+    Removed(usize),
+    Added(usize),
+}
+
+/// The `start,count` of a hunk side, the count being left out when it's 1
+fn hunk_range(start: usize, count: usize) -> String {
+    match count {
+        1 => start.to_string(),
+        _ => format!("{},{}", start, count),
+    }
+}
+
+/// Writes the changes from `old` to `new` as a unified diff of `path`, empty if there are none
+///
+/// A missing side is `/dev/null`, for files created or deleted. Lines end with `\n`.
+pub fn unified_diff(path: &str, old: Option<&[u8]>, new: Option<&[u8]>) -> Vec<u8> {
+    let (old_lines, old_trailing) = split_lines(old.unwrap_or_default());
+    let (new_lines, new_trailing) = split_lines(new.unwrap_or_default());
+
+    let mut pairs = matching_lines(&old_lines, &new_lines);
+    // The last lines differ when only one of them ends with a line break
+    if old_trailing != new_trailing
+        && pairs.last()
+            == Some(&(
+                old_lines.len().wrapping_sub(1),
+                new_lines.len().wrapping_sub(1),
+            ))
+    {
+        pairs.pop();
+    }
+
+    let mut edits = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    for (x, y) in pairs
+        .into_iter()
+        .chain([(old_lines.len(), new_lines.len())])
+    {
+        edits.extend((i..x).map(Edit::Removed));
+        edits.extend((j..y).map(Edit::Added));
+        if x < old_lines.len() {
+            edits.push(Edit::Same(x));
+        }
+        (i, j) = (x + 1, y + 1);
+    }
+
+    // Edits that are changes or close enough to one to be context
+    let mut kept = vec![false; edits.len()];
+    for (k, edit) in edits.iter().enumerate() {
+        if !matches!(edit, Edit::Same(_)) {
+            let end = (k + DIFF_CONTEXT + 1).min(edits.len());
+            kept[k.saturating_sub(DIFF_CONTEXT)..end].fill(true);
+        }
+    }
+
+    if !kept.contains(&true) && old.is_some() == new.is_some() {
+        return Vec::new();
+    }
+
+    let mut diff = format!(
+        "--- {}\n+++ {}\n",
+        old.map_or("/dev/null", |_| path),
+        new.map_or("/dev/null", |_| path)
+    )
+    .into_bytes();
+
+    // Old and new lines before each edit
+    let (mut old_at, mut new_at) = (0, 0);
+    let mut k = 0;
+    while k < edits.len() {
+        if !kept[k] {
+            match edits[k] {
+                Edit::Same(_) => (old_at, new_at) = (old_at + 1, new_at + 1),
+                Edit::Removed(_) => old_at += 1,
+                Edit::Added(_) => new_at += 1,
+            }
+            k += 1;
+            continue;
+        }
+
+        let end = (k..edits.len()).find(|k| !kept[*k]).unwrap_or(edits.len());
+        let hunk = &edits[k..end];
+
+        let old_count = hunk
+            .iter()
+            .filter(|edit| !matches!(edit, Edit::Added(_)))
+            .count();
+        let new_count = hunk
+            .iter()
+            .filter(|edit| !matches!(edit, Edit::Removed(_)))
+            .count();
+        // Empty sides start at the line before, 0 for an empty file
+        let old_start = old_at + usize::from(old_count > 0);
+        let new_start = new_at + usize::from(new_count > 0);
+
+        diff.extend_from_slice(
+            format!(
+                "@@ -{} +{} @@\n",
+                hunk_range(old_start, old_count),
+                hunk_range(new_start, new_count)
+            )
+            .as_bytes(),
+        );
+
+        for edit in hunk {
+            let (prefix, line, last) = match *edit {
+                Edit::Same(x) => (
+                    b' ',
+                    old_lines[x],
+                    x + 1 == old_lines.len() && !old_trailing,
+                ),
+                Edit::Removed(x) => (
+                    b'-',
+                    old_lines[x],
+                    x + 1 == old_lines.len() && !old_trailing,
+                ),
+                Edit::Added(y) => (
+                    b'+',
+                    new_lines[y],
+                    y + 1 == new_lines.len() && !new_trailing,
+                ),
+            };
+
+            diff.push(prefix);
+            diff.extend_from_slice(line);
+            diff.push(b'\n');
+            if last {
+                diff.extend_from_slice(b"\\ No newline at end of file\n");
+            }
+        }
+
+        old_at += old_count;
+        new_at += new_count;
+        k = end;
+    }
+
+    diff
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LABELS: MergeLabels = MergeLabels {
+        ours: "yours",
+        theirs: "new",
+    };
+
+    fn merged(base: &str, ours: &str, theirs: &str) -> (String, usize) {
+        let merged = merge3(base.as_bytes(), ours.as_bytes(), theirs.as_bytes(), &LABELS);
+        (
+            String::from_utf8(merged.contents).unwrap(),
+            merged.conflicts,
+        )
+    }
+
+    #[test]
+    fn finds_a_longest_common_subsequence() {
+        let a: Vec<&[u8]> = ["a", "b", "c", "a", "b", "b", "a"]
+            .iter()
+            .map(|line| line.as_bytes())
+            .collect();
+        let b: Vec<&[u8]> = ["c", "b", "a", "b", "a", "c"]
+            .iter()
+            .map(|line| line.as_bytes())
+            .collect();
+
+        let pairs = matching_lines(&a, &b);
+
+        assert_eq!(pairs.len(), 4);
+        for (x, y) in &pairs {
+            assert_eq!(a[*x], b[*y]);
+        }
+        for window in pairs.windows(2) {
+            assert!(window[0].0 < window[1].0 && window[0].1 < window[1].1);
+        }
+    }
+
+    #[test]
+    fn merges_separate_regions() {
+        let base = "a\nb\nc\nd\ne\n";
+
+        assert_eq!(
+            merged(base, "A\nb\nc\nd\ne\n", "a\nb\nc\nd\nE\n"),
+            ("A\nb\nc\nd\nE\n".to_string(), 0)
+        );
+    }
+
+    #[test]
+    fn marks_conflicts() {
+        assert_eq!(
+            merged("a\nb\nc\n", "a\nours\nc\n", "a\ntheirs\nc\n"),
+            (
+                "a\n<<<<<<< yours\nours\n=======\ntheirs\n>>>>>>> new\nc\n".to_string(),
+                1
+            )
+        );
+    }
+
+    #[test]
+    fn deleting_lines_the_other_side_edited_conflicts() {
+        assert_eq!(
+            merged("a\nb\nc\n", "a\nc\n", "a\nB\nc\n"),
+            (
+                "a\n<<<<<<< yours\n=======\nB\n>>>>>>> new\nc\n".to_string(),
+                1
+            )
+        );
+    }
+
+    #[test]
+    fn keeps_missing_trailing_line_breaks() {
+        assert_eq!(
+            merged("a\nb", "A\nb", "a\nb\nc"),
+            ("A\nb\nc".to_string(), 0)
+        );
+        assert_eq!(
+            merged("a\nb\n", "A\nb", "a\nb\nc\n"),
+            ("A\nb\nc\n".to_string(), 0)
+        );
+    }
+
+    #[test]
+    fn merges_crlf_with_lf() {
+        assert_eq!(
+            merged("a\nb\nc\n", "A\r\nb\r\nc\r\n", "a\nb\nC\n"),
+            ("A\r\nb\r\nC\r\n".to_string(), 0)
+        );
+    }
+}
@@ -1,13 +1,15 @@
 //! Module related to metadata left behind for future updating
 
-use std::path::PathBuf;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
 
+use chrono::{DateTime, Utc};
 use serde::{Serialize, Deserialize};
 
 use crate::installer::windows::exit_or_windows;
-use super::{alloy::SAVED_DIFF_NAME, INSTALLER_FOLDER};
+use super::{alloy::{ALLOY_VERSION, SAVED_DIFF_NAME}, copy::CopyStrategy, mod_patches::AppliedPatch, mods::InstalledMod, platform::TargetPlatform, INSTALLER_FOLDER};
 
-const METADATA_FILENAME: &str = "install_metadata.ron";
+pub const METADATA_FILENAME: &str = "install_metadata.ron";
 
 /// Data left for future versions to update
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -18,23 +20,84 @@ pub struct InstallMetadata {
     pub installer_version: String,
     /// The filename of the diff file used, must be in the same folder as this metadata file
     pub diff_file: String, 
+    /// Steam build id of the Cobalt version the imported daisyMoon was identified as, if known
+    #[serde(default)]
+    pub daisymoon_build: Option<String>,
+    /// Content hash of daisyMoon before Alloy was applied, unknown for older installs
+    #[serde(default)]
+    pub daisymoon_hash: Option<String>,
+    /// sha256 of Alloy's translations last merged, unknown for older installs
+    #[serde(default)]
+    pub translations_sha256: Option<String>,
+    /// The platform the install was made for, older installs were always made for the installer's
+    #[serde(default)]
+    pub target: TargetPlatform,
+    /// How the game was copied for this install, `None` if Alloy was installed into the game itself
+    #[serde(default)]
+    pub copy_strategy: Option<CopyStrategy>,
+    /// The Cobalt folder the install was made from
+    #[serde(default)]
+    pub source_game: Option<PathBuf>,
+    /// Steam build id of the game when it was copied or last synced, if Steam manages it
+    #[serde(default)]
+    pub source_build: Option<String>,
+    /// When the install was made, unknown for older installs
+    #[serde(default)]
+    pub created_at: Option<DateTime<Utc>>,
+    /// The mods installed into `alloys`
+    #[serde(default)]
+    pub mods: Vec<InstalledMod>,
+    /// Named sets of enabled mods
+    #[serde(default)]
+    pub mod_profiles: BTreeMap<String, BTreeSet<String>>,
+    /// The profile last switched to or saved
+    #[serde(default)]
+    pub active_profile: Option<String>,
+    /// The mod patches applied to daisyMoon after Alloy's diff, in the order they were applied
+    #[serde(default)]
+    pub patch_stack: Vec<AppliedPatch>,
 }
 
 impl InstallMetadata {
     /// Creates install metadata valid for this version of the installer.
     pub fn new() -> InstallMetadata {
         let installer_version = env!("CARGO_PKG_VERSION").to_string();
-        Self { alloy_version: "0.0.4".to_string(), installer_version, diff_file: SAVED_DIFF_NAME.to_string() }
+        Self {
+            alloy_version: ALLOY_VERSION.to_string(),
+            installer_version,
+            diff_file: SAVED_DIFF_NAME.to_string(),
+            daisymoon_build: None,
+            daisymoon_hash: None,
+            translations_sha256: None,
+            target: TargetPlatform::host(),
+            copy_strategy: None,
+            source_game: None,
+            source_build: None,
+            created_at: Some(Utc::now()),
+            mods: Vec::new(),
+            mod_profiles: BTreeMap::new(),
+            active_profile: None,
+            patch_stack: Vec::new(),
+        }
     }
 }
 
-/// Writes the metadata into the installer folder
-pub fn write_metadata(base_path: PathBuf) {
-    let meta = InstallMetadata::new();
+impl Default for InstallMetadata {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
-    let as_string = ron::to_string(&meta).expect("Failed to encode metadata, this should not happen");
+/// Returns where the metadata of an install is
+pub fn metadata_path(base_path: &Path) -> PathBuf {
+    base_path.join(INSTALLER_FOLDER).join(METADATA_FILENAME)
+}
 
-    if let Err(e) = std::fs::write(base_path.join(INSTALLER_FOLDER).join(METADATA_FILENAME), as_string) {
+/// Writes the metadata into the installer folder
+pub fn write_metadata(base_path: PathBuf, meta: &InstallMetadata) {
+    let as_string = ron::to_string(meta).expect("Failed to encode metadata, this should not happen");
+
+    if let Err(e) = std::fs::write(metadata_path(&base_path), as_string) {
         println!("Failed to write install metadata: {}", e);
         exit_or_windows(100);
     }
@@ -43,9 +106,7 @@ pub fn write_metadata(base_path: PathBuf) {
 /// Reads the metadata from the installer folder
 pub fn read_metadata(base_path: PathBuf) -> Option<InstallMetadata> {
     
-    let path = base_path.join(INSTALLER_FOLDER).join(METADATA_FILENAME);
-    
-    let as_string = std::fs::read_to_string(path).ok()?;
+    let as_string = std::fs::read_to_string(metadata_path(&base_path)).ok()?;
 
-    ron::from_str(&as_string).expect("Failed to decode install metadata, this should not happen")
+    Some(ron::from_str(&as_string).expect("Failed to decode install metadata, this should not happen"))
 }
@@ -1,9 +1,40 @@
 pub mod alloy;
+pub mod artwork;
+pub mod backup;
+pub mod bytecode;
 pub mod cobalt;
+pub mod copy;
+pub mod daisymoon;
+pub mod daisymoon_source;
+pub mod desktop;
+pub mod download_cache;
+pub mod errors;
+pub mod exe_icon;
+pub mod files;
 pub mod gnuwin32;
 pub mod google_drive;
+pub mod in_place;
 pub mod inquire;
+pub mod library;
+pub mod line_endings;
+pub mod lock;
+pub mod merge;
+pub mod mod_authoring;
+pub mod mod_index;
+pub mod mod_patches;
+pub mod mod_plan;
+pub mod mod_profiles;
+pub mod mods;
+pub mod platform;
+pub mod registry;
+pub mod runner;
+pub mod shortcuts;
 pub mod steam;
+pub mod sync;
+pub mod translations;
+pub mod update;
+pub mod user_data;
+pub mod vdf;
 pub mod windows;
 pub mod metadata;
 
@@ -0,0 +1,237 @@
+//! Module related to making mods: new packages, diffs of daisyMoon and zips to share
+//!
+//! Diffs compare daisyMoon with what the installer recorded it left there, the
+//! files mod patches made first, then Alloy's, then the game's own. Their paths
+//! are from the install's folder, like the diffs mods ship. The recorded files
+//! are compared in the format the current ones have, as some were kept while
+//! normalized for patching.
+
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use zip::{write::FileOptions, CompressionMethod, ZipWriter};
+
+use super::{
+    alloy::ALLOY_VERSION,
+    errors::ModError,
+    files::{list_files, path_key, sha256_hex},
+    line_endings::in_format_of,
+    merge::unified_diff,
+    mods::{unpack, ModManifest, ModPackage, MANIFEST_FILENAME},
+    update::{has_pristine, installed_version, kept_files},
+};
+
+/// Files patch leaves behind, never part of a diff
+const PATCH_LEFTOVERS: &[&str] = &["orig", "rej"];
+
+/// A diff of daisyMoon
+#[derive(Debug, Clone, Default)]
+pub struct DaisyDiff {
+    /// The unified diff of every changed file
+    pub diff: Vec<u8>,
+    /// Files in the diff, relative to the install
+    pub changed: Vec<String>,
+    /// Changed files that aren't text, left out of the diff
+    pub binary: Vec<String>,
+}
+
+/// A package zipped by `pack`
+#[derive(Debug, Clone)]
+pub struct PackedMod {
+    pub manifest: ModManifest,
+    pub path: PathBuf,
+    /// The sha256 of the zip, as mod indexes list it
+    pub sha256: String,
+    /// Path in the zip -> sha256 of every file packed
+    pub files: BTreeMap<String, String>,
+}
+
+/// Starts a package in `folder`, with a manifest and an empty Lua file
+pub fn scaffold(
+    folder: &Path,
+    id: &str,
+    name: &str,
+    author: &str,
+) -> Result<ModManifest, ModError> {
+    if folder.exists() && std::fs::read_dir(folder)?.next().is_some() {
+        return Err(ModError::NotEmpty {
+            path: folder.display().to_string(),
+        });
+    }
+
+    let main_file = format!("{}/init.lua", id);
+    let manifest = ModManifest {
+        id: id.to_string(),
+        name: name.to_string(),
+        version: "0.1.0".to_string(),
+        author: author.to_string(),
+        alloy_versions: vec![format!("^{}", ALLOY_VERSION)],
+        files: vec![main_file.clone()],
+        depends: Default::default(),
+        conflicts: Default::default(),
+        patches: Vec::new(),
+    };
+    manifest.validate()?;
+
+    std::fs::create_dir_all(folder.join(id))?;
+    std::fs::write(folder.join(&main_file), format!("-- {}\n", name))?;
+
+    let as_string = ron::ser::to_string_pretty(&manifest, ron::ser::PrettyConfig::default())
+        .expect("Failed to encode a mod manifest, this should not happen");
+    std::fs::write(folder.join(MANIFEST_FILENAME), as_string)?;
+
+    Ok(manifest)
+}
+
+/// Diffs daisyMoon against what the installer left there
+///
+/// `only` limits the diff to files and folders, relative to the install.
+pub fn diff_daisy(install_dir: &Path, only: &[String]) -> Result<DaisyDiff, ModError> {
+    if !has_pristine(install_dir) {
+        return Err(ModError::NoPristine);
+    }
+
+    let mut relatives = kept_files(install_dir)?;
+    relatives.extend(
+        list_files(&install_dir.join("daisyMoon"))?
+            .iter()
+            .map(|relative| path_key(&Path::new("daisyMoon").join(relative))),
+    );
+
+    let only: Vec<String> = only.iter().map(|path| path_key(Path::new(path))).collect();
+    let wanted = |relative: &String| {
+        only.is_empty()
+            || only.iter().any(|path| {
+                relative == path
+                    || relative.starts_with(&format!("{}/", path.trim_end_matches('/')))
+            })
+    };
+
+    let mut daisy_diff = DaisyDiff::default();
+    for relative in relatives.iter().filter(|relative| wanted(relative)) {
+        let leftover = Path::new(relative)
+            .extension()
+            .is_some_and(|extension| PATCH_LEFTOVERS.iter().any(|left| extension == *left));
+        if leftover {
+            continue;
+        }
+
+        let current = match install_dir.join(relative) {
+            path if path.is_file() => Some(std::fs::read(path)?),
+            _ => None,
+        };
+        let recorded = match installed_version(install_dir, relative) {
+            // Only what changed counts, not the format patching left it in
+            Some(path) => {
+                let bytes = std::fs::read(path)?;
+                Some(match &current {
+                    Some(current) => in_format_of(&bytes, current),
+                    None => bytes,
+                })
+            }
+            None => None,
+        };
+
+        if recorded == current {
+            continue;
+        }
+
+        let binary = [&recorded, &current]
+            .iter()
+            .any(|contents| contents.as_ref().is_some_and(|bytes| bytes.contains(&0)));
+        if binary {
+            daisy_diff.binary.push(relative.clone());
+            continue;
+        }
+
+        daisy_diff.diff.extend(unified_diff(
+            relative,
+            recorded.as_deref(),
+            current.as_deref(),
+        ));
+        daisy_diff.changed.push(relative.clone());
+    }
+
+    Ok(daisy_diff)
+}
+
+/// Checks the package in `folder` and zips it, by default as `<id>-<version>.zip` here
+///
+/// The zip only has the manifest, the files and the patches, and is read back
+/// the way `mod install` reads it before being kept.
+pub fn pack(folder: &Path, output: Option<&Path>) -> Result<PackedMod, ModError> {
+    let package = ModPackage::open(folder)?;
+    let manifest = package.manifest.clone();
+
+    let path = match output {
+        Some(output) => output.to_path_buf(),
+        None => PathBuf::from(format!("{}-{}.zip", manifest.id, manifest.version)),
+    };
+
+    let mut packed = vec![MANIFEST_FILENAME.to_string()];
+    packed.extend(manifest.files.iter().cloned());
+    packed.extend(manifest.patches.iter().cloned());
+
+    let mut bytes = Vec::new();
+    let mut files = BTreeMap::new();
+    {
+        let mut zip = ZipWriter::new(std::io::Cursor::new(&mut bytes));
+        let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+
+        for relative in packed {
+            let contents = std::fs::read(package.root.join(&relative))?;
+            let key = path_key(Path::new(&relative));
+
+            zip.start_file(key.as_str(), options)?;
+            zip.write_all(&contents)?;
+            files.insert(key, sha256_hex(&contents));
+        }
+
+        zip.finish()?;
+    }
+
+    unpack(&bytes, &path.display().to_string())?;
+
+    File::create(&path)?.write_all(&bytes)?;
+
+    Ok(PackedMod {
+        manifest,
+        path,
+        sha256: sha256_hex(&bytes),
+        files,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::installer::{update::pristine_dir, INSTALLER_FOLDER};
+
+    #[test]
+    fn diffs_ignore_the_format_patching_left() {
+        let install = tempfile::tempdir().unwrap();
+        let pristine = pristine_dir(install.path());
+        std::fs::create_dir_all(pristine.join("vanilla")).unwrap();
+        std::fs::create_dir_all(pristine.join("base/daisyMoon")).unwrap();
+        std::fs::create_dir_all(install.path().join("daisyMoon")).unwrap();
+        assert!(install.path().join(INSTALLER_FOLDER).is_dir());
+
+        // Kept while normalized, then given its byte order mark and CRLF back
+        std::fs::write(pristine.join("base/daisyMoon/a.lua"), "a\nb\n").unwrap();
+        let path = install.path().join("daisyMoon/a.lua");
+        std::fs::write(&path, "\u{feff}a\r\nb\r\n").unwrap();
+
+        assert!(diff_daisy(install.path(), &[]).unwrap().changed.is_empty());
+
+        std::fs::write(&path, "\u{feff}a\r\nc\r\n").unwrap();
+        let diff = diff_daisy(install.path(), &[]).unwrap();
+
+        assert_eq!(diff.changed, vec!["daisyMoon/a.lua"]);
+        let text = String::from_utf8_lossy(&diff.diff);
+        assert!(text.contains("-b\n+c\n"));
+    }
+}
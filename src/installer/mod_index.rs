@@ -0,0 +1,362 @@
+//! Module related to mod indexes, lists of mods with where to download each version
+//!
+//! Indexes are JSON or RON files read from a URL or a local path. The ones used
+//! are listed one per line in `mod_indexes.txt` in the user data folder.
+//! Download locations in an index can be relative to the index itself.
+
+use std::path::{Path, PathBuf};
+
+use semver::Version;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    download_cache::fetch_verified,
+    errors::{ModError, ModIndexError},
+    mods::{unpack, version_matches, ModPackage, Requirements},
+    user_data::user_data_dir,
+};
+
+/// The indexes used, in the user data folder
+const INDEXES_FILENAME: &str = "mod_indexes.txt";
+
+/// The contents of an index file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModIndex {
+    pub mods: Vec<IndexedMod>,
+}
+
+/// A mod listed in an index
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IndexedMod {
+    pub id: String,
+    pub name: String,
+    pub author: String,
+    #[serde(default)]
+    pub description: String,
+    pub versions: Vec<IndexedVersion>,
+}
+
+/// A version of a mod listed in an index, the rest being like in its manifest
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IndexedVersion {
+    pub version: String,
+    /// Where the zipped package is, a URL or a path, relative to the index or not
+    pub url: String,
+    /// The sha256 of the zip
+    pub sha256: String,
+    pub alloy_versions: Vec<String>,
+    #[serde(default)]
+    pub depends: Requirements,
+    #[serde(default)]
+    pub conflicts: Requirements,
+}
+
+impl IndexedVersion {
+    /// Whether this version works with an Alloy version
+    pub fn supports(&self, alloy_version: &str) -> bool {
+        self.alloy_versions
+            .iter()
+            .any(|requirement| version_matches(requirement, alloy_version))
+    }
+}
+
+/// An index that was read, with where it came from
+#[derive(Debug, Clone)]
+pub struct LoadedIndex {
+    pub location: String,
+    pub index: ModIndex,
+}
+
+impl LoadedIndex {
+    /// Where the package of a version is
+    pub fn package_location(&self, version: &IndexedVersion) -> String {
+        if is_url(&self.location) {
+            return reqwest::Url::parse(&self.location)
+                .and_then(|base| base.join(&version.url))
+                .map(|url| url.to_string())
+                .unwrap_or_else(|_| version.url.clone());
+        }
+
+        if is_url(&version.url) {
+            return version.url.clone();
+        }
+
+        Path::new(&self.location)
+            .parent()
+            .unwrap_or(Path::new(""))
+            .join(&version.url)
+            .display()
+            .to_string()
+    }
+}
+
+/// A version found in the indexes
+#[derive(Debug, Clone, Copy)]
+pub struct Found<'a> {
+    pub index: &'a LoadedIndex,
+    pub listed: &'a IndexedMod,
+    pub version: &'a IndexedVersion,
+}
+
+fn is_url(location: &str) -> bool {
+    location.starts_with("http://") || location.starts_with("https://")
+}
+
+/// Where the list of indexes is
+pub fn indexes_path() -> PathBuf {
+    user_data_dir().join(INDEXES_FILENAME)
+}
+
+/// The indexes used, none if the list doesn't exist yet
+pub fn configured_indexes() -> std::io::Result<Vec<String>> {
+    match std::fs::read_to_string(indexes_path()) {
+        Ok(list) => Ok(list
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(str::to_string)
+            .collect()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(e),
+    }
+}
+
+fn write_indexes(indexes: &[String]) -> std::io::Result<()> {
+    std::fs::create_dir_all(user_data_dir())?;
+
+    let mut list = indexes.join("\n");
+    list.push('\n');
+
+    std::fs::write(indexes_path(), list)
+}
+
+/// Adds an index to the list, returns whether it wasn't there already
+///
+/// Local paths are made absolute so the list works from anywhere.
+pub fn add_index(location: &str) -> std::io::Result<bool> {
+    let location = if is_url(location) {
+        location.to_string()
+    } else {
+        std::path::absolute(location)?.display().to_string()
+    };
+
+    let mut indexes = configured_indexes()?;
+    if indexes.contains(&location) {
+        return Ok(false);
+    }
+
+    indexes.push(location);
+    write_indexes(&indexes)?;
+
+    Ok(true)
+}
+
+/// Removes an index from the list, returns whether it was there
+pub fn remove_index(location: &str) -> std::io::Result<bool> {
+    let absolute = std::path::absolute(location)
+        .map(|path| path.display().to_string())
+        .unwrap_or_default();
+
+    let mut indexes = configured_indexes()?;
+    let count = indexes.len();
+    indexes.retain(|index| index != location && *index != absolute);
+
+    if indexes.len() == count {
+        return Ok(false);
+    }
+
+    write_indexes(&indexes)?;
+
+    Ok(true)
+}
+
+/// Reads an index as JSON or RON, by its extension or else by how it starts
+fn parse_index(location: &str, text: &str) -> Result<ModIndex, ModIndexError> {
+    let path = location.split(['?', '#']).next().unwrap_or(location);
+
+    let json = if path.ends_with(".json") {
+        true
+    } else if path.ends_with(".ron") {
+        false
+    } else {
+        text.trim_start().starts_with('{')
+    };
+
+    if json {
+        Ok(serde_json::from_str(text)?)
+    } else {
+        Ok(ron::from_str(text)?)
+    }
+}
+
+/// Reads the index at a URL or a path
+pub async fn load_index(location: &str) -> Result<LoadedIndex, ModIndexError> {
+    let text = if is_url(location) {
+        let response = reqwest::get(location).await?;
+
+        if !response.status().is_success() {
+            return Err(ModIndexError::Status {
+                url: location.to_string(),
+                status: response.status().as_u16(),
+            });
+        }
+
+        response.text().await?
+    } else {
+        std::fs::read_to_string(location)?
+    };
+
+    Ok(LoadedIndex {
+        location: location.to_string(),
+        index: parse_index(location, &text)?,
+    })
+}
+
+/// Every version of a mod in the indexes
+pub fn find_versions<'a>(indexes: &'a [LoadedIndex], id: &str) -> Vec<Found<'a>> {
+    indexes
+        .iter()
+        .flat_map(|index| {
+            index
+                .index
+                .mods
+                .iter()
+                .filter(move |listed| listed.id == id)
+                .flat_map(move |listed| {
+                    listed.versions.iter().map(move |version| Found {
+                        index,
+                        listed,
+                        version,
+                    })
+                })
+        })
+        .collect()
+}
+
+/// The newest version of a mod matching a requirement, and working with `alloy_version` if given
+pub fn newest<'a>(
+    indexes: &'a [LoadedIndex],
+    id: &str,
+    requirement: &str,
+    alloy_version: Option<&str>,
+) -> Option<Found<'a>> {
+    find_versions(indexes, id)
+        .into_iter()
+        .filter(|found| version_matches(requirement, &found.version.version))
+        .filter(|found| alloy_version.is_none_or(|alloy| found.version.supports(alloy)))
+        .max_by_key(|found| Version::parse(&found.version.version).ok())
+}
+
+/// Downloads the package of a version, through the download cache, and checks it's what the index says
+pub async fn fetch_indexed(found: Found<'_>) -> Result<ModPackage, ModError> {
+    let location = found.index.package_location(found.version);
+    let bytes = fetch_verified(&location, &found.version.sha256).await?;
+    let package = unpack(&bytes, &location)?;
+
+    let expected = format!("{} {}", found.listed.id, found.version.version);
+    let actual = format!("{} {}", package.manifest.id, package.manifest.version);
+    if expected != actual {
+        return Err(ModError::IndexMismatch {
+            location,
+            expected,
+            found: actual,
+        });
+    }
+
+    Ok(package)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const JSON_INDEX: &str = r#"{
+        "mods": [{
+            "id": "better_ships",
+            "name": "Better Ships",
+            "author": "someone",
+            "versions": [
+                {"version": "1.0.0", "url": "packages/better_ships-1.0.0.zip", "sha256": "aa", "alloy_versions": ["0.0.3"]},
+                {"version": "1.1.0", "url": "packages/better_ships-1.1.0.zip", "sha256": "bb", "alloy_versions": ["0.0.4"]},
+                {"version": "2.0.0", "url": "https://mods.example/better_ships-2.0.0.zip", "sha256": "cc", "alloy_versions": [">=0.0.5"]}
+            ]
+        }]
+    }"#;
+
+    const RON_INDEX: &str = r#"(
+        mods: [(
+            id: "quiet_menus",
+            name: "Quiet Menus",
+            author: "someone else",
+            versions: [(
+                version: "0.1.0",
+                url: "quiet_menus.zip",
+                sha256: "dd",
+                alloy_versions: ["*"],
+            )],
+        )],
+    )"#;
+
+    #[tokio::test]
+    async fn loads_json_and_ron_indexes_from_paths() {
+        let folder = tempfile::tempdir().unwrap();
+        let json = folder.path().join("index.json");
+        let ron = folder.path().join("index.ron");
+        std::fs::write(&json, JSON_INDEX).unwrap();
+        std::fs::write(&ron, RON_INDEX).unwrap();
+
+        let json = load_index(&json.display().to_string()).await.unwrap();
+        assert_eq!(json.index.mods[0].versions.len(), 3);
+
+        let ron = load_index(&ron.display().to_string()).await.unwrap();
+        assert_eq!(ron.index.mods[0].id, "quiet_menus");
+    }
+
+    #[test]
+    fn package_locations_are_relative_to_the_index() {
+        let index = LoadedIndex {
+            location: "/srv/mods/index.json".to_string(),
+            index: parse_index("index.json", JSON_INDEX).unwrap(),
+        };
+        let versions = &index.index.mods[0].versions;
+
+        assert_eq!(
+            index.package_location(&versions[0]),
+            Path::new("/srv/mods")
+                .join("packages/better_ships-1.0.0.zip")
+                .display()
+                .to_string()
+        );
+        assert_eq!(
+            index.package_location(&versions[2]),
+            "https://mods.example/better_ships-2.0.0.zip"
+        );
+
+        let served = LoadedIndex {
+            location: "https://mods.example/alloy/index.json".to_string(),
+            ..index.clone()
+        };
+        assert_eq!(
+            served.package_location(&versions[0]),
+            "https://mods.example/alloy/packages/better_ships-1.0.0.zip"
+        );
+    }
+
+    #[test]
+    fn newest_only_picks_versions_for_the_alloy_version() {
+        let indexes = [LoadedIndex {
+            location: "index.json".to_string(),
+            index: parse_index("index.json", JSON_INDEX).unwrap(),
+        }];
+
+        let pick = |alloy: Option<&str>| {
+            newest(&indexes, "better_ships", "*", alloy).map(|found| &found.version.version)
+        };
+
+        assert_eq!(pick(None).unwrap(), "2.0.0");
+        assert_eq!(pick(Some("0.0.4")).unwrap(), "1.1.0");
+        assert_eq!(pick(Some("0.0.3")).unwrap(), "1.0.0");
+        assert!(pick(Some("0.0.1")).is_none());
+        assert!(newest(&indexes, "better_ships", "^1.0.0", Some("0.0.5")).is_none());
+    }
+}
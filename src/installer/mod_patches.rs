@@ -0,0 +1,397 @@
+//! Module related to mods that patch daisyMoon with their own diffs
+//!
+//! A package can list unified diffs (`-p0`, from the install's folder) that
+//! change daisyMoon. They're kept in the installer folder and applied in order
+//! after Alloy's own diff, the applied ones being recorded as a stack in the
+//! install's metadata. Changing the stack takes patches off the top, newest
+//! first, down to what stays, then applies the new ones.
+
+use std::{
+    collections::BTreeSet,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use super::{
+    alloy::patch_strictly,
+    errors::ModError,
+    files::{path_key, sha256_file, sha256_hex},
+    in_place::{read_marker, save_marker},
+    line_endings::{diff_targets, parse_diff, stays_inside, LineEnding},
+    metadata::InstallMetadata,
+    mods::ModPackage,
+    platform::TargetPlatform,
+    update::modded_dir,
+    INSTALLER_FOLDER,
+};
+
+/// Folder in the installer folder the patches of installed mods are kept in
+pub const PATCHES_FOLDER: &str = "mod_patches";
+
+/// The lines of a file a hunk changes, as numbered before patching
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HunkRegion {
+    /// Path relative to the install
+    pub file: String,
+    /// First line
+    pub start: usize,
+    /// Line after the last one
+    pub end: usize,
+}
+
+/// A patch of an installed mod
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AppliedPatch {
+    /// The mod it's from
+    pub id: String,
+    /// Its path in the mod's package
+    pub name: String,
+    /// Where it's kept, relative to the install
+    pub path: String,
+    /// The sha256 of the diff in the package
+    pub sha256: String,
+}
+
+/// The regions every hunk of a diff changes
+pub fn hunk_regions(diff: &[u8]) -> Vec<HunkRegion> {
+    parse_diff(diff)
+        .into_iter()
+        .flat_map(|file| {
+            // Files the diff deletes are only named on the old side
+            let name = match file.new.as_str() {
+                "/dev/null" => file.old,
+                _ => file.new,
+            };
+
+            file.hunks
+                .into_iter()
+                .map(move |(start, count)| HunkRegion {
+                    file: name.clone(),
+                    start,
+                    // Hunks only adding lines still touch where they add them
+                    end: start + count.max(1),
+                })
+        })
+        .collect()
+}
+
+/// The first file and line two sets of regions both change, if any
+pub fn first_overlap(regions: &[HunkRegion], others: &[HunkRegion]) -> Option<(String, usize)> {
+    regions.iter().find_map(|region| {
+        others
+            .iter()
+            .find(|other| {
+                other.file == region.file && other.start < region.end && region.start < other.end
+            })
+            .map(|other| (region.file.clone(), region.start.max(other.start)))
+    })
+}
+
+/// Checks a patch of a package only changes daisyMoon, returns the regions it changes
+pub fn check_patch(name: &str, diff: &[u8]) -> Result<Vec<HunkRegion>, ModError> {
+    for target in diff_targets(diff) {
+        if !stays_inside(&target) || !target.starts_with("daisyMoon/") {
+            return Err(ModError::BadPatch {
+                patch: name.to_string(),
+                path: target,
+            });
+        }
+    }
+
+    Ok(hunk_regions(diff))
+}
+
+/// Keeps the patches of a package in the installer folder, returns them in order
+///
+/// Each gets the line endings of the first file it changes, like Alloy's diff.
+pub fn store_patches(
+    install_dir: &Path,
+    package: &ModPackage,
+) -> Result<Vec<AppliedPatch>, ModError> {
+    let folder = Path::new(INSTALLER_FOLDER)
+        .join(PATCHES_FOLDER)
+        .join(&package.manifest.id);
+    std::fs::create_dir_all(install_dir.join(&folder))?;
+
+    let mut stored = Vec::new();
+    for name in &package.manifest.patches {
+        let diff = std::fs::read(package.root.join(name))?;
+        let sha256 = sha256_hex(&diff);
+
+        let line_ending = diff_targets(&diff)
+            .iter()
+            .find_map(|target| std::fs::read(install_dir.join(target)).ok())
+            .map(|bytes| LineEnding::detect(&bytes))
+            .unwrap_or(LineEnding::None);
+
+        let path = folder.join(format!("{}.diff", sha256));
+        std::fs::write(install_dir.join(&path), line_ending.apply(&diff))?;
+
+        stored.push(AppliedPatch {
+            id: package.manifest.id.clone(),
+            name: name.clone(),
+            path: path_key(&path),
+            sha256,
+        });
+    }
+
+    Ok(stored)
+}
+
+/// The stack once exactly `patches` are applied, given in the order their mods are in
+///
+/// Patches already applied keep their place, the others go on top.
+pub fn wanted_stack<'a>(
+    stack: &[AppliedPatch],
+    patches: impl IntoIterator<Item = &'a AppliedPatch>,
+) -> Vec<AppliedPatch> {
+    let patches: Vec<&AppliedPatch> = patches.into_iter().collect();
+
+    let mut wanted: Vec<AppliedPatch> = stack
+        .iter()
+        .filter(|applied| patches.contains(applied))
+        .cloned()
+        .collect();
+
+    for patch in patches {
+        if !wanted.contains(patch) {
+            wanted.push(patch.clone());
+        }
+    }
+
+    wanted
+}
+
+async fn apply(
+    install_dir: &Path,
+    target: TargetPlatform,
+    patch: &AppliedPatch,
+    reverse: bool,
+) -> Result<(), ModError> {
+    let result = patch_strictly(install_dir, &install_dir.join(&patch.path), target, reverse).await;
+
+    result.map_err(|output| match reverse {
+        false => ModError::Patch {
+            id: patch.id.clone(),
+            name: patch.name.clone(),
+            output,
+        },
+        true => ModError::Unpatch {
+            id: patch.id.clone(),
+            name: patch.name.clone(),
+            output,
+        },
+    })
+}
+
+/// Applies patches in order, taking the ones it applied off again if one doesn't apply
+async fn push_all(
+    install_dir: &Path,
+    target: TargetPlatform,
+    patches: &[AppliedPatch],
+) -> Result<(), ModError> {
+    for (i, patch) in patches.iter().enumerate() {
+        if let Err(e) = apply(install_dir, target, patch, false).await {
+            for applied in patches[..i].iter().rev() {
+                let _ = apply(install_dir, target, applied, true).await;
+            }
+            return Err(e);
+        }
+    }
+
+    Ok(())
+}
+
+/// Changes the applied patches from `current` to `wanted`
+///
+/// If a patch doesn't apply or can't be taken off, `current` is put back.
+pub async fn rebuild_stack(
+    install_dir: &Path,
+    target: TargetPlatform,
+    current: &[AppliedPatch],
+    wanted: &[AppliedPatch],
+) -> Result<(), ModError> {
+    let kept = current
+        .iter()
+        .zip(wanted)
+        .take_while(|(current, wanted)| current == wanted)
+        .count();
+
+    let taken_off = &current[kept..];
+    for (i, patch) in taken_off.iter().enumerate().rev() {
+        if let Err(e) = apply(install_dir, target, patch, true).await {
+            let _ = push_all(install_dir, target, &taken_off[i + 1..]).await;
+            return Err(e);
+        }
+    }
+
+    if let Err(e) = push_all(install_dir, target, &wanted[kept..]).await {
+        let _ = push_all(install_dir, target, taken_off).await;
+        return Err(e);
+    }
+
+    Ok(())
+}
+
+/// Applies a stack on a freshly patched daisyMoon, mod by mod
+///
+/// Returns what was applied, and the mods left out because a patch of theirs
+/// doesn't apply anymore, with patch's output.
+pub async fn reapply_stack(
+    install_dir: &Path,
+    target: TargetPlatform,
+    stack: &[AppliedPatch],
+) -> (Vec<AppliedPatch>, Vec<(String, String)>) {
+    let mut applied = Vec::new();
+    let mut left_out: Vec<(String, String)> = Vec::new();
+
+    let mut ids: Vec<&str> = Vec::new();
+    for patch in stack {
+        if !ids.contains(&patch.id.as_str()) {
+            ids.push(&patch.id);
+        }
+    }
+
+    for id in ids {
+        let patches: Vec<AppliedPatch> = stack
+            .iter()
+            .filter(|patch| patch.id == id)
+            .cloned()
+            .collect();
+
+        match push_all(install_dir, target, &patches).await {
+            Ok(()) => applied.extend(patches),
+            Err(e) => left_out.push((id.to_string(), e.to_string())),
+        }
+    }
+
+    (applied, left_out)
+}
+
+/// Every file a stack changes, relative to the install
+fn stack_targets(install_dir: &Path, stack: &[AppliedPatch]) -> BTreeSet<String> {
+    stack
+        .iter()
+        .filter_map(|patch| std::fs::read(install_dir.join(&patch.path)).ok())
+        .flat_map(|diff| diff_targets(&diff))
+        .collect()
+}
+
+/// Keeps the files the stack changed for `repair`, and records them as written in in-place installs
+///
+/// Call with the stack that was applied before, while its patches are still kept.
+pub fn record_stack(
+    install_dir: &Path,
+    previous: &[AppliedPatch],
+    stack: &[AppliedPatch],
+) -> std::io::Result<()> {
+    let targets = stack_targets(install_dir, stack);
+
+    let modded = modded_dir(install_dir);
+    if modded.exists() {
+        std::fs::remove_dir_all(&modded)?;
+    }
+
+    for relative in &targets {
+        let path = install_dir.join(relative);
+
+        if path.is_file() {
+            let copy = modded.join(relative);
+            std::fs::create_dir_all(copy.parent().unwrap())?;
+            std::fs::copy(&path, copy)?;
+        }
+    }
+
+    if let Some(mut marker) = read_marker(install_dir)? {
+        for relative in targets.union(&stack_targets(install_dir, previous)) {
+            let path = install_dir.join(relative);
+
+            if path.is_file() {
+                marker.files.insert(relative.clone(), sha256_file(&path)?);
+            }
+        }
+
+        save_marker(install_dir, &marker)?;
+    }
+
+    Ok(())
+}
+
+/// Deletes the kept patches no installed mod has anymore
+pub fn clean_patches(install_dir: &Path, metadata: &InstallMetadata) -> std::io::Result<()> {
+    let folder = install_dir.join(INSTALLER_FOLDER).join(PATCHES_FOLDER);
+    if !folder.is_dir() {
+        return Ok(());
+    }
+
+    let kept: BTreeSet<PathBuf> = metadata
+        .mods
+        .iter()
+        .flat_map(|installed| &installed.patches)
+        .map(|patch| install_dir.join(&patch.path))
+        .collect();
+
+    for entry in std::fs::read_dir(&folder)?.flatten() {
+        let mod_folder = entry.path();
+
+        for patch in std::fs::read_dir(&mod_folder)?.flatten() {
+            if !kept.contains(&patch.path()) {
+                std::fs::remove_file(patch.path())?;
+            }
+        }
+
+        // Fails when the folder still has something in it
+        let _ = std::fs::remove_dir(&mod_folder);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::installer::merge::unified_diff;
+
+    #[test]
+    fn patches_may_delete_lua_comments() {
+        let old = b"local a = 1\n-- old note\nreturn a\n";
+        let new = b"local a = 1\nreturn a\n";
+        let diff = unified_diff("daisyMoon/a.lua", Some(old), Some(new));
+
+        let regions = check_patch("remove_note.diff", &diff).unwrap();
+        assert!(!regions.is_empty());
+        assert!(regions
+            .iter()
+            .all(|region| region.file == "daisyMoon/a.lua"));
+    }
+
+    #[test]
+    fn regions_of_created_and_deleted_files() {
+        let diff = b"--- /dev/null\n\
++++ daisyMoon/new.lua\n\
+@@ -0,0 +1 @@\n\
++return 1\n\
+--- daisyMoon/old.lua\n\
++++ /dev/null\n\
+@@ -1,2 +0,0 @@\n\
+--- gone\n\
+-return 2\n";
+
+        assert_eq!(
+            hunk_regions(diff),
+            vec![
+                HunkRegion {
+                    file: "daisyMoon/new.lua".to_string(),
+                    start: 0,
+                    end: 1,
+                },
+                HunkRegion {
+                    file: "daisyMoon/old.lua".to_string(),
+                    start: 1,
+                    end: 3,
+                },
+            ]
+        );
+    }
+}
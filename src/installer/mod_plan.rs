@@ -0,0 +1,553 @@
+//! Module related to working out which mods to install together
+//!
+//! Mods declare the mods they depend on and the ones they conflict with, each
+//! with a semver requirement. Installing packages starts from the mods already
+//! installed, pulls in the dependencies missing from the packages available,
+//! then checks the whole set, including that no two mods have the same file or
+//! patch the same lines of daisyMoon.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+
+use semver::Version;
+
+use super::{
+    errors::PlanProblem,
+    files::path_key,
+    mod_patches::{first_overlap, HunkRegion},
+    mods::{version_matches, InstalledMod, ModPackage, Requirements},
+};
+
+/// Mods to install, in the order they go in
+#[derive(Debug, Clone)]
+pub struct InstallPlan {
+    /// Packages to install, dependencies before the mods needing them
+    pub packages: Vec<ModPackage>,
+    /// Ids of the packages installed only because something depends on them
+    pub pulled_in: Vec<String>,
+}
+
+/// A mod in the set being checked
+#[derive(Debug, Clone, Copy)]
+enum Selected<'a> {
+    Installed(&'a InstalledMod),
+    New(&'a ModPackage),
+}
+
+impl Selected<'_> {
+    fn version(&self) -> &str {
+        match self {
+            Selected::Installed(installed) => &installed.version,
+            Selected::New(package) => &package.manifest.version,
+        }
+    }
+
+    fn depends(&self) -> &Requirements {
+        match self {
+            Selected::Installed(installed) => &installed.depends,
+            Selected::New(package) => &package.manifest.depends,
+        }
+    }
+
+    fn conflicts(&self) -> &Requirements {
+        match self {
+            Selected::Installed(installed) => &installed.conflicts,
+            Selected::New(package) => &package.manifest.conflicts,
+        }
+    }
+
+    fn files(&self) -> Vec<String> {
+        match self {
+            Selected::Installed(installed) => installed.files.keys().cloned().collect(),
+            Selected::New(package) => package
+                .manifest
+                .files
+                .iter()
+                .map(|file| path_key(Path::new(file)))
+                .collect(),
+        }
+    }
+
+    fn patch_regions(&self) -> &[HunkRegion] {
+        match self {
+            Selected::Installed(installed) => &installed.patch_regions,
+            Selected::New(package) => &package.patch_regions,
+        }
+    }
+
+    fn is_new(&self) -> bool {
+        matches!(self, Selected::New(_))
+    }
+
+    fn is_disabled(&self) -> bool {
+        matches!(self, Selected::Installed(installed) if installed.disabled)
+    }
+}
+
+/// The newest available package of a mod matching a requirement
+fn best_candidate<'a>(
+    available: &'a [ModPackage],
+    id: &str,
+    requirement: &str,
+) -> Option<&'a ModPackage> {
+    available
+        .iter()
+        .filter(|package| package.manifest.id == id)
+        .filter(|package| version_matches(requirement, &package.manifest.version))
+        .max_by_key(|package| Version::parse(&package.manifest.version).ok())
+}
+
+/// Works out how to install `requested` next to the `installed` mods
+///
+/// Dependencies missing or too old are taken from `available`. Every problem is
+/// returned when the mods can't be installed together.
+pub fn resolve(
+    installed: &[InstalledMod],
+    alloy_version: &str,
+    requested: &[ModPackage],
+    available: &[ModPackage],
+) -> Result<InstallPlan, Vec<PlanProblem>> {
+    let mut problems = Vec::new();
+    let mut selected: BTreeMap<String, Selected> = installed
+        .iter()
+        .map(|installed| (installed.id.clone(), Selected::Installed(installed)))
+        .collect();
+
+    let mut queue = Vec::new();
+    let mut given = BTreeSet::new();
+    for package in requested {
+        let id = &package.manifest.id;
+
+        if !given.insert(id.clone()) {
+            problems.push(PlanProblem::Duplicate { id: id.clone() });
+            continue;
+        }
+
+        selected.insert(id.clone(), Selected::New(package));
+        queue.push(package);
+    }
+
+    let mut pulled_in = Vec::new();
+    while let Some(package) = queue.pop() {
+        for (dependency, requirement) in &package.manifest.depends {
+            let current = selected.get(dependency);
+
+            // Disabled dependencies get enabled by installing them again
+            if current.is_some_and(|current| {
+                version_matches(requirement, current.version()) && !current.is_disabled()
+            }) {
+                continue;
+            }
+
+            // Packages given or already picked stay, what's wrong with them is reported below
+            if current.is_some_and(Selected::is_new) {
+                continue;
+            }
+
+            if let Some(candidate) = best_candidate(available, dependency, requirement) {
+                selected.insert(dependency.clone(), Selected::New(candidate));
+                pulled_in.push(dependency.clone());
+                queue.push(candidate);
+            }
+        }
+    }
+
+    for (id, current) in &selected {
+        if let Selected::New(package) = current {
+            if !package.manifest.supports(alloy_version) {
+                problems.push(PlanProblem::Unsupported {
+                    id: id.clone(),
+                    version: current.version().to_string(),
+                    alloy_version: alloy_version.to_string(),
+                });
+            }
+        }
+
+        for (dependency, requirement) in current.depends() {
+            match selected.get(dependency) {
+                None if current.is_new() => problems.push(PlanProblem::Missing {
+                    id: id.clone(),
+                    dependency: dependency.clone(),
+                    requirement: requirement.clone(),
+                }),
+                Some(found) if current.is_new() && found.is_disabled() => {
+                    problems.push(PlanProblem::Disabled {
+                        id: id.clone(),
+                        dependency: dependency.clone(),
+                    })
+                }
+                Some(found)
+                    if (current.is_new() || found.is_new())
+                        && !version_matches(requirement, found.version()) =>
+                {
+                    problems.push(PlanProblem::WrongVersion {
+                        id: id.clone(),
+                        dependency: dependency.clone(),
+                        requirement: requirement.clone(),
+                        found: found.version().to_string(),
+                    })
+                }
+                _ => {}
+            }
+        }
+
+        for (other, requirement) in current.conflicts() {
+            let Some(found) = selected.get(other) else {
+                continue;
+            };
+
+            if (current.is_new() || found.is_new()) && version_matches(requirement, found.version())
+            {
+                problems.push(PlanProblem::Conflict {
+                    id: id.clone(),
+                    other: other.clone(),
+                    version: found.version().to_string(),
+                });
+            }
+        }
+    }
+
+    let mut owners: BTreeMap<String, &str> = BTreeMap::new();
+    for (id, current) in &selected {
+        for file in current.files() {
+            match owners.get(&file) {
+                Some(other) if current.is_new() || selected[*other].is_new() => {
+                    problems.push(PlanProblem::File {
+                        path: file.clone(),
+                        id: other.to_string(),
+                        other: id.clone(),
+                    })
+                }
+                Some(_) => {}
+                None => {
+                    owners.insert(file, id);
+                }
+            }
+        }
+    }
+
+    let patching: Vec<(&String, &Selected)> = selected
+        .iter()
+        .filter(|(_, current)| !current.patch_regions().is_empty())
+        .collect();
+    for (i, (id, current)) in patching.iter().enumerate() {
+        for (other, found) in &patching[i + 1..] {
+            if !current.is_new() && !found.is_new() {
+                continue;
+            }
+
+            if let Some((path, line)) =
+                first_overlap(current.patch_regions(), found.patch_regions())
+            {
+                problems.push(PlanProblem::Hunk {
+                    path,
+                    line,
+                    id: id.to_string(),
+                    other: other.to_string(),
+                });
+            }
+        }
+    }
+
+    if !problems.is_empty() {
+        return Err(problems);
+    }
+
+    // Dependencies first, the rest in the order they were given
+    let mut packages = Vec::new();
+    let mut placed = BTreeSet::new();
+    let new_ids = requested
+        .iter()
+        .map(|package| &package.manifest.id)
+        .chain(pulled_in.iter());
+    for id in new_ids {
+        place(id, &selected, &mut placed, &mut packages);
+    }
+
+    Ok(InstallPlan {
+        packages,
+        pulled_in,
+    })
+}
+
+/// Adds a new mod to the install order after its new dependencies
+fn place(
+    id: &str,
+    selected: &BTreeMap<String, Selected>,
+    placed: &mut BTreeSet<String>,
+    packages: &mut Vec<ModPackage>,
+) {
+    let Some(Selected::New(package)) = selected.get(id) else {
+        return;
+    };
+
+    // Inserted before visiting dependencies so cycles stop
+    if !placed.insert(id.to_string()) {
+        return;
+    }
+
+    for dependency in package.manifest.depends.keys() {
+        place(dependency, selected, placed, packages);
+    }
+
+    packages.push((*package).clone());
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::installer::mods::ModManifest;
+
+    const ALLOY: &str = "1.0.0";
+
+    fn package(id: &str, version: &str) -> ModPackage {
+        ModPackage {
+            manifest: ModManifest {
+                id: id.to_string(),
+                name: id.to_string(),
+                version: version.to_string(),
+                author: "someone".to_string(),
+                alloy_versions: vec!["^1".to_string()],
+                files: Vec::new(),
+                depends: Requirements::new(),
+                conflicts: Requirements::new(),
+                patches: Vec::new(),
+            },
+            root: PathBuf::from(id),
+            patch_regions: Vec::new(),
+        }
+    }
+
+    fn installed(id: &str, version: &str) -> InstalledMod {
+        InstalledMod {
+            id: id.to_string(),
+            name: id.to_string(),
+            version: version.to_string(),
+            author: "someone".to_string(),
+            files: BTreeMap::new(),
+            depends: Requirements::new(),
+            conflicts: Requirements::new(),
+            disabled: false,
+            patches: Vec::new(),
+            patch_regions: Vec::new(),
+        }
+    }
+
+    fn depends(mut package: ModPackage, id: &str, requirement: &str) -> ModPackage {
+        package
+            .manifest
+            .depends
+            .insert(id.to_string(), requirement.to_string());
+        package
+    }
+
+    fn conflicts(mut package: ModPackage, id: &str, requirement: &str) -> ModPackage {
+        package
+            .manifest
+            .conflicts
+            .insert(id.to_string(), requirement.to_string());
+        package
+    }
+
+    fn patching(mut package: ModPackage, start: usize, end: usize) -> ModPackage {
+        package.patch_regions.push(HunkRegion {
+            file: "daisyMoon/main.lua".to_string(),
+            start,
+            end,
+        });
+        package
+    }
+
+    struct Case {
+        name: &'static str,
+        installed: Vec<InstalledMod>,
+        requested: Vec<ModPackage>,
+        available: Vec<ModPackage>,
+        /// `id version` of the packages in install order, or every problem
+        expected: Result<Vec<&'static str>, Vec<&'static str>>,
+    }
+
+    fn cases() -> Vec<Case> {
+        let disabled = InstalledMod {
+            disabled: true,
+            ..installed("lib", "1.0.0")
+        };
+        let conflicting = InstalledMod {
+            conflicts: Requirements::from([("app".to_string(), "<2".to_string())]),
+            ..installed("old", "1.0.0")
+        };
+        let owning = InstalledMod {
+            files: BTreeMap::from([("shared.lua".to_string(), "hash".to_string())]),
+            ..installed("lib", "1.0.0")
+        };
+        let mut owning_too = package("app", "1.0.0");
+        owning_too.manifest.files.push("shared.lua".to_string());
+        let mut unsupported = package("app", "1.0.0");
+        unsupported.manifest.alloy_versions = vec!["^2".to_string()];
+
+        vec![
+            Case {
+                name: "pulls in the newest matching dependency",
+                installed: Vec::new(),
+                requested: vec![depends(package("app", "1.0.0"), "lib", "^1")],
+                available: vec![
+                    package("lib", "1.0.0"),
+                    package("lib", "1.2.0"),
+                    package("lib", "2.0.0"),
+                ],
+                expected: Ok(vec!["lib 1.2.0", "app 1.0.0"]),
+            },
+            Case {
+                name: "missing dependency",
+                installed: Vec::new(),
+                requested: vec![depends(package("app", "1.0.0"), "lib", "^1")],
+                available: Vec::new(),
+                expected: Err(vec![
+                    "app needs lib ^1, which isn't installed, given or in your mod indexes",
+                ]),
+            },
+            Case {
+                name: "installed dependency of the wrong version",
+                installed: vec![installed("lib", "2.0.0")],
+                requested: vec![depends(package("app", "1.0.0"), "lib", "^1")],
+                available: Vec::new(),
+                expected: Err(vec!["app needs lib ^1, but it would be 2.0.0"]),
+            },
+            Case {
+                name: "unsupported Alloy version",
+                installed: Vec::new(),
+                requested: vec![unsupported],
+                available: Vec::new(),
+                expected: Err(vec!["app 1.0.0 doesn't work with Alloy 1.0.0"]),
+            },
+            Case {
+                name: "new mod conflicting with an installed one",
+                installed: vec![installed("old", "1.0.0")],
+                requested: vec![conflicts(package("app", "1.0.0"), "old", "*")],
+                available: Vec::new(),
+                expected: Err(vec!["app can't be installed with old 1.0.0"]),
+            },
+            Case {
+                name: "installed mod conflicting with a new one",
+                installed: vec![conflicting],
+                requested: vec![package("app", "1.0.0")],
+                available: Vec::new(),
+                expected: Err(vec!["old can't be installed with app 1.0.0"]),
+            },
+            Case {
+                name: "disabled dependency",
+                installed: vec![disabled.clone()],
+                requested: vec![depends(package("app", "1.0.0"), "lib", "^1")],
+                available: Vec::new(),
+                expected: Err(vec!["app needs lib, which would be disabled"]),
+            },
+            Case {
+                name: "disabled dependency installed again",
+                installed: vec![disabled],
+                requested: vec![depends(package("app", "1.0.0"), "lib", "^1")],
+                available: vec![package("lib", "1.0.0")],
+                expected: Ok(vec!["lib 1.0.0", "app 1.0.0"]),
+            },
+            Case {
+                name: "two mods with the same file",
+                installed: vec![owning],
+                requested: vec![owning_too],
+                available: Vec::new(),
+                expected: Err(vec!["app and lib both have alloys/shared.lua"]),
+            },
+            Case {
+                name: "overlapping patches",
+                installed: Vec::new(),
+                requested: vec![
+                    patching(package("app", "1.0.0"), 10, 20),
+                    patching(package("other", "1.0.0"), 15, 25),
+                ],
+                available: Vec::new(),
+                expected: Err(vec![
+                    "app and other both patch daisyMoon/main.lua around line 15",
+                ]),
+            },
+            Case {
+                name: "patches next to each other",
+                installed: Vec::new(),
+                requested: vec![
+                    patching(package("app", "1.0.0"), 10, 20),
+                    patching(package("other", "1.0.0"), 20, 25),
+                ],
+                available: Vec::new(),
+                expected: Ok(vec!["app 1.0.0", "other 1.0.0"]),
+            },
+            Case {
+                name: "duplicate package",
+                installed: Vec::new(),
+                requested: vec![package("app", "1.0.0"), package("app", "1.1.0")],
+                available: Vec::new(),
+                expected: Err(vec!["app was given more than once"]),
+            },
+            Case {
+                name: "dependencies go first",
+                installed: Vec::new(),
+                requested: vec![
+                    depends(package("app", "1.0.0"), "lib", "*"),
+                    depends(package("lib", "1.0.0"), "base", "*"),
+                    package("base", "1.0.0"),
+                ],
+                available: Vec::new(),
+                expected: Ok(vec!["base 1.0.0", "lib 1.0.0", "app 1.0.0"]),
+            },
+            Case {
+                name: "dependency cycle",
+                installed: Vec::new(),
+                requested: vec![
+                    depends(package("app", "1.0.0"), "lib", "*"),
+                    depends(package("lib", "1.0.0"), "app", "*"),
+                ],
+                available: Vec::new(),
+                expected: Ok(vec!["lib 1.0.0", "app 1.0.0"]),
+            },
+        ]
+    }
+
+    #[test]
+    fn resolves_every_case() {
+        for case in cases() {
+            let found = resolve(&case.installed, ALLOY, &case.requested, &case.available)
+                .map(|plan| {
+                    plan.packages
+                        .iter()
+                        .map(|package| {
+                            format!("{} {}", package.manifest.id, package.manifest.version)
+                        })
+                        .collect::<Vec<_>>()
+                })
+                .map_err(|problems| {
+                    problems
+                        .iter()
+                        .map(|problem| problem.to_string())
+                        .collect::<Vec<_>>()
+                });
+
+            let expected = case
+                .expected
+                .map(|ids| ids.into_iter().map(String::from).collect())
+                .map_err(|problems| problems.into_iter().map(String::from).collect());
+
+            assert_eq!(found, expected, "{}", case.name);
+        }
+    }
+
+    #[test]
+    fn records_what_was_pulled_in() {
+        let plan = resolve(
+            &[],
+            ALLOY,
+            &[depends(package("app", "1.0.0"), "lib", "^1")],
+            &[package("lib", "1.0.0")],
+        )
+        .unwrap();
+
+        assert_eq!(plan.pulled_in, vec!["lib".to_string()]);
+    }
+}
@@ -0,0 +1,143 @@
+//! Module related to turning installed mods on and off, and to mod profiles
+//!
+//! Disabled mods are moved out of `alloys` into the installer folder, so the game
+//! doesn't load them and they come back without being downloaded again. A profile
+//! is a named set of enabled mods, switching to it enables exactly those.
+
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+
+use super::{
+    errors::{ModError, PlanProblem},
+    metadata::InstallMetadata,
+    mods::{disabled_mods_dir, remove_empty_folders, ALLOYS_FOLDER},
+};
+
+/// What changed when the enabled mods were set
+#[derive(Debug, Clone, Default)]
+pub struct EnabledChanges {
+    pub enabled: Vec<String>,
+    pub disabled: Vec<String>,
+}
+
+/// The ids of the installed mods that are enabled
+pub fn enabled_ids(metadata: &InstallMetadata) -> BTreeSet<String> {
+    metadata
+        .mods
+        .iter()
+        .filter(|installed| !installed.disabled)
+        .map(|installed| installed.id.clone())
+        .collect()
+}
+
+/// Why only the `wanted` mods can't be enabled, every mod in it being installed
+pub fn check_enabled(metadata: &InstallMetadata, wanted: &BTreeSet<String>) -> Vec<PlanProblem> {
+    metadata
+        .mods
+        .iter()
+        .filter(|installed| wanted.contains(&installed.id))
+        .flat_map(|installed| {
+            installed
+                .depends
+                .keys()
+                .filter(|dependency| !wanted.contains(*dependency))
+                .map(|dependency| PlanProblem::Disabled {
+                    id: installed.id.clone(),
+                    dependency: dependency.clone(),
+                })
+        })
+        .collect()
+}
+
+/// Renames files, putting back the ones already renamed if one can't be
+fn move_all(moves: &[(PathBuf, PathBuf)]) -> std::io::Result<()> {
+    for (i, (from, to)) in moves.iter().enumerate() {
+        let result =
+            std::fs::create_dir_all(to.parent().unwrap()).and_then(|_| std::fs::rename(from, to));
+
+        if let Err(e) = result {
+            for (from, to) in moves[..i].iter().rev() {
+                let _ = std::fs::rename(to, from);
+            }
+            return Err(e);
+        }
+    }
+
+    Ok(())
+}
+
+/// Enables exactly the `wanted` installed mods, moving the others out of `alloys`
+///
+/// Either every mod is moved or none is. `metadata` is updated, the caller writes it.
+pub fn set_enabled(
+    install_dir: &Path,
+    metadata: &mut InstallMetadata,
+    wanted: &BTreeSet<String>,
+) -> Result<EnabledChanges, ModError> {
+    let alloys = install_dir.join(ALLOYS_FOLDER);
+    let disabled_root = disabled_mods_dir(install_dir);
+
+    let mut moves = Vec::new();
+    let mut changes = EnabledChanges::default();
+
+    for installed in &metadata.mods {
+        let enable = wanted.contains(&installed.id);
+        // Already enabled, or already disabled
+        if enable != installed.disabled {
+            continue;
+        }
+
+        let store = disabled_root.join(&installed.id);
+        for file in installed.files.keys() {
+            let (from, to) = match enable {
+                true => (store.join(file), alloys.join(file)),
+                false => (alloys.join(file), store.join(file)),
+            };
+
+            // Files deleted by hand stay deleted
+            if from.exists() {
+                moves.push((from, to));
+            }
+        }
+
+        match enable {
+            true => changes.enabled.push(installed.id.clone()),
+            false => changes.disabled.push(installed.id.clone()),
+        }
+    }
+
+    move_all(&moves)?;
+
+    for (from, _) in &moves {
+        let root = match from.starts_with(&alloys) {
+            true => &alloys,
+            false => &disabled_root,
+        };
+        remove_empty_folders(root, from);
+    }
+
+    for installed in &mut metadata.mods {
+        installed.disabled = !wanted.contains(&installed.id);
+    }
+
+    Ok(changes)
+}
+
+/// The mods a profile enables, the ones it lists that aren't installed being left out
+pub fn profile_mods(
+    metadata: &InstallMetadata,
+    name: &str,
+) -> Result<(BTreeSet<String>, Vec<String>), ModError> {
+    let listed = metadata
+        .mod_profiles
+        .get(name)
+        .ok_or_else(|| ModError::NoProfile {
+            name: name.to_string(),
+        })?;
+
+    let installed = |id: &&String| metadata.mods.iter().any(|installed| installed.id == **id);
+    let wanted = listed.iter().filter(installed).cloned().collect();
+    let missing = listed.iter().filter(|id| !installed(id)).cloned().collect();
+
+    Ok((wanted, missing))
+}
@@ -0,0 +1,576 @@
+//! Module related to Alloy mods, which live in the `alloys` folder of an install
+//!
+//! A mod package is a zip or a folder with an `alloy_mod.ron` manifest next to
+//! the files it puts into `alloys`. Only the files the manifest lists are
+//! installed, and what was installed is recorded in the install's metadata so
+//! it can be removed again. Packages can also patch daisyMoon, see `mod_patches`.
+
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::{Cursor, Read, Seek},
+    path::{Component, Path, PathBuf},
+};
+
+use semver::{Version, VersionReq};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    errors::ModError,
+    files::{path_key, sha256_file, sha256_hex},
+    metadata::InstallMetadata,
+    mod_patches::{check_patch, store_patches, AppliedPatch, HunkRegion},
+    user_data::user_data_dir,
+    INSTALLER_FOLDER,
+};
+
+/// Folder of an install mods go into
+pub const ALLOYS_FOLDER: &str = "alloys";
+
+/// The manifest at the root of every package
+pub const MANIFEST_FILENAME: &str = "alloy_mod.ron";
+
+/// Folder in the installer folder disabled mods are moved to, one folder for each
+const DISABLED_MODS_FOLDER: &str = "disabled_mods";
+
+/// Folder in the user data folder packages are unpacked into before being installed
+const STAGING_FOLDER: &str = "mod_staging";
+
+/// Mod id -> semver requirement on its version, like `">=1.2, <2"` or `"*"`
+pub type Requirements = BTreeMap<String, String>;
+
+/// What a package says about itself, the contents of `alloy_mod.ron`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModManifest {
+    /// Tells mods apart, letters, digits, `-` and `_` only
+    pub id: String,
+    /// Human readable name
+    pub name: String,
+    /// Semver version of the mod
+    pub version: String,
+    pub author: String,
+    /// Semver requirements on the Alloy version, the mod works with Alloy if one matches
+    pub alloy_versions: Vec<String>,
+    /// Files the package puts into `alloys`, relative to it and to the manifest
+    pub files: Vec<String>,
+    /// Mods that have to be installed with this one
+    #[serde(default)]
+    pub depends: Requirements,
+    /// Mod versions this one can't be installed with
+    #[serde(default)]
+    pub conflicts: Requirements,
+    /// Diffs changing daisyMoon, relative to the manifest, applied in order after Alloy's
+    #[serde(default)]
+    pub patches: Vec<String>,
+}
+
+impl ModManifest {
+    /// Whether the mod works with an Alloy version
+    pub fn supports(&self, alloy_version: &str) -> bool {
+        self.alloy_versions
+            .iter()
+            .any(|requirement| version_matches(requirement, alloy_version))
+    }
+
+    /// Checks the id, the versions and that every file and patch stays inside the package
+    pub fn validate(&self) -> Result<(), ModError> {
+        let valid_id = !self.id.is_empty()
+            && self
+                .id
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+
+        if !valid_id {
+            return Err(ModError::BadId {
+                id: self.id.clone(),
+            });
+        }
+
+        if let Err(e) = Version::parse(&self.version) {
+            return Err(ModError::BadVersion {
+                version: self.version.clone(),
+                reason: e.to_string(),
+            });
+        }
+
+        let requirements = self
+            .alloy_versions
+            .iter()
+            .chain(self.depends.values())
+            .chain(self.conflicts.values());
+        for requirement in requirements {
+            if let Err(e) = VersionReq::parse(requirement) {
+                return Err(ModError::BadVersion {
+                    version: requirement.clone(),
+                    reason: e.to_string(),
+                });
+            }
+        }
+
+        for file in self.files.iter().chain(&self.patches) {
+            if safe_relative(file).is_none() {
+                return Err(ModError::BadPath { path: file.clone() });
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// A mod recorded in the metadata of an install
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InstalledMod {
+    pub id: String,
+    pub name: String,
+    pub version: String,
+    pub author: String,
+    /// Relative path in `alloys` -> sha256 of every file the mod installed
+    pub files: BTreeMap<String, String>,
+    #[serde(default)]
+    pub depends: Requirements,
+    #[serde(default)]
+    pub conflicts: Requirements,
+    /// Whether the files were moved out of `alloys` into the mod's disabled folder
+    #[serde(default)]
+    pub disabled: bool,
+    /// The mod's patches, in the order they're applied
+    #[serde(default)]
+    pub patches: Vec<AppliedPatch>,
+    /// What the patches change in daisyMoon
+    #[serde(default)]
+    pub patch_regions: Vec<HunkRegion>,
+}
+
+impl InstalledMod {
+    /// Hash of the files and patches the mod installed, see `content_hash`
+    pub fn content_hash(&self) -> String {
+        let patches: Vec<(String, String)> = self
+            .patches
+            .iter()
+            .map(|patch| (patch.name.clone(), patch.sha256.clone()))
+            .collect();
+
+        content_hash(&self.files, &patches)
+    }
+}
+
+/// A package ready to be installed
+#[derive(Debug, Clone)]
+pub struct ModPackage {
+    pub manifest: ModManifest,
+    /// The folder holding the manifest and the files
+    pub root: PathBuf,
+    /// What the patches change in daisyMoon
+    pub patch_regions: Vec<HunkRegion>,
+}
+
+impl ModPackage {
+    /// Reads the package in a folder, or in its only subfolder as zips often have one
+    pub fn open(folder: &Path) -> Result<ModPackage, ModError> {
+        let root = if folder.join(MANIFEST_FILENAME).is_file() {
+            folder.to_path_buf()
+        } else {
+            let subfolders: Vec<PathBuf> = std::fs::read_dir(folder)?
+                .flatten()
+                .map(|entry| entry.path())
+                .filter(|path| path.is_dir())
+                .collect();
+
+            match subfolders.as_slice() {
+                [only] if only.join(MANIFEST_FILENAME).is_file() => only.clone(),
+                _ => return Err(ModError::NoManifest),
+            }
+        };
+
+        let manifest: ModManifest =
+            ron::from_str(&std::fs::read_to_string(root.join(MANIFEST_FILENAME))?)?;
+        manifest.validate()?;
+
+        for file in manifest.files.iter().chain(&manifest.patches) {
+            if !root.join(file).is_file() {
+                return Err(ModError::MissingFile { path: file.clone() });
+            }
+        }
+
+        let mut patch_regions = Vec::new();
+        for patch in &manifest.patches {
+            patch_regions.extend(check_patch(patch, &std::fs::read(root.join(patch))?)?);
+        }
+
+        Ok(ModPackage {
+            manifest,
+            root,
+            patch_regions,
+        })
+    }
+
+    /// Hash of the files and patches installing the package would install, see `content_hash`
+    pub fn content_hash(&self) -> Result<String, ModError> {
+        let mut files = BTreeMap::new();
+        for file in &self.manifest.files {
+            files.insert(
+                path_key(Path::new(file)),
+                sha256_file(&self.root.join(file))?,
+            );
+        }
+
+        let mut patches = Vec::new();
+        for name in &self.manifest.patches {
+            patches.push((name.clone(), sha256_file(&self.root.join(name))?));
+        }
+
+        Ok(content_hash(&files, &patches))
+    }
+}
+
+/// Hash of what a mod installs, the same for a package and the mod it installed
+///
+/// Zips of the same files hash differently, so locks pin mods by this instead.
+fn content_hash(files: &BTreeMap<String, String>, patches: &[(String, String)]) -> String {
+    let mut listing = String::new();
+
+    for (path, sha256) in files {
+        listing.push_str(&format!("file {} {}\n", sha256, path));
+    }
+    for (name, sha256) in patches {
+        listing.push_str(&format!("patch {} {}\n", sha256, name));
+    }
+
+    sha256_hex(listing.as_bytes())
+}
+
+/// Whether a version matches a semver requirement, a bare `1.2.0` meaning `^1.2.0`
+pub fn version_matches(requirement: &str, version: &str) -> bool {
+    match (VersionReq::parse(requirement), Version::parse(version)) {
+        (Ok(requirement), Ok(version)) => requirement.matches(&version),
+        _ => requirement == version,
+    }
+}
+
+/// The path a manifest entry stands for, `None` if it could leave the folder it's in
+fn safe_relative(path: &str) -> Option<PathBuf> {
+    let path = Path::new(path);
+    let normal = path
+        .components()
+        .all(|component| matches!(component, Component::Normal(_)));
+
+    (normal && path.components().next().is_some()).then(|| path.to_path_buf())
+}
+
+/// Extracts a zipped package into a folder
+fn extract_zip<R: Read + Seek>(reader: R, destination: &Path) -> Result<(), ModError> {
+    let mut archive = zip::ZipArchive::new(reader)?;
+
+    for i in 0..archive.len() {
+        let mut file = archive.by_index(i)?;
+
+        if file.is_dir() {
+            continue;
+        }
+
+        let Some(name) = file.enclosed_name().map(Path::to_path_buf) else {
+            continue;
+        };
+
+        let outpath = destination.join(name);
+
+        std::fs::create_dir_all(outpath.parent().unwrap())?;
+
+        let mut outfile = File::create(&outpath)?;
+        std::io::copy(&mut file, &mut outfile)?;
+    }
+
+    Ok(())
+}
+
+/// A fresh staging folder for a package, packages fetched together each getting their own
+fn staging_dir(input: &str) -> Result<PathBuf, ModError> {
+    let staging = user_data_dir()
+        .join(STAGING_FOLDER)
+        .join(format!("{:08x}", crc32fast::hash(input.as_bytes())));
+
+    if staging.exists() {
+        std::fs::remove_dir_all(&staging)?;
+    }
+    std::fs::create_dir_all(&staging)?;
+
+    Ok(staging)
+}
+
+/// Reads a zipped package, `origin` being where it came from
+pub fn unpack(bytes: &[u8], origin: &str) -> Result<ModPackage, ModError> {
+    let staging = staging_dir(origin)?;
+    extract_zip(Cursor::new(bytes), &staging)?;
+
+    ModPackage::open(&staging)
+}
+
+/// Gets the package a folder, a zip or a URL points to
+///
+/// Zips are unpacked into the staging folder, folders are read where they are.
+pub async fn fetch_package(input: &str) -> Result<ModPackage, ModError> {
+    let input = input.trim();
+
+    if input.starts_with("http://") || input.starts_with("https://") {
+        let response = reqwest::get(input).await?;
+
+        if !response.status().is_success() {
+            return Err(ModError::Status {
+                url: input.to_string(),
+                status: response.status().as_u16(),
+            });
+        }
+
+        return unpack(&response.bytes().await?, input);
+    }
+
+    let path = PathBuf::from(input);
+
+    if path.is_dir() {
+        return ModPackage::open(&path);
+    }
+
+    if path.is_file() && path.extension().is_some_and(|extension| extension == "zip") {
+        let staging = staging_dir(input)?;
+        extract_zip(File::open(&path)?, &staging)?;
+
+        return ModPackage::open(&staging);
+    }
+
+    Err(ModError::NotAPackage {
+        input: input.to_string(),
+    })
+}
+
+/// Deletes files of `alloys`, and the folders they leave empty
+fn delete_files<'a>(
+    alloys: &Path,
+    files: impl IntoIterator<Item = &'a String>,
+) -> std::io::Result<()> {
+    for file in files {
+        let path = alloys.join(file);
+
+        match std::fs::remove_file(&path) {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+
+        remove_empty_folders(alloys, &path);
+    }
+
+    Ok(())
+}
+
+/// Removes the folders of a file that are left empty, up to `root`
+pub fn remove_empty_folders(root: &Path, file: &Path) {
+    let mut folder = file.parent();
+    while let Some(current) = folder.filter(|current| *current != root) {
+        // Fails when the folder still has something in it
+        if std::fs::remove_dir(current).is_err() {
+            break;
+        }
+        folder = current.parent();
+    }
+}
+
+/// The folder every disabled mod is moved into
+pub fn disabled_mods_dir(install_dir: &Path) -> PathBuf {
+    install_dir
+        .join(INSTALLER_FOLDER)
+        .join(DISABLED_MODS_FOLDER)
+}
+
+/// Deletes the disabled folder of a mod, if it has one
+fn delete_disabled(install_dir: &Path, id: &str) -> std::io::Result<()> {
+    match std::fs::remove_dir_all(disabled_mods_dir(install_dir).join(id)) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+/// Installs a package into `alloys`, replacing the version of it already installed
+///
+/// Records the mod in `metadata`, which the caller writes, and returns the version it replaced.
+/// Its patches are only kept, applying them is up to the caller.
+pub fn install_mod(
+    install_dir: &Path,
+    metadata: &mut InstallMetadata,
+    package: &ModPackage,
+) -> Result<Option<InstalledMod>, ModError> {
+    let manifest = &package.manifest;
+
+    if !manifest.supports(&metadata.alloy_version) {
+        return Err(ModError::Unsupported {
+            id: manifest.id.clone(),
+            alloy_version: metadata.alloy_version.clone(),
+            supported: manifest.alloy_versions.join(" or "),
+        });
+    }
+
+    let alloys = install_dir.join(ALLOYS_FOLDER);
+    let previous = metadata
+        .mods
+        .iter()
+        .position(|installed| installed.id == manifest.id)
+        .map(|index| metadata.mods.remove(index));
+
+    if let Some(previous) = &previous {
+        // Installing a disabled mod again enables it, its files aren't in `alloys` to delete
+        if previous.disabled {
+            delete_disabled(install_dir, &previous.id)?;
+        } else {
+            let dropped = previous.files.keys().filter(|file| {
+                !manifest
+                    .files
+                    .iter()
+                    .any(|new| path_key(Path::new(new)) == **file)
+            });
+            delete_files(&alloys, dropped)?;
+        }
+    }
+
+    let mut files = BTreeMap::new();
+    for file in &manifest.files {
+        let destination = alloys.join(file);
+
+        std::fs::create_dir_all(destination.parent().unwrap())?;
+        std::fs::copy(package.root.join(file), &destination)?;
+
+        files.insert(path_key(Path::new(file)), sha256_file(&destination)?);
+    }
+
+    let patches = store_patches(install_dir, package)?;
+
+    metadata.mods.push(InstalledMod {
+        id: manifest.id.clone(),
+        name: manifest.name.clone(),
+        version: manifest.version.clone(),
+        author: manifest.author.clone(),
+        files,
+        depends: manifest.depends.clone(),
+        conflicts: manifest.conflicts.clone(),
+        disabled: false,
+        patches,
+        patch_regions: package.patch_regions.clone(),
+    });
+
+    Ok(previous)
+}
+
+/// Deletes the files of an installed mod and takes it out of `metadata`, which the caller writes
+///
+/// Its patches have to be taken off by the caller first.
+pub fn remove_mod(
+    install_dir: &Path,
+    metadata: &mut InstallMetadata,
+    id: &str,
+) -> Result<InstalledMod, ModError> {
+    let index = check_removable(metadata, id)?;
+
+    if metadata.mods[index].disabled {
+        delete_disabled(install_dir, id)?;
+    } else {
+        delete_files(
+            &install_dir.join(ALLOYS_FOLDER),
+            metadata.mods[index].files.keys(),
+        )?;
+    }
+
+    Ok(metadata.mods.remove(index))
+}
+
+/// Checks a mod is installed and no other mod needs it, returns where it is in `metadata`
+pub fn check_removable(metadata: &InstallMetadata, id: &str) -> Result<usize, ModError> {
+    let index = metadata
+        .mods
+        .iter()
+        .position(|installed| installed.id == id)
+        .ok_or_else(|| ModError::NotInstalled { id: id.to_string() })?;
+
+    let needed_by: Vec<&str> = metadata
+        .mods
+        .iter()
+        .filter(|installed| installed.depends.contains_key(id))
+        .map(|installed| installed.id.as_str())
+        .collect();
+
+    if !needed_by.is_empty() {
+        return Err(ModError::Needed {
+            id: id.to_string(),
+            needed_by: needed_by.join(", "),
+        });
+    }
+
+    Ok(index)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn package(root: &Path, version: &str, files: &[&str]) -> ModPackage {
+        for file in files {
+            std::fs::write(root.join(file), version).unwrap();
+        }
+
+        ModPackage {
+            manifest: ModManifest {
+                id: "shaders".to_string(),
+                name: "Shaders".to_string(),
+                version: version.to_string(),
+                author: "someone".to_string(),
+                alloy_versions: vec!["*".to_string()],
+                files: files.iter().map(|file| file.to_string()).collect(),
+                depends: Requirements::new(),
+                conflicts: Requirements::new(),
+                patches: Vec::new(),
+            },
+            root: root.to_path_buf(),
+            patch_regions: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn reinstalling_a_disabled_mod_leaves_alloys_alone() {
+        let install = tempfile::tempdir().unwrap();
+        let source = tempfile::tempdir().unwrap();
+        let alloys = install.path().join(ALLOYS_FOLDER);
+        let mut metadata = InstallMetadata::default();
+
+        install_mod(
+            install.path(),
+            &mut metadata,
+            &package(source.path(), "1.0.0", &["old.lua", "kept.lua"]),
+        )
+        .unwrap();
+
+        // Disabled, then another mod put its own old.lua in alloys
+        let disabled = disabled_mods_dir(install.path()).join("shaders");
+        std::fs::create_dir_all(&disabled).unwrap();
+        for file in ["old.lua", "kept.lua"] {
+            std::fs::rename(alloys.join(file), disabled.join(file)).unwrap();
+        }
+        metadata.mods[0].disabled = true;
+        std::fs::write(alloys.join("old.lua"), "someone else's").unwrap();
+
+        let previous = install_mod(
+            install.path(),
+            &mut metadata,
+            &package(source.path(), "2.0.0", &["kept.lua"]),
+        )
+        .unwrap();
+
+        assert!(previous.unwrap().disabled);
+        assert_eq!(
+            std::fs::read_to_string(alloys.join("old.lua")).unwrap(),
+            "someone else's"
+        );
+        assert_eq!(
+            std::fs::read_to_string(alloys.join("kept.lua")).unwrap(),
+            "2.0.0"
+        );
+        assert!(!disabled.exists());
+        assert!(!metadata.mods[0].disabled);
+    }
+}
@@ -0,0 +1,75 @@
+//! Module related to the platform an install is made for, which isn't always the one we run on
+
+use std::{fmt::Display, path::Path};
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use super::line_endings::LineEnding;
+
+/// The platform the game of an install runs on
+///
+/// Linux players running Cobalt through Proton need a `Windows` install.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+pub enum TargetPlatform {
+    Windows,
+    Linux,
+}
+
+impl TargetPlatform {
+    /// The platform the installer is running on
+    pub fn host() -> TargetPlatform {
+        if cfg!(target_os = "windows") {
+            TargetPlatform::Windows
+        } else {
+            TargetPlatform::Linux
+        }
+    }
+
+    /// Whether this is the platform the installer is running on
+    pub fn is_host(&self) -> bool {
+        *self == TargetPlatform::host()
+    }
+
+    /// The line endings the game's files and Alloy's diff use on this platform
+    pub fn line_ending(&self) -> LineEnding {
+        match self {
+            TargetPlatform::Windows => LineEnding::Crlf,
+            TargetPlatform::Linux => LineEnding::Lf,
+        }
+    }
+
+    /// Tells the user how to start the game once it's installed
+    pub fn print_launch_instructions(&self, install_dir: &Path) {
+        let exe = install_dir.join("cobaltDM.exe");
+
+        match (self, TargetPlatform::host()) {
+            (TargetPlatform::Windows, TargetPlatform::Linux) => {
+                println!(
+                    "Add {} to Steam as a non-steam game, then force a Proton version",
+                    exe.display()
+                );
+                println!("in its Properties > Compatibility, and enjoy! :D");
+            }
+            _ => println!(
+                "Add {} to Steam as a non-steam game and enjoy! :D",
+                exe.display()
+            ),
+        }
+    }
+}
+
+impl Default for TargetPlatform {
+    fn default() -> Self {
+        TargetPlatform::host()
+    }
+}
+
+impl Display for TargetPlatform {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TargetPlatform::Windows => write!(f, "Windows"),
+            TargetPlatform::Linux => write!(f, "Linux"),
+        }
+    }
+}
@@ -0,0 +1,228 @@
+//! Module related to the per-user registry of every Alloy install
+//!
+//! Installs are recorded when they are made, so they can be listed and
+//! managed later. If the registry is lost it gets rebuilt by looking for
+//! install metadata in the Steam libraries.
+
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Local, Utc};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    errors::RegistryError,
+    metadata::{metadata_path, InstallMetadata},
+    steam::library_folders,
+    user_data::user_data_dir,
+};
+
+/// The registry, in the user data folder
+const REGISTRY_FILENAME: &str = "installs.ron";
+
+/// What new copies are called, followed by a number if that's taken
+pub const COPY_FOLDER_NAME: &str = "CobaltAlloy";
+
+/// An install recorded in the registry
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RegisteredInstall {
+    /// The game folder Alloy is installed in
+    pub path: PathBuf,
+    /// Version of Alloy installed there
+    pub alloy_version: String,
+    /// The game folder it was copied from, if it is a copy
+    pub source_game: Option<PathBuf>,
+    /// When it was installed, if known
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+impl RegisteredInstall {
+    /// Records an install from its metadata
+    pub fn from_metadata(path: &Path, metadata: &InstallMetadata) -> Self {
+        RegisteredInstall {
+            path: path.canonicalize().unwrap_or(path.to_path_buf()),
+            alloy_version: metadata.alloy_version.clone(),
+            source_game: metadata.source_game.clone(),
+            created_at: metadata.created_at,
+        }
+    }
+
+    /// The name of the install's folder
+    pub fn name(&self) -> String {
+        self.path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_else(|| self.path.display().to_string())
+    }
+
+    /// Whether the install is still there
+    pub fn exists(&self) -> bool {
+        metadata_path(&self.path).exists()
+    }
+
+    /// Whether Alloy was installed straight into the game instead of a copy
+    pub fn is_in_place(&self) -> bool {
+        match &self.source_game {
+            Some(source) => same_path(source, &self.path),
+            None => self.path.ends_with(super::cobalt::STEAM_RELATIVE_PATH),
+        }
+    }
+
+    /// One line description for listings
+    pub fn describe(&self) -> String {
+        let created = match self.created_at {
+            Some(created_at) => created_at
+                .with_timezone(&Local)
+                .format("%Y-%m-%d %H:%M")
+                .to_string(),
+            None => "an unknown date".to_string(),
+        };
+
+        format!(
+            "Alloy {} at {}, installed on {}",
+            self.alloy_version,
+            self.path.display(),
+            created
+        )
+    }
+}
+
+/// Returns where the registry is
+pub fn registry_path() -> PathBuf {
+    user_data_dir().join(REGISTRY_FILENAME)
+}
+
+/// Reads the registry, rebuilding it if it is missing
+pub fn read_registry() -> Result<Vec<RegisteredInstall>, RegistryError> {
+    let path = registry_path();
+
+    if !path.exists() {
+        let found = scan_for_installs();
+        write_registry(&found)?;
+
+        return Ok(found);
+    }
+
+    Ok(ron::from_str(&std::fs::read_to_string(path)?)?)
+}
+
+fn write_registry(installs: &[RegisteredInstall]) -> Result<(), RegistryError> {
+    std::fs::create_dir_all(user_data_dir())?;
+
+    let as_string = ron::ser::to_string_pretty(installs, ron::ser::PrettyConfig::default())?;
+    std::fs::write(registry_path(), as_string)?;
+
+    Ok(())
+}
+
+/// Looks for installs in every game folder of every Steam library
+pub fn scan_for_installs() -> Vec<RegisteredInstall> {
+    let mut found = Vec::new();
+
+    for library in library_folders() {
+        let Ok(entries) = std::fs::read_dir(library.join("steamapps").join("common")) else {
+            continue;
+        };
+
+        for entry in entries.flatten() {
+            let path = entry.path();
+
+            if !metadata_path(&path).exists() {
+                continue;
+            }
+
+            // Metadata from older installers might not decode, those installs are skipped
+            let metadata = std::fs::read_to_string(metadata_path(&path))
+                .ok()
+                .and_then(|as_string| ron::from_str::<InstallMetadata>(&as_string).ok());
+
+            if let Some(metadata) = metadata {
+                found.push(RegisteredInstall::from_metadata(&path, &metadata));
+            }
+        }
+    }
+
+    found.sort_by(|a, b| a.path.cmp(&b.path));
+
+    found
+}
+
+/// Records an install, replacing what was known about the same folder
+pub fn register(
+    path: &Path,
+    metadata: &InstallMetadata,
+) -> Result<RegisteredInstall, RegistryError> {
+    let install = RegisteredInstall::from_metadata(path, metadata);
+
+    let mut installs = read_registry()?;
+    installs.retain(|other| !same_path(&other.path, &install.path));
+    installs.push(install.clone());
+    write_registry(&installs)?;
+
+    Ok(install)
+}
+
+/// Forgets an install, without touching its folder
+pub fn unregister(path: &Path) -> Result<(), RegistryError> {
+    let mut installs = read_registry()?;
+    installs.retain(|other| !same_path(&other.path, path));
+    write_registry(&installs)
+}
+
+/// Finds an install by its path or folder name
+pub fn find(query: &str) -> Result<RegisteredInstall, RegistryError> {
+    let installs = read_registry()?;
+
+    if let Some(install) = installs
+        .iter()
+        .find(|install| same_path(&install.path, Path::new(query)))
+    {
+        return Ok(install.clone());
+    }
+
+    let mut matches = installs.iter().filter(|install| install.name() == query);
+
+    match (matches.next(), matches.next()) {
+        (Some(install), None) => Ok(install.clone()),
+        (Some(_), Some(_)) => Err(RegistryError::Ambiguous {
+            query: query.to_string(),
+        }),
+        (None, _) => {
+            // It might have been made before the registry existed
+            let path = Path::new(query);
+            let Ok(as_string) = std::fs::read_to_string(metadata_path(path)) else {
+                return Err(RegistryError::NotFound {
+                    query: query.to_string(),
+                });
+            };
+
+            match ron::from_str::<InstallMetadata>(&as_string) {
+                Ok(metadata) => register(path, &metadata),
+                Err(e) => Err(RegistryError::Metadata {
+                    path: path.display().to_string(),
+                    reason: e.to_string(),
+                }),
+            }
+        }
+    }
+}
+
+/// Returns the first free folder for a new copy in `parent`
+pub fn next_copy_dir(parent: &Path) -> PathBuf {
+    let mut copy_dir = parent.join(COPY_FOLDER_NAME);
+    let mut n: usize = 1;
+
+    while copy_dir.exists() {
+        copy_dir = parent.join(format!("{}{}", COPY_FOLDER_NAME, n));
+        n += 1;
+    }
+
+    copy_dir
+}
+
+/// Compares two paths, following links when both exist
+pub fn same_path(a: &Path, b: &Path) -> bool {
+    match (a.canonicalize(), b.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
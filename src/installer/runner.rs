@@ -0,0 +1,441 @@
+//! Module related to what runs the game of an install
+//!
+//! Windows installs run natively on Windows. On Linux they go through Proton,
+//! found where Steam keeps it, or through the system's Wine if there's no Proton.
+
+use std::path::{Path, PathBuf};
+
+use clap::ValueEnum;
+
+use super::{
+    errors::LaunchError,
+    platform::TargetPlatform,
+    steam::{library_folders, steam_roots},
+    INSTALLER_FOLDER,
+};
+
+/// Where Proton keeps the Windows prefix of an install, in the installer folder
+pub const COMPAT_DATA_FOLDER: &str = "compatdata";
+
+/// What the game printed the last time it was launched, in the installer folder
+pub const LAUNCH_LOG_FILENAME: &str = "launch.log";
+
+/// The runner asked for on the command line
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum RunnerKind {
+    /// Natively when the install was made for this platform, else the newest Proton, else Wine
+    #[default]
+    Auto,
+    Native,
+    Proton,
+    Wine,
+}
+
+/// What runs the game
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Runner {
+    /// The game runs by itself
+    Native,
+    /// A Proton version, `proton` being its launch script
+    Proton {
+        proton: PathBuf,
+        steam_root: PathBuf,
+    },
+    /// Wine, found in the `PATH`
+    Wine { wine: PathBuf },
+}
+
+impl Runner {
+    /// A name to show the player
+    pub fn describe(&self) -> String {
+        match self {
+            Runner::Native => "natively".to_string(),
+            Runner::Proton { proton, .. } => format!(
+                "with {}",
+                proton
+                    .parent()
+                    .and_then(Path::file_name)
+                    .map(|name| name.to_string_lossy().to_string())
+                    .unwrap_or_else(|| "Proton".to_string())
+            ),
+            Runner::Wine { wine } => format!("with Wine ({})", wine.display()),
+        }
+    }
+}
+
+/// A command that starts the game
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LaunchCommand {
+    pub program: PathBuf,
+    pub args: Vec<String>,
+    /// Variables set on top of the installer's environment
+    pub env: Vec<(String, String)>,
+    pub working_dir: PathBuf,
+}
+
+impl LaunchCommand {
+    /// The command, ready to be spawned
+    pub fn to_command(&self) -> std::process::Command {
+        let mut command = std::process::Command::new(&self.program);
+        command
+            .args(&self.args)
+            .envs(self.env.iter().map(|(key, value)| (key, value)))
+            .current_dir(&self.working_dir);
+        command
+    }
+}
+
+/// What went wrong when the game stopped, `None` if it exited normally
+pub fn describe_exit(status: std::process::ExitStatus) -> Option<String> {
+    if status.success() {
+        return None;
+    }
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+
+        if let Some(signal) = status.signal() {
+            return Some(format!("crashed (signal {})", signal));
+        }
+    }
+
+    match status.code() {
+        // Windows reports crashes as NTSTATUS codes, like 0xC0000005 for access violations
+        Some(code) if cfg!(windows) && (code as u32) >= 0xC000_0000 => {
+            Some(format!("crashed (0x{:08X})", code as u32))
+        }
+        Some(code) => Some(format!("exited with code {}", code)),
+        None => Some("was stopped".to_string()),
+    }
+}
+
+/// Finds a program in the `PATH`
+pub fn find_in_path(name: &str) -> Option<PathBuf> {
+    std::env::split_paths(&std::env::var_os("PATH")?)
+        .map(|folder| folder.join(name))
+        .find(|path| path.is_file())
+}
+
+/// Sorts Proton versions by the numbers in their name, Experimental being the newest
+fn proton_rank(folder: &Path) -> (bool, Vec<u32>) {
+    let name = folder
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+
+    let numbers = name
+        .split(|c: char| !c.is_ascii_digit())
+        .filter_map(|part| part.parse().ok())
+        .collect();
+
+    (name.contains("Experimental"), numbers)
+}
+
+/// Every Proton version Steam has, the launch script of each with its Steam folder
+pub fn find_protons() -> Vec<(PathBuf, PathBuf)> {
+    let mut found: Vec<(PathBuf, PathBuf)> = Vec::new();
+    let roots = steam_roots();
+
+    let Some(default_root) = roots.first().cloned() else {
+        return found;
+    };
+
+    // Versions installed by hand, like GE-Proton
+    for root in &roots {
+        let Ok(entries) = std::fs::read_dir(root.join("compatibilitytools.d")) else {
+            continue;
+        };
+
+        for entry in entries.flatten() {
+            let proton = entry.path().join("proton");
+            if proton.is_file() {
+                found.push((proton, root.clone()));
+            }
+        }
+    }
+
+    for library in library_folders() {
+        let Ok(entries) = std::fs::read_dir(library.join("steamapps").join("common")) else {
+            continue;
+        };
+
+        for entry in entries.flatten() {
+            let is_proton = entry.file_name().to_string_lossy().starts_with("Proton");
+            let proton = entry.path().join("proton");
+
+            if is_proton && proton.is_file() {
+                found.push((proton, default_root.clone()));
+            }
+        }
+    }
+
+    found.sort_by_key(|(proton, _)| proton_rank(proton.parent().unwrap_or(proton)));
+    found
+}
+
+/// Finds what runs the game of an install made for `target`, `None` if nothing can
+pub fn detect(target: TargetPlatform) -> Option<Runner> {
+    if target.is_host() {
+        return Some(Runner::Native);
+    }
+
+    if target == TargetPlatform::Linux {
+        return None;
+    }
+
+    if let Some((proton, steam_root)) = find_protons().pop() {
+        return Some(Runner::Proton { proton, steam_root });
+    }
+
+    find_in_path("wine").map(|wine| Runner::Wine { wine })
+}
+
+/// Finds the runner asked for
+///
+/// `proton` is the path of a Proton folder or part of its name, `wine` the path of
+/// the Wine binary, both found automatically when not given.
+pub fn choose(
+    kind: RunnerKind,
+    target: TargetPlatform,
+    proton: Option<&str>,
+    wine: Option<&Path>,
+) -> Result<Runner, LaunchError> {
+    let kind = match kind {
+        RunnerKind::Auto if proton.is_some() => RunnerKind::Proton,
+        RunnerKind::Auto if wine.is_some() => RunnerKind::Wine,
+        kind => kind,
+    };
+
+    match kind {
+        RunnerKind::Auto => detect(target).ok_or(LaunchError::NoRunner { target }),
+        RunnerKind::Native if target.is_host() => Ok(Runner::Native),
+        RunnerKind::Native => Err(LaunchError::NotNative { target }),
+        RunnerKind::Proton => {
+            let protons = find_protons();
+
+            let found = match proton {
+                Some(query) if Path::new(query).join("proton").is_file() => {
+                    let steam_root = protons
+                        .first()
+                        .map(|(_, root)| root.clone())
+                        .or_else(|| steam_roots().first().cloned())
+                        .ok_or(LaunchError::NoSteam)?;
+                    Some((Path::new(query).join("proton"), steam_root))
+                }
+                Some(query) => protons.into_iter().rev().find(|(found, _)| {
+                    found
+                        .parent()
+                        .and_then(Path::file_name)
+                        .map(|name| {
+                            name.to_string_lossy()
+                                .to_lowercase()
+                                .contains(&query.to_lowercase())
+                        })
+                        .unwrap_or(false)
+                }),
+                None => protons.into_iter().last(),
+            };
+
+            found
+                .map(|(proton, steam_root)| Runner::Proton { proton, steam_root })
+                .ok_or_else(|| match proton {
+                    Some(query) => LaunchError::ProtonNotFound {
+                        query: query.to_string(),
+                    },
+                    None => LaunchError::NoProton,
+                })
+        }
+        RunnerKind::Wine => wine
+            .map(Path::to_path_buf)
+            .or_else(|| find_in_path("wine"))
+            .map(|wine| Runner::Wine { wine })
+            .ok_or(LaunchError::WineNotFound),
+    }
+}
+
+/// Builds the command starting the game of an install
+///
+/// `appid` is the content of the install's `steam_appid.txt`, which Proton and the
+/// Steam API read from the environment when the game isn't started by Steam.
+pub fn launch_command(
+    runner: &Runner,
+    install_dir: &Path,
+    appid: Option<&str>,
+    args: &[String],
+) -> LaunchCommand {
+    let exe = install_dir.join("cobaltDM.exe");
+
+    let (program, mut full_args, mut env) = match runner {
+        Runner::Native => (exe, Vec::new(), Vec::new()),
+        Runner::Proton { proton, steam_root } => {
+            let compat_data = install_dir.join(INSTALLER_FOLDER).join(COMPAT_DATA_FOLDER);
+
+            (
+                proton.clone(),
+                vec!["run".to_string(), exe.display().to_string()],
+                vec![
+                    (
+                        "STEAM_COMPAT_DATA_PATH".to_string(),
+                        compat_data.display().to_string(),
+                    ),
+                    (
+                        "STEAM_COMPAT_CLIENT_INSTALL_PATH".to_string(),
+                        steam_root.display().to_string(),
+                    ),
+                ],
+            )
+        }
+        Runner::Wine { wine } => (wine.clone(), vec![exe.display().to_string()], Vec::new()),
+    };
+
+    if let Some(appid) = appid {
+        env.push(("SteamAppId".to_string(), appid.to_string()));
+        env.push(("SteamGameId".to_string(), appid.to_string()));
+    }
+
+    full_args.extend(args.iter().cloned());
+
+    LaunchCommand {
+        program,
+        args: full_args,
+        env,
+        working_dir: install_dir.to_path_buf(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn env_var<'a>(command: &'a LaunchCommand, key: &str) -> Option<&'a str> {
+        command
+            .env
+            .iter()
+            .find(|(found, _)| found == key)
+            .map(|(_, value)| value.as_str())
+    }
+
+    #[test]
+    fn launches_natively() {
+        let install = Path::new("/games/Cobalt");
+        let command = launch_command(&Runner::Native, install, None, &["-windowed".to_string()]);
+
+        assert_eq!(command.program, install.join("cobaltDM.exe"));
+        assert_eq!(command.args, vec!["-windowed"]);
+        assert!(command.env.is_empty());
+        assert_eq!(command.working_dir, install);
+    }
+
+    #[test]
+    fn launches_with_proton() {
+        let install = Path::new("/games/Cobalt");
+        let runner = Runner::Proton {
+            proton: PathBuf::from("/steam/compatibilitytools.d/GE-Proton9-1/proton"),
+            steam_root: PathBuf::from("/steam"),
+        };
+        let command = launch_command(&runner, install, Some("357340"), &[]);
+
+        assert_eq!(
+            command.program,
+            Path::new("/steam/compatibilitytools.d/GE-Proton9-1/proton")
+        );
+        assert_eq!(
+            command.args,
+            vec![
+                "run".to_string(),
+                install.join("cobaltDM.exe").display().to_string()
+            ]
+        );
+        assert_eq!(
+            env_var(&command, "STEAM_COMPAT_DATA_PATH"),
+            Some(
+                install
+                    .join(INSTALLER_FOLDER)
+                    .join(COMPAT_DATA_FOLDER)
+                    .display()
+                    .to_string()
+                    .as_str()
+            )
+        );
+        assert_eq!(
+            env_var(&command, "STEAM_COMPAT_CLIENT_INSTALL_PATH"),
+            Some("/steam")
+        );
+        assert_eq!(env_var(&command, "SteamAppId"), Some("357340"));
+        assert_eq!(runner.describe(), "with GE-Proton9-1");
+    }
+
+    #[test]
+    fn launches_with_wine() {
+        let install = Path::new("/games/Cobalt");
+        let runner = Runner::Wine {
+            wine: PathBuf::from("/usr/bin/wine"),
+        };
+        let command = launch_command(&runner, install, None, &["-windowed".to_string()]);
+
+        assert_eq!(command.program, Path::new("/usr/bin/wine"));
+        assert_eq!(
+            command.args,
+            vec![
+                install.join("cobaltDM.exe").display().to_string(),
+                "-windowed".to_string()
+            ]
+        );
+        assert_eq!(env_var(&command, "SteamAppId"), None);
+    }
+
+    #[test]
+    fn chooses_the_runner_asked_for() {
+        let host = TargetPlatform::host();
+        let other = match host {
+            TargetPlatform::Windows => TargetPlatform::Linux,
+            TargetPlatform::Linux => TargetPlatform::Windows,
+        };
+
+        assert_eq!(
+            choose(RunnerKind::Native, host, None, None).unwrap(),
+            Runner::Native
+        );
+        assert!(matches!(
+            choose(RunnerKind::Native, other, None, None),
+            Err(LaunchError::NotNative { .. })
+        ));
+
+        // A Wine binary given makes Auto use Wine
+        let wine = Path::new("/opt/wine/bin/wine");
+        assert_eq!(
+            choose(RunnerKind::Auto, TargetPlatform::Windows, None, Some(wine)).unwrap(),
+            Runner::Wine {
+                wine: wine.to_path_buf()
+            }
+        );
+
+        assert!(matches!(
+            choose(
+                RunnerKind::Proton,
+                TargetPlatform::Windows,
+                Some("no-such-proton-anywhere"),
+                None
+            ),
+            Err(LaunchError::ProtonNotFound { .. })
+        ));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn describes_how_the_game_stopped() {
+        use std::os::unix::process::ExitStatusExt;
+        use std::process::ExitStatus;
+
+        assert_eq!(describe_exit(ExitStatus::from_raw(0)), None);
+        assert_eq!(
+            describe_exit(ExitStatus::from_raw(3 << 8)).as_deref(),
+            Some("exited with code 3")
+        );
+        assert_eq!(
+            describe_exit(ExitStatus::from_raw(11)).as_deref(),
+            Some("crashed (signal 11)")
+        );
+    }
+}
@@ -0,0 +1,472 @@
+//! Module related to adding installs to Steam as non-Steam games
+//!
+//! Non-Steam games are entries of `userdata/<user>/config/shortcuts.vdf`, and on
+//! Linux the Proton version they run with is set in `config/config.vdf`.
+//! Steam rewrites both when it exits, so it has to be restarted to see changes.
+
+use std::path::{Path, PathBuf};
+
+use chrono::Local;
+
+use super::{
+    errors::ShortcutError,
+    platform::TargetPlatform,
+    registry::COPY_FOLDER_NAME,
+    steam::steam_roots,
+    vdf::{self, VdfMap, VdfValue},
+};
+
+/// The Proton version used when none is picked
+pub const DEFAULT_COMPAT_TOOL: &str = "proton_experimental";
+
+/// A non-Steam game entry
+#[derive(Debug, Clone)]
+pub struct Shortcut {
+    /// The name shown in the library
+    pub app_name: String,
+    /// The executable
+    pub exe: PathBuf,
+    /// The folder the game starts in
+    pub start_dir: PathBuf,
+    /// Arguments given to the game
+    pub launch_options: String,
+    /// The icon shown in the library, Steam uses the executable's if empty
+    pub icon: Option<PathBuf>,
+}
+
+/// What adding a shortcut did
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShortcutOutcome {
+    /// There was no entry for the executable yet
+    Added,
+    /// The existing entry for the executable was updated
+    Updated,
+}
+
+impl Shortcut {
+    /// The shortcut for the game in an install
+    pub fn for_install(install_dir: &Path, app_name: &str) -> Shortcut {
+        Shortcut {
+            app_name: app_name.to_string(),
+            exe: install_dir.join("cobaltDM.exe"),
+            start_dir: install_dir.to_path_buf(),
+            launch_options: String::new(),
+            icon: None,
+        }
+    }
+
+    /// `Exe` as Steam writes it, quoted
+    pub fn quoted_exe(&self) -> String {
+        format!("\"{}\"", self.exe.display())
+    }
+
+    /// The id Steam gives the shortcut, used for its artwork and Proton settings
+    pub fn appid(&self) -> u32 {
+        shortcut_appid(&self.quoted_exe(), &self.app_name)
+    }
+}
+
+/// Computes the id of a non-Steam game the way Steam does
+pub fn shortcut_appid(quoted_exe: &str, app_name: &str) -> u32 {
+    let mut hasher = crc32fast::Hasher::new();
+    hasher.update(quoted_exe.as_bytes());
+    hasher.update(app_name.as_bytes());
+
+    hasher.finalize() | 0x8000_0000
+}
+
+/// A Steam account that logged in on this machine
+#[derive(Debug, Clone)]
+pub struct SteamUser {
+    /// The account id, the name of its userdata folder
+    pub id: String,
+    /// The Steam folder it belongs to
+    pub steam_root: PathBuf,
+}
+
+impl SteamUser {
+    /// The account's config folder
+    pub fn config_dir(&self) -> PathBuf {
+        self.steam_root
+            .join("userdata")
+            .join(&self.id)
+            .join("config")
+    }
+
+    /// The folder Steam looks for library artwork in
+    pub fn grid_dir(&self) -> PathBuf {
+        self.config_dir().join("grid")
+    }
+
+    /// The file holding the account's non-Steam games
+    pub fn shortcuts_path(&self) -> PathBuf {
+        self.config_dir().join("shortcuts.vdf")
+    }
+}
+
+/// Returns every Steam account that logged in on this machine
+pub fn steam_users() -> Vec<SteamUser> {
+    let mut users = Vec::new();
+
+    for root in steam_roots() {
+        let Ok(entries) = std::fs::read_dir(root.join("userdata")) else {
+            continue;
+        };
+
+        for entry in entries.flatten() {
+            let id = entry.file_name().to_string_lossy().to_string();
+
+            // 0 is where Steam puts settings before anyone logs in
+            if id == "0" || !id.chars().all(|c| c.is_ascii_digit()) {
+                continue;
+            }
+
+            users.push(SteamUser {
+                id,
+                steam_root: root.clone(),
+            });
+        }
+    }
+
+    users
+}
+
+/// Finds a Steam account by id, or returns every one
+pub fn find_user(id: Option<&str>) -> Result<Vec<SteamUser>, ShortcutError> {
+    let users = steam_users();
+
+    if users.is_empty() {
+        return Err(ShortcutError::NoSteam);
+    }
+
+    match id {
+        Some(id) => {
+            let found: Vec<SteamUser> = users.into_iter().filter(|user| user.id == id).collect();
+
+            if found.is_empty() {
+                return Err(ShortcutError::NoUser {
+                    user: id.to_string(),
+                });
+            }
+
+            Ok(found)
+        }
+        None => Ok(users),
+    }
+}
+
+/// Copies a Steam file next to itself before it is changed
+fn back_up(path: &Path) -> std::io::Result<()> {
+    if !path.exists() {
+        return Ok(());
+    }
+
+    let mut backup = path.as_os_str().to_owned();
+    backup.push(format!(
+        ".alloy-{}.bak",
+        Local::now().format("%Y%m%d-%H%M%S")
+    ));
+
+    std::fs::copy(path, PathBuf::from(backup)).map(|_| ())
+}
+
+fn same_exe(existing: &str, ours: &str) -> bool {
+    let existing = existing.trim_matches('"');
+    let ours = ours.trim_matches('"');
+
+    if cfg!(target_os = "windows") {
+        existing.eq_ignore_ascii_case(ours)
+    } else {
+        existing == ours
+    }
+}
+
+/// Adds the shortcut to an account's non-Steam games, or updates the entry for the same executable
+///
+/// Returns the id of the entry along with what was done.
+pub fn add_shortcut(
+    user: &SteamUser,
+    shortcut: &Shortcut,
+) -> Result<(ShortcutOutcome, u32), ShortcutError> {
+    let path = user.shortcuts_path();
+
+    let mut root: VdfMap = if path.exists() {
+        vdf::read_binary(&std::fs::read(&path)?)?
+    } else {
+        Vec::new()
+    };
+
+    let shortcuts = vdf::map_entry(&mut root, "shortcuts");
+    let quoted_exe = shortcut.quoted_exe();
+
+    let existing = shortcuts.iter().position(|(_, entry)| {
+        entry
+            .as_map()
+            .and_then(|entry| vdf::get(entry, "Exe"))
+            .and_then(VdfValue::as_str)
+            .is_some_and(|exe| same_exe(exe, &quoted_exe))
+    });
+
+    let outcome = match existing {
+        Some(_) => ShortcutOutcome::Updated,
+        None => ShortcutOutcome::Added,
+    };
+
+    let index = match existing {
+        Some(index) => index,
+        None => {
+            let next_key = shortcuts
+                .iter()
+                .filter_map(|(key, _)| key.parse::<usize>().ok())
+                .max()
+                .map(|max| max + 1)
+                .unwrap_or(0);
+
+            shortcuts.push((next_key.to_string(), VdfValue::Map(Vec::new())));
+            shortcuts.len() - 1
+        }
+    };
+
+    let VdfValue::Map(entry) = &mut shortcuts[index].1 else {
+        unreachable!("shortcut entries are maps");
+    };
+
+    // Keep the id of an existing entry, its artwork and playtime are tied to it
+    let appid = match vdf::get(entry, "appid") {
+        Some(VdfValue::Int(appid)) => *appid as u32,
+        _ => {
+            vdf::set(entry, "appid", VdfValue::Int(shortcut.appid() as i32));
+            shortcut.appid()
+        }
+    };
+
+    let icon = shortcut
+        .icon
+        .as_ref()
+        .map(|icon| icon.display().to_string())
+        .unwrap_or_default();
+
+    vdf::set(
+        entry,
+        "AppName",
+        VdfValue::String(shortcut.app_name.clone()),
+    );
+    vdf::set(entry, "Exe", VdfValue::String(quoted_exe));
+    vdf::set(
+        entry,
+        "StartDir",
+        VdfValue::String(format!("\"{}\"", shortcut.start_dir.display())),
+    );
+    vdf::set(entry, "icon", VdfValue::String(icon));
+    vdf::set(
+        entry,
+        "LaunchOptions",
+        VdfValue::String(shortcut.launch_options.clone()),
+    );
+
+    for (key, default) in [
+        ("ShortcutPath", VdfValue::String(String::new())),
+        ("IsHidden", VdfValue::Int(0)),
+        ("AllowDesktopConfig", VdfValue::Int(1)),
+        ("AllowOverlay", VdfValue::Int(1)),
+        ("OpenVR", VdfValue::Int(0)),
+        ("Devkit", VdfValue::Int(0)),
+        ("DevkitGameID", VdfValue::String(String::new())),
+        ("DevkitOverrideAppID", VdfValue::Int(0)),
+        ("LastPlayTime", VdfValue::Int(0)),
+        ("FlatpakAppID", VdfValue::String(String::new())),
+        ("tags", VdfValue::Map(Vec::new())),
+    ] {
+        if vdf::get(entry, key).is_none() {
+            entry.push((key.to_string(), default));
+        }
+    }
+
+    back_up(&path)?;
+    std::fs::create_dir_all(user.config_dir())?;
+    std::fs::write(&path, vdf::write_binary(&root))?;
+
+    Ok((outcome, appid))
+}
+
+/// The name an install gets in the Steam library
+pub fn default_name(install_dir: &Path) -> String {
+    let folder = install_dir
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+
+    if folder.is_empty() || folder == COPY_FOLDER_NAME {
+        "Cobalt Alloy".to_string()
+    } else {
+        format!("Cobalt Alloy - {}", folder)
+    }
+}
+
+/// Adds an install's shortcut to an account, with Proton when it's a Windows install on Linux
+pub fn add_install(
+    user: &SteamUser,
+    shortcut: &Shortcut,
+    target: TargetPlatform,
+    compat_tool: &str,
+) -> Result<(ShortcutOutcome, u32), ShortcutError> {
+    let (outcome, appid) = add_shortcut(user, shortcut)?;
+
+    if target == TargetPlatform::Windows && TargetPlatform::host() == TargetPlatform::Linux {
+        set_compat_tool(&user.steam_root, appid, compat_tool)?;
+    }
+
+    Ok((outcome, appid))
+}
+
+/// The id of the shortcut, the one it already has if the account has it
+pub fn appid_for(user: &SteamUser, shortcut: &Shortcut) -> u32 {
+    existing_appid(user, &shortcut.exe).unwrap_or_else(|| shortcut.appid())
+}
+
+/// Returns the id Steam uses for the shortcut of an executable, if the account has one
+pub fn existing_appid(user: &SteamUser, exe: &Path) -> Option<u32> {
+    let root = vdf::read_binary(&std::fs::read(user.shortcuts_path()).ok()?).ok()?;
+    let quoted_exe = format!("\"{}\"", exe.display());
+
+    vdf::get(&root, "shortcuts")?
+        .as_map()?
+        .iter()
+        .filter_map(|(_, entry)| entry.as_map())
+        .find(|entry| {
+            vdf::get(entry, "Exe")
+                .and_then(VdfValue::as_str)
+                .is_some_and(|exe| same_exe(exe, &quoted_exe))
+        })
+        .and_then(|entry| match vdf::get(entry, "appid")? {
+            VdfValue::Int(appid) => Some(*appid as u32),
+            _ => None,
+        })
+}
+
+/// Makes Steam run a non-Steam game with Proton, `tool` being e.g. `proton_experimental`
+pub fn set_compat_tool(steam_root: &Path, appid: u32, tool: &str) -> Result<(), ShortcutError> {
+    let path = steam_root.join("config").join("config.vdf");
+
+    let mut root: VdfMap = if path.exists() {
+        vdf::read_text(&std::fs::read_to_string(&path)?)?
+    } else {
+        Vec::new()
+    };
+
+    let mapping = [
+        "InstallConfigStore",
+        "Software",
+        "Valve",
+        "Steam",
+        "CompatToolMapping",
+    ]
+    .iter()
+    .fold(&mut root, |map, key| vdf::map_entry(map, key));
+
+    let entry = vdf::map_entry(mapping, &appid.to_string());
+    vdf::set(entry, "name", VdfValue::String(tool.to_string()));
+    vdf::set(entry, "config", VdfValue::String(String::new()));
+    vdf::set(entry, "priority", VdfValue::String("250".to_string()));
+
+    back_up(&path)?;
+    std::fs::create_dir_all(steam_root.join("config"))?;
+    std::fs::write(&path, vdf::write_text(&root))?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn shortcut() -> Shortcut {
+        Shortcut::for_install(Path::new("/games/Cobalt"), "Cobalt Alloy")
+    }
+
+    fn user(steam_root: &Path) -> SteamUser {
+        SteamUser {
+            id: "1234".to_string(),
+            steam_root: steam_root.to_path_buf(),
+        }
+    }
+
+    fn shortcut_entries(user: &SteamUser) -> VdfMap {
+        let root = vdf::read_binary(&std::fs::read(user.shortcuts_path()).unwrap()).unwrap();
+        vdf::get(&root, "shortcuts")
+            .unwrap()
+            .as_map()
+            .unwrap()
+            .clone()
+    }
+
+    #[test]
+    fn appids_are_steams_crc() {
+        assert_eq!(
+            shortcut_appid("\"/games/Cobalt/cobaltDM.exe\"", "Cobalt Alloy"),
+            3_096_593_557
+        );
+    }
+
+    #[test]
+    fn adding_twice_updates_the_same_entry() {
+        let steam = tempfile::tempdir().unwrap();
+        let user = user(steam.path());
+
+        let (first, appid) = add_shortcut(&user, &shortcut()).unwrap();
+        let mut renamed = shortcut();
+        renamed.app_name = "Renamed".to_string();
+        let (second, same_appid) = add_shortcut(&user, &renamed).unwrap();
+
+        assert_eq!(first, ShortcutOutcome::Added);
+        assert_eq!(second, ShortcutOutcome::Updated);
+        assert_eq!(appid, shortcut().appid());
+        assert_eq!(same_appid, appid);
+
+        let entries = shortcut_entries(&user);
+        assert_eq!(entries.len(), 1);
+        let entry = entries[0].1.as_map().unwrap();
+        assert_eq!(
+            vdf::get(entry, "AppName").unwrap().as_str(),
+            Some("Renamed")
+        );
+        assert_eq!(existing_appid(&user, &shortcut().exe), Some(appid));
+    }
+
+    #[test]
+    fn sets_the_compat_tool_keeping_other_settings() {
+        let steam = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(steam.path().join("config")).unwrap();
+        std::fs::write(
+            steam.path().join("config/config.vdf"),
+            "\"InstallConfigStore\"\n{\n\t\"Software\"\n\t{\n\t\t\"Valve\"\n\t\t{\n\t\t\t\"Steam\"\n\t\t\t{\n\t\t\t\t\"AutoUpdateWindowEnabled\"\t\t\"0\"\n\t\t\t}\n\t\t}\n\t}\n}\n",
+        )
+        .unwrap();
+
+        set_compat_tool(steam.path(), 3_096_593_557, DEFAULT_COMPAT_TOOL).unwrap();
+
+        let text = std::fs::read_to_string(steam.path().join("config/config.vdf")).unwrap();
+        let root = vdf::read_text(&text).unwrap();
+        let steam_settings = ["InstallConfigStore", "Software", "Valve", "Steam"]
+            .iter()
+            .fold(&root, |map, key| {
+                vdf::get(map, key).unwrap().as_map().unwrap()
+            });
+
+        assert_eq!(
+            vdf::get(steam_settings, "AutoUpdateWindowEnabled")
+                .unwrap()
+                .as_str(),
+            Some("0")
+        );
+        let mapping = vdf::get(steam_settings, "CompatToolMapping")
+            .unwrap()
+            .as_map()
+            .unwrap();
+        let entry = vdf::get(mapping, "3096593557").unwrap().as_map().unwrap();
+        assert_eq!(
+            vdf::get(entry, "name").unwrap().as_str(),
+            Some(DEFAULT_COMPAT_TOOL)
+        );
+    }
+}
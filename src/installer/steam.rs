@@ -1,4 +1,4 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 const COBALT_APPID: usize = 357340;
 
@@ -8,3 +8,120 @@ pub async fn create_app_id_txt(base_path: PathBuf) {
 
     std::fs::write(appid_path, COBALT_APPID.to_string()).unwrap();
 }
+
+/// Reads the app id in an install's steam_appid.txt, if it has one
+pub fn read_app_id(base_path: &Path) -> Option<String> {
+    let appid = std::fs::read_to_string(base_path.join("steam_appid.txt")).ok()?;
+    let appid = appid.trim();
+
+    (!appid.is_empty()).then(|| appid.to_string())
+}
+
+/// Returns the path to Cobalt's appmanifest, assuming the game lives in `steamapps/common/<folder>`
+pub fn appmanifest_path(game_path: &Path) -> Option<PathBuf> {
+    let steamapps = game_path.parent()?.parent()?;
+
+    Some(steamapps.join(format!("appmanifest_{}.acf", COBALT_APPID)))
+}
+
+/// Returns every value of `key` in a text VDF file (appmanifests, libraryfolders.vdf, ...)
+pub fn vdf_values(vdf: &str, key: &str) -> Vec<String> {
+    vdf.lines()
+        .filter_map(|line| {
+            let mut parts = line.split('"').filter(|part| !part.trim().is_empty());
+
+            match (parts.next(), parts.next()) {
+                (Some(found), Some(value)) if found.eq_ignore_ascii_case(key) => {
+                    Some(value.replace("\\\\", "\\"))
+                }
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+/// Reads the Steam build id of the Cobalt install at `game_path`, if Steam manages it
+pub fn read_build_id(game_path: &Path) -> Option<String> {
+    let manifest = std::fs::read_to_string(appmanifest_path(game_path)?).ok()?;
+
+    vdf_values(&manifest, "buildid").into_iter().next()
+}
+
+/// Whether Steam manages the game folder at `game_path`, in which case it can change its files at any time
+pub fn is_steam_managed(game_path: &Path) -> bool {
+    let Some(manifest) =
+        appmanifest_path(game_path).and_then(|path| std::fs::read_to_string(path).ok())
+    else {
+        return false;
+    };
+
+    let Some(folder) = game_path.file_name() else {
+        return false;
+    };
+
+    vdf_values(&manifest, "installdir")
+        .iter()
+        .any(|installdir| folder.eq_ignore_ascii_case(installdir.as_str()))
+}
+
+/// Returns the places Steam is usually installed that exist on this machine
+pub fn steam_roots() -> Vec<PathBuf> {
+    let mut candidates: Vec<PathBuf> = Vec::new();
+
+    cfg_if::cfg_if! {
+        if #[cfg(target_os = "linux")] {
+            let username = whoami::username();
+            candidates.push(format!("/home/{}/.steam", username).into());
+            candidates.push(format!("/home/{}/.steam/steam", username).into());
+            candidates.push(format!("/home/{}/.var/app/com.valvesoftware.Steam/data/Steam", username).into());
+            candidates.push(format!("/home/{}/.local/share/Steam", username).into());
+        }
+        else if #[cfg(target_os = "windows")] {
+            candidates.push("C:/Program Files (x86)/Steam".into());
+            candidates.push("C:/Program Files/Steam".into());
+        }
+        else if #[cfg(target_os = "macos")] {
+            let username = whoami::username();
+            candidates.push(format!("/Users/{}/Library/Application Support/Steam", username).into());
+        }
+    }
+
+    let mut roots: Vec<PathBuf> = Vec::new();
+
+    for candidate in candidates {
+        // ~/.steam/steam is usually a link to ~/.local/share/Steam
+        let Ok(canonical) = candidate.canonicalize() else {
+            continue;
+        };
+
+        if canonical.join("steamapps").is_dir() && !roots.contains(&canonical) {
+            roots.push(canonical);
+        }
+    }
+
+    roots
+}
+
+/// Returns every Steam library folder on this machine, including the ones on other drives
+pub fn library_folders() -> Vec<PathBuf> {
+    let mut folders: Vec<PathBuf> = Vec::new();
+
+    for root in steam_roots() {
+        let vdf_path = root.join("steamapps").join("libraryfolders.vdf");
+        let listed = std::fs::read_to_string(vdf_path)
+            .map(|vdf| vdf_values(&vdf, "path"))
+            .unwrap_or_default();
+
+        let found = std::iter::once(root).chain(listed.into_iter().map(PathBuf::from));
+
+        for folder in found {
+            let folder = folder.canonicalize().unwrap_or(folder);
+
+            if folder.join("steamapps").is_dir() && !folders.contains(&folder) {
+                folders.push(folder);
+            }
+        }
+    }
+
+    folders
+}
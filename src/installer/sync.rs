@@ -0,0 +1,280 @@
+//! Module related to keeping copies in sync with the game they were made from
+//!
+//! When a copy is made, the hash of every file of the game is recorded. Once
+//! Steam updates the game, the files that changed there can be copied over
+//! again, unless they were changed in the copy as well.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use super::{
+    copy::{self, CopyPlan, CopyReport, CopyStrategy, GameCopyOptions, PlannedFile},
+    errors::CopyError,
+    files::{list_files, path_key, sha256_file, sha256_hex},
+    metadata::InstallMetadata,
+    steam::read_build_id,
+    INSTALLER_FOLDER,
+};
+
+/// Where the hashes of the game's files are recorded, in the installer folder
+const SNAPSHOT_FILENAME: &str = "game_snapshot.ron";
+
+/// Paths of an install that don't come from the game
+const NOT_FROM_GAME: &[&str] = &["daisyMoon", "alloys", "steam_appid.txt", INSTALLER_FOLDER];
+
+/// Paths Alloy writes over, so they only differ from the game because of Alloy
+pub const REAPPLIED_PATHS: &[&str] = &["translations"];
+
+/// The hash of every file of the game when a copy was made or last synced
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct GameSnapshot {
+    /// Path, relative to the game, and the sha256 of the file
+    pub files: BTreeMap<String, String>,
+    /// Hash of the game's own daisyMoon, unknown for snapshots of older installers
+    #[serde(default)]
+    pub daisymoon: Option<String>,
+}
+
+/// What syncing a copy with its game will do
+#[derive(Debug, Clone, Default)]
+pub struct SyncPlan {
+    /// Files that changed or appeared in the game, and can be copied again
+    pub updated: Vec<PathBuf>,
+    /// Files that were removed from the game, and can be removed from the copy
+    pub removed: Vec<PathBuf>,
+    /// Files that changed both in the game and in the copy, these are left alone
+    pub conflicts: Vec<PathBuf>,
+    /// Whether Steam changed the game's daisyMoon, which Alloy was made for
+    pub daisymoon_changed: bool,
+    /// The snapshot to record once the plan was applied
+    pub snapshot: GameSnapshot,
+}
+
+impl SyncPlan {
+    /// Whether the copy already matches the game
+    pub fn is_empty(&self) -> bool {
+        self.updated.is_empty() && self.removed.is_empty() && self.conflicts.is_empty()
+    }
+
+    /// Whether Alloy has to be applied again after the plan
+    pub fn touches_reapplied(&self) -> bool {
+        self.updated
+            .iter()
+            .chain(&self.removed)
+            .any(|relative| is_under(relative, REAPPLIED_PATHS))
+    }
+}
+
+fn is_under(relative: &Path, paths: &[&str]) -> bool {
+    let Some(first) = relative.components().next() else {
+        return false;
+    };
+
+    paths.iter().any(|path| first.as_os_str() == *path)
+}
+
+/// Hashes every file of the game
+pub fn snapshot_game(game: &Path) -> std::io::Result<GameSnapshot> {
+    let mut snapshot = GameSnapshot::default();
+    let mut daisymoon = String::new();
+
+    for relative in list_files(game)? {
+        if is_under(&relative, &["daisyMoon"]) {
+            let key = path_key(&relative);
+            let hash = sha256_file(&game.join(&relative))?;
+            daisymoon.push_str(&format!("{} {}\n", key, hash));
+            continue;
+        }
+
+        if is_under(&relative, NOT_FROM_GAME) {
+            continue;
+        }
+
+        snapshot
+            .files
+            .insert(path_key(&relative), sha256_file(&game.join(&relative))?);
+    }
+
+    // The game's daisyMoon is never copied, only watched for changes
+    if !daisymoon.is_empty() {
+        snapshot.daisymoon = Some(sha256_hex(daisymoon.as_bytes()));
+    }
+
+    Ok(snapshot)
+}
+
+/// Reads the snapshot recorded in an install, `None` for installs made before snapshots
+pub fn read_snapshot(install_dir: &Path) -> std::io::Result<Option<GameSnapshot>> {
+    let path = install_dir.join(INSTALLER_FOLDER).join(SNAPSHOT_FILENAME);
+
+    if !path.exists() {
+        return Ok(None);
+    }
+
+    ron::from_str(&std::fs::read_to_string(path)?)
+        .map(Some)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+}
+
+/// Records a snapshot in an install
+pub fn write_snapshot(install_dir: &Path, snapshot: &GameSnapshot) -> std::io::Result<()> {
+    std::fs::create_dir_all(install_dir.join(INSTALLER_FOLDER))?;
+
+    let as_string =
+        ron::to_string(snapshot).expect("Failed to encode snapshot, this should not happen");
+    std::fs::write(
+        install_dir.join(INSTALLER_FOLDER).join(SNAPSHOT_FILENAME),
+        as_string,
+    )
+}
+
+fn hash_if_exists(path: &Path) -> std::io::Result<Option<String>> {
+    match sha256_file(path) {
+        Ok(hash) => Ok(Some(hash)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Whether Steam updated the game since the copy was made or synced, `None` if the build ids aren't known
+pub fn game_was_updated(game: &Path, metadata: &InstallMetadata) -> Option<bool> {
+    let recorded = metadata.source_build.as_ref()?;
+    let current = read_build_id(game)?;
+
+    Some(*recorded != current)
+}
+
+/// Compares the game, the copy and what the game was like when it was copied.
+///
+/// Without a recorded snapshot, every file that differs counts as changed on both sides.
+pub fn plan_sync(
+    game: &Path,
+    install_dir: &Path,
+    recorded: Option<&GameSnapshot>,
+) -> std::io::Result<SyncPlan> {
+    let current = snapshot_game(game)?;
+    let empty = BTreeMap::new();
+    let old_files = recorded.map(|snapshot| &snapshot.files).unwrap_or(&empty);
+
+    let mut plan = SyncPlan::default();
+
+    let old_daisymoon = recorded.and_then(|snapshot| snapshot.daisymoon.as_ref());
+    plan.daisymoon_changed =
+        old_daisymoon.is_some_and(|old| current.daisymoon.as_ref() != Some(old));
+    plan.snapshot.daisymoon = current.daisymoon.clone();
+
+    let keys: BTreeSet<&String> = old_files.keys().chain(current.files.keys()).collect();
+
+    for key in keys {
+        let old = old_files.get(key);
+        let new = current.files.get(key);
+
+        if recorded.is_some() && old == new {
+            if let Some(hash) = new {
+                plan.snapshot.files.insert(key.clone(), hash.clone());
+            }
+            continue;
+        }
+
+        let relative = PathBuf::from(key);
+        let mine = hash_if_exists(&install_dir.join(&relative))?;
+
+        let unchanged_here =
+            recorded.is_some() && (mine.as_ref() == old || is_under(&relative, REAPPLIED_PATHS));
+
+        if mine.as_ref() != new && !unchanged_here {
+            plan.conflicts.push(relative);
+
+            if let Some(hash) = old {
+                plan.snapshot.files.insert(key.clone(), hash.clone());
+            }
+            continue;
+        }
+
+        match new {
+            Some(hash) => {
+                if mine.as_ref() != new {
+                    plan.updated.push(relative);
+                }
+                plan.snapshot.files.insert(key.clone(), hash.clone());
+            }
+            None => {
+                if mine.is_some() {
+                    plan.removed.push(relative);
+                }
+            }
+        }
+    }
+
+    Ok(plan)
+}
+
+/// Copies the updated files from the game and removes the removed ones
+pub fn apply_sync(
+    game: &Path,
+    install_dir: &Path,
+    plan: &SyncPlan,
+    strategy: CopyStrategy,
+) -> Result<CopyReport, CopyError> {
+    let mut copy_plan = CopyPlan::default();
+
+    for relative in &plan.updated {
+        let size = std::fs::metadata(game.join(relative))?.len();
+
+        if let Some(parent) = relative.parent() {
+            copy_plan.folders.push(parent.to_path_buf());
+        }
+
+        copy_plan.total_bytes += size;
+        copy_plan.bytes_to_copy += size;
+        copy_plan.files.push(PlannedFile {
+            relative: relative.clone(),
+            size,
+        });
+    }
+
+    let options = GameCopyOptions {
+        strategy,
+        ..Default::default()
+    };
+
+    let report = copy::copy_game(game, install_dir, &copy_plan, &options)?;
+
+    for relative in &plan.removed {
+        std::fs::remove_file(install_dir.join(relative))?;
+    }
+
+    write_snapshot(install_dir, &plan.snapshot)?;
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn notices_the_game_changing_daisymoon() {
+        let game = tempfile::tempdir().unwrap();
+        let copy = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(game.path().join("daisyMoon")).unwrap();
+        std::fs::write(game.path().join("daisyMoon/main.lua"), "old").unwrap();
+
+        let recorded = snapshot_game(game.path()).unwrap();
+        assert!(recorded.files.is_empty());
+
+        let plan = plan_sync(game.path(), copy.path(), Some(&recorded)).unwrap();
+        assert!(!plan.daisymoon_changed);
+
+        std::fs::write(game.path().join("daisyMoon/main.lua"), "new").unwrap();
+
+        let plan = plan_sync(game.path(), copy.path(), Some(&recorded)).unwrap();
+        assert!(plan.daisymoon_changed);
+        assert!(plan.updated.is_empty());
+        assert_ne!(plan.snapshot.daisymoon, recorded.daisymoon);
+    }
+}
@@ -0,0 +1,547 @@
+//! Module related to Cobalt's `.translations` files
+//!
+//! Every line of a translations file is either an entry, `key = text` (a tab also
+//! separates them), or something else kept as it is: blank lines and comments
+//! starting with `#`, `//`, `--` or `;`. Files are written back line for line, so
+//! merging Alloy's keys only changes the lines of those keys.
+//!
+//! Alloy ships English text. Its keys replace the ones in `eng.translations`,
+//! and are added to the other languages only where they are missing, so those
+//! show English for Alloy's menus instead of nothing. Before the first merge,
+//! every file is copied to `pristine/translations` for uninstalling.
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use super::{
+    errors::TranslationsError,
+    files::{sha256_file, sha256_hex},
+    update::pristine_dir,
+    INSTALLER_FOLDER,
+};
+
+/// The folder of translations in the game
+pub const TRANSLATIONS_FOLDER: &str = "translations";
+
+/// The language Alloy's text is in, which it overrides
+pub const ALLOY_LANGUAGE_FILE: &str = "eng.translations";
+
+/// What the merges did, in the installer folder
+pub const INDEX_FILENAME: &str = "translations.ron";
+
+/// The report of the last merge, in the installer folder
+pub const TRANSLATIONS_REPORT_FILENAME: &str = "translations_report.txt";
+
+/// A line of a translations file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Line {
+    Entry {
+        key: String,
+        value: String,
+        /// The line as it was read, written back unless the value changes
+        raw: String,
+    },
+    Other(String),
+}
+
+/// A parsed translations file
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Translations {
+    pub lines: Vec<Line>,
+    /// Whether lines end in `\r\n`
+    pub crlf: bool,
+    /// Whether the file starts with a UTF-8 byte order mark
+    pub bom: bool,
+    /// Whether the last line ends with a line break
+    pub trailing_newline: bool,
+}
+
+fn is_comment(line: &str) -> bool {
+    let trimmed = line.trim_start();
+    trimmed.is_empty()
+        || ["#", "//", "--", ";"]
+            .iter()
+            .any(|start| trimmed.starts_with(start))
+}
+
+fn parse_line(raw: &str) -> Line {
+    if is_comment(raw) {
+        return Line::Other(raw.to_string());
+    }
+
+    // Whichever comes first, values can have the other in them
+    let separator = raw.find(['=', '\t']);
+
+    match separator {
+        Some(index) if !raw[..index].trim().is_empty() => Line::Entry {
+            key: raw[..index].trim().to_string(),
+            value: raw[index + 1..].trim().to_string(),
+            raw: raw.to_string(),
+        },
+        _ => Line::Other(raw.to_string()),
+    }
+}
+
+impl Translations {
+    /// Reads a translations file
+    pub fn parse(text: &str) -> Translations {
+        let bom = text.starts_with('\u{feff}');
+        let text = text.trim_start_matches('\u{feff}');
+
+        let crlf = text.contains("\r\n");
+        let trailing_newline = text.ends_with('\n');
+
+        let lines = text
+            .lines()
+            .map(|line| parse_line(line.trim_end_matches('\r')))
+            .collect();
+
+        Translations {
+            lines,
+            crlf,
+            bom,
+            trailing_newline,
+        }
+    }
+
+    /// Writes the file back, unchanged lines exactly as they were read
+    pub fn serialize(&self) -> String {
+        let newline = if self.crlf { "\r\n" } else { "\n" };
+
+        let mut text: String = if self.bom {
+            "\u{feff}".to_string()
+        } else {
+            String::new()
+        };
+
+        let lines: Vec<&str> = self
+            .lines
+            .iter()
+            .map(|line| match line {
+                Line::Entry { raw, .. } => raw.as_str(),
+                Line::Other(raw) => raw.as_str(),
+            })
+            .collect();
+
+        text.push_str(&lines.join(newline));
+        if self.trailing_newline && !lines.is_empty() {
+            text.push_str(newline);
+        }
+
+        text
+    }
+
+    /// The value of a key
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.lines.iter().find_map(|line| match line {
+            Line::Entry {
+                key: found, value, ..
+            } if found == key => Some(value.as_str()),
+            _ => None,
+        })
+    }
+
+    /// The line of a key, as it was read
+    pub fn raw(&self, key: &str) -> Option<&str> {
+        self.entries()
+            .find(|(found, _, _)| *found == key)
+            .map(|(_, _, raw)| raw)
+    }
+
+    /// Every entry, in file order
+    pub fn entries(&self) -> impl Iterator<Item = (&str, &str, &str)> {
+        self.lines.iter().filter_map(|line| match line {
+            Line::Entry { key, value, raw } => Some((key.as_str(), value.as_str(), raw.as_str())),
+            Line::Other(_) => None,
+        })
+    }
+
+    /// Sets a key with the line written for it, replacing the line it had
+    pub fn set(&mut self, key: &str, value: &str, raw: &str) {
+        let entry = Line::Entry {
+            key: key.to_string(),
+            value: value.to_string(),
+            raw: raw.to_string(),
+        };
+
+        match self
+            .lines
+            .iter_mut()
+            .find(|line| matches!(line, Line::Entry { key: found, .. } if found == key))
+        {
+            Some(line) => *line = entry,
+            None => self.lines.push(entry),
+        }
+    }
+
+    /// Removes a key
+    pub fn remove(&mut self, key: &str) {
+        self.lines
+            .retain(|line| !matches!(line, Line::Entry { key: found, .. } if found == key));
+    }
+}
+
+/// A key that already had different text
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Conflict {
+    pub key: String,
+    /// The text it had
+    pub previous: String,
+    /// The text Alloy gives it
+    pub alloy: String,
+}
+
+/// What merging Alloy's keys into a file did
+#[derive(Debug, Clone, Default)]
+pub struct MergedTranslations {
+    /// Keys that weren't in the file
+    pub added: Vec<String>,
+    /// Keys that were, with other text; Alloy's replaced it when overriding
+    pub conflicts: Vec<Conflict>,
+}
+
+/// Merges Alloy's keys into a file, `overriding` replacing the keys it already has
+pub fn merge(
+    into: &mut Translations,
+    alloy: &Translations,
+    overriding: bool,
+) -> MergedTranslations {
+    let mut merged = MergedTranslations::default();
+
+    for (key, value, raw) in alloy.entries() {
+        match into.get(key) {
+            None => {
+                into.set(key, value, raw);
+                merged.added.push(key.to_string());
+            }
+            Some(previous) if previous == value => {}
+            Some(previous) => {
+                // Other languages have their own text for the keys, that isn't a conflict
+                if overriding {
+                    merged.conflicts.push(Conflict {
+                        key: key.to_string(),
+                        previous: previous.to_string(),
+                        alloy: value.to_string(),
+                    });
+                    into.set(key, value, raw);
+                }
+            }
+        }
+    }
+
+    merged
+}
+
+/// What the installer last wrote into a translations file
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct MergedFile {
+    /// sha256 of the file as written
+    written: String,
+    /// Keys the file didn't have before Alloy
+    added: Vec<String>,
+    /// Keys Alloy gave other text, with the line they had before
+    #[serde(default)]
+    replaced: BTreeMap<String, String>,
+}
+
+/// Every translations file the installer merged into, by file name
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct TranslationsIndex {
+    files: BTreeMap<String, MergedFile>,
+}
+
+fn index_path(install_dir: &Path) -> PathBuf {
+    install_dir.join(INSTALLER_FOLDER).join(INDEX_FILENAME)
+}
+
+fn read_index(install_dir: &Path) -> Result<TranslationsIndex, TranslationsError> {
+    let path = index_path(install_dir);
+
+    if !path.exists() {
+        return Ok(TranslationsIndex::default());
+    }
+
+    Ok(ron::from_str(&std::fs::read_to_string(path)?)?)
+}
+
+fn write_index(install_dir: &Path, index: &TranslationsIndex) -> Result<(), TranslationsError> {
+    let as_string = ron::ser::to_string_pretty(index, ron::ser::PrettyConfig::default())?;
+    std::fs::write(index_path(install_dir), as_string)?;
+
+    Ok(())
+}
+
+/// Where the files are kept as they were before Alloy
+pub fn pristine_translations_dir(install_dir: &Path) -> PathBuf {
+    pristine_dir(install_dir).join(TRANSLATIONS_FOLDER)
+}
+
+/// What merging Alloy's translations into an install did, by file name
+#[derive(Debug, Clone, Default)]
+pub struct TranslationsReport {
+    pub files: BTreeMap<String, MergedTranslations>,
+    /// sha256 of Alloy's translations that were merged
+    pub alloy_sha256: String,
+}
+
+impl TranslationsReport {
+    /// Every conflict, with the file it's in
+    pub fn conflicts(&self) -> impl Iterator<Item = (&str, &Conflict)> {
+        self.files.iter().flat_map(|(file, merged)| {
+            merged
+                .conflicts
+                .iter()
+                .map(move |conflict| (file.as_str(), conflict))
+        })
+    }
+
+    /// The report as written to the report file
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+
+        for (file, conflict) in self.conflicts() {
+            text.push_str(&format!(
+                "CONFLICT {} {}\n  was:   {}\n  alloy: {}\n",
+                file, conflict.key, conflict.previous, conflict.alloy
+            ));
+        }
+        for (file, merged) in &self.files {
+            text.push_str(&format!(
+                "added    {} ({} keys)\n",
+                file,
+                merged.added.len()
+            ));
+        }
+
+        text
+    }
+}
+
+/// The translations files of an install
+fn language_files(translations_dir: &Path) -> std::io::Result<Vec<String>> {
+    let mut files: Vec<String> = std::fs::read_dir(translations_dir)?
+        .flatten()
+        .map(|entry| entry.file_name().to_string_lossy().to_string())
+        .filter(|name| name.ends_with(".translations"))
+        .collect();
+    files.sort();
+
+    Ok(files)
+}
+
+/// Merges Alloy's translations into every language of an install
+///
+/// The pristine copy of a file is taken again when it isn't what the installer
+/// last wrote, because Steam or `sync` replaced it. Whatever of Alloy is still
+/// in it is left out: keys it added are removed, keys it replaced get their
+/// old line back.
+pub fn merge_alloy_translations(
+    install_dir: &Path,
+    alloy_text: &str,
+) -> Result<TranslationsReport, TranslationsError> {
+    let alloy = Translations::parse(alloy_text);
+    let translations_dir = install_dir.join(TRANSLATIONS_FOLDER);
+    let pristine_dir = pristine_translations_dir(install_dir);
+
+    std::fs::create_dir_all(&translations_dir)?;
+    std::fs::create_dir_all(&pristine_dir)?;
+
+    let mut files = language_files(&translations_dir)?;
+    if !files.iter().any(|file| file == ALLOY_LANGUAGE_FILE) {
+        files.push(ALLOY_LANGUAGE_FILE.to_string());
+    }
+
+    let mut index = read_index(install_dir)?;
+    let mut report = TranslationsReport {
+        alloy_sha256: sha256_hex(alloy_text.as_bytes()),
+        ..Default::default()
+    };
+
+    for file in files {
+        let path = translations_dir.join(&file);
+        let pristine = pristine_dir.join(&file);
+
+        let current = if path.exists() {
+            std::fs::read_to_string(&path)?
+        } else {
+            String::new()
+        };
+
+        let recorded = index.files.get(&file);
+        let is_ours = match recorded {
+            Some(recorded) => path.exists() && sha256_file(&path)? == recorded.written,
+            None => false,
+        };
+
+        if !is_ours {
+            let mut original = Translations::parse(&current);
+
+            if let Some(recorded) = recorded {
+                for key in &recorded.added {
+                    if original.get(key) == alloy.get(key) {
+                        original.remove(key);
+                    }
+                }
+
+                for (key, raw) in &recorded.replaced {
+                    if let Line::Entry { value, .. } = parse_line(raw) {
+                        if original.get(key) == alloy.get(key) {
+                            original.set(key, &value, raw);
+                        }
+                    }
+                }
+            }
+
+            std::fs::write(&pristine, original.serialize())?;
+        }
+
+        let mut translations = Translations::parse(&current);
+        if current.is_empty() {
+            translations.crlf = alloy.crlf;
+            translations.trailing_newline = true;
+        }
+
+        let overriding = file == ALLOY_LANGUAGE_FILE;
+        merge(&mut translations, &alloy, overriding);
+
+        // Reported against the file before Alloy, so merging again reports the same
+        let original = Translations::parse(&std::fs::read_to_string(&pristine)?);
+        let mut before = original.clone();
+        let merged = merge(&mut before, &alloy, overriding);
+
+        std::fs::write(&path, translations.serialize())?;
+
+        index.files.insert(
+            file.clone(),
+            MergedFile {
+                written: sha256_file(&path)?,
+                added: merged.added.clone(),
+                replaced: merged
+                    .conflicts
+                    .iter()
+                    .filter_map(|conflict| {
+                        let raw = original.raw(&conflict.key)?;
+                        Some((conflict.key.clone(), raw.to_string()))
+                    })
+                    .collect(),
+            },
+        );
+        report.files.insert(file, merged);
+    }
+
+    write_index(install_dir, &index)?;
+    std::fs::write(
+        install_dir
+            .join(INSTALLER_FOLDER)
+            .join(TRANSLATIONS_REPORT_FILENAME),
+        report.to_text(),
+    )?;
+
+    Ok(report)
+}
+
+/// Puts back the translations files as they were before Alloy, returns the files restored
+pub fn restore_pristine(install_dir: &Path) -> Result<Vec<String>, TranslationsError> {
+    let pristine_dir = pristine_translations_dir(install_dir);
+    let translations_dir = install_dir.join(TRANSLATIONS_FOLDER);
+    let index = read_index(install_dir)?;
+
+    let mut restored = Vec::new();
+
+    for file in index.files.keys() {
+        let pristine = pristine_dir.join(file);
+        let path = translations_dir.join(file);
+
+        if !pristine.exists() {
+            continue;
+        }
+
+        // An empty pristine copy means the installer made the file
+        if std::fs::metadata(&pristine)?.len() == 0 {
+            if path.exists() {
+                std::fs::remove_file(&path)?;
+            }
+        } else {
+            std::fs::copy(&pristine, &path)?;
+        }
+
+        restored.push(file.clone());
+    }
+
+    if index_path(install_dir).exists() {
+        std::fs::remove_file(index_path(install_dir))?;
+    }
+
+    Ok(restored)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::installer::errors::AlloyDownloadError;
+
+    fn entry(raw: &str) -> Option<(String, String)> {
+        match parse_line(raw) {
+            Line::Entry { key, value, .. } => Some((key, value)),
+            Line::Other(_) => None,
+        }
+    }
+
+    #[test]
+    fn splits_at_the_first_separator() {
+        let pair = |key: &str, value: &str| Some((key.to_string(), value.to_string()));
+
+        assert_eq!(entry("menu = Start"), pair("menu", "Start"));
+        assert_eq!(entry("menu\tStart"), pair("menu", "Start"));
+        assert_eq!(entry("formula\ta = b"), pair("formula", "a = b"));
+        assert_eq!(entry("tabbed = a\tb"), pair("tabbed", "a\tb"));
+        assert_eq!(entry("-- menu = Start"), None);
+        assert_eq!(entry(" = Start"), None);
+    }
+
+    #[test]
+    fn retaken_pristine_copies_leave_alloy_out() {
+        let install = tempfile::tempdir().unwrap();
+        let translations = install.path().join(TRANSLATIONS_FOLDER);
+        std::fs::create_dir_all(&translations).unwrap();
+        let eng = translations.join(ALLOY_LANGUAGE_FILE);
+        std::fs::write(&eng, "menu = Old\nother = x\n").unwrap();
+
+        let alloy = "menu = New\nalloy_key = A\n";
+        merge_alloy_translations(install.path(), alloy).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&eng).unwrap(),
+            "menu = New\nother = x\nalloy_key = A\n"
+        );
+
+        // Edited since, so its pristine copy is taken again
+        std::fs::write(&eng, "menu = New\nother = y\nalloy_key = A\n").unwrap();
+        let report = merge_alloy_translations(install.path(), alloy).unwrap();
+        assert_eq!(report.files[ALLOY_LANGUAGE_FILE].conflicts.len(), 1);
+
+        restore_pristine(install.path()).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&eng).unwrap(),
+            "menu = Old\nother = y\n"
+        );
+    }
+
+    #[test]
+    fn unreadable_files_are_translation_errors() {
+        let install = tempfile::tempdir().unwrap();
+        let translations = install.path().join(TRANSLATIONS_FOLDER);
+        std::fs::create_dir_all(&translations).unwrap();
+        std::fs::write(translations.join(ALLOY_LANGUAGE_FILE), b"menu = \xff\xfe\n").unwrap();
+
+        let error = merge_alloy_translations(install.path(), "menu = New\n").unwrap_err();
+
+        assert!(matches!(
+            AlloyDownloadError::from(error),
+            AlloyDownloadError::Translations { .. }
+        ));
+    }
+}
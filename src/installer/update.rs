@@ -0,0 +1,370 @@
+//! Module related to updating Alloy in an install without losing local edits
+//!
+//! Installs keep daisyMoon as it was before patching (`pristine/vanilla`) and
+//! the files the diff produced (`pristine/base`). An update patches the vanilla
+//! files with the new diff, then merges that with the user's files three ways,
+//! the base being what both started from. Files mod patches changed are kept
+//! too (`pristine/modded`), as they are with every patch applied.
+
+use std::{
+    collections::BTreeSet,
+    path::{Path, PathBuf},
+};
+
+use super::{
+    alloy::{apply_diff, SAVED_DIFF_NAME},
+    errors::UpdateError,
+    files::{list_files, path_key},
+    line_endings::{diff_targets, in_format_of, normalize_for_patch},
+    merge::{merge3, MergeLabels},
+    platform::TargetPlatform,
+    INSTALLER_FOLDER,
+};
+
+/// Folder in the installer folder holding the pristine files
+pub const PRISTINE_FOLDER: &str = "pristine";
+
+/// daisyMoon before Alloy
+const VANILLA_FOLDER: &str = "vanilla";
+
+/// The files of the installed diff, as the diff made them
+const BASE_FOLDER: &str = "base";
+
+/// The files mod patches changed, as the patches made them
+const MODDED_FOLDER: &str = "modded";
+
+/// Where the new diff is applied to vanilla during an update
+const NEXT_FOLDER: &str = "next";
+
+/// The report of the last update, in the installer folder
+pub const MERGE_REPORT_FILENAME: &str = "merge_report.txt";
+
+/// What an update did to every file the old or new diff touches
+#[derive(Debug, Clone, Default)]
+pub struct UpdateReport {
+    /// Files that weren't edited, replaced by the new version
+    pub replaced: Vec<String>,
+    /// Files with edits that merged cleanly with the new version
+    pub merged: Vec<String>,
+    /// Files with edits that conflict with the new version, and how many conflicts
+    pub conflicts: Vec<(String, usize)>,
+}
+
+impl UpdateReport {
+    /// The report as written to the report file
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+
+        for (relative, count) in &self.conflicts {
+            text.push_str(&format!("CONFLICT {} ({} conflicts)\n", relative, count));
+        }
+        for relative in &self.merged {
+            text.push_str(&format!("merged   {}\n", relative));
+        }
+        for relative in &self.replaced {
+            text.push_str(&format!("replaced {}\n", relative));
+        }
+
+        text
+    }
+}
+
+/// The folder holding the pristine files
+pub fn pristine_dir(install_dir: &Path) -> PathBuf {
+    install_dir.join(INSTALLER_FOLDER).join(PRISTINE_FOLDER)
+}
+
+/// Where daisyMoon was kept as it was before patching
+pub fn vanilla_dir(install_dir: &Path) -> PathBuf {
+    pristine_dir(install_dir).join(VANILLA_FOLDER)
+}
+
+/// Where the files mod patches changed are kept
+pub fn modded_dir(install_dir: &Path) -> PathBuf {
+    pristine_dir(install_dir).join(MODDED_FOLDER)
+}
+
+/// The file the installer wrote at `relative`, as it wrote it, if it was kept
+pub fn installed_version(install_dir: &Path, relative: &str) -> Option<PathBuf> {
+    [MODDED_FOLDER, BASE_FOLDER, VANILLA_FOLDER]
+        .iter()
+        .map(|folder| pristine_dir(install_dir).join(folder).join(relative))
+        .find(|path| path.is_file())
+}
+
+/// Every file kept in the pristine folders, relative to the install
+pub fn kept_files(install_dir: &Path) -> std::io::Result<BTreeSet<String>> {
+    let mut kept = BTreeSet::new();
+
+    for folder in [MODDED_FOLDER, BASE_FOLDER, VANILLA_FOLDER] {
+        let folder = pristine_dir(install_dir).join(folder);
+
+        if folder.is_dir() {
+            kept.extend(list_files(&folder)?.iter().map(|relative| path_key(relative)));
+        }
+    }
+
+    Ok(kept)
+}
+
+/// Whether the install kept what updating needs
+pub fn has_pristine(install_dir: &Path) -> bool {
+    pristine_dir(install_dir).join(VANILLA_FOLDER).is_dir()
+        && pristine_dir(install_dir).join(BASE_FOLDER).is_dir()
+}
+
+fn copy_tree(from: &Path, to: &Path) -> std::io::Result<()> {
+    for relative in list_files(from)? {
+        copy_one(&from.join(&relative), &to.join(&relative))?;
+    }
+
+    Ok(())
+}
+
+fn copy_one(from: &Path, to: &Path) -> std::io::Result<()> {
+    if let Some(parent) = to.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    std::fs::copy(from, to).map(|_| ())
+}
+
+fn replace_folder(path: &Path) -> std::io::Result<()> {
+    if path.exists() {
+        std::fs::remove_dir_all(path)?;
+    }
+
+    std::fs::create_dir_all(path)
+}
+
+fn read_if_exists(path: &Path) -> std::io::Result<Option<Vec<u8>>> {
+    match std::fs::read(path) {
+        Ok(bytes) => Ok(Some(bytes)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Keeps daisyMoon as it is before patching, call right before patching
+pub fn keep_vanilla(install_dir: &Path) -> std::io::Result<()> {
+    let vanilla = pristine_dir(install_dir).join(VANILLA_FOLDER);
+    replace_folder(&vanilla)?;
+
+    copy_tree(&install_dir.join("daisyMoon"), &vanilla.join("daisyMoon"))
+}
+
+/// Keeps the files the diff produced, call right after patching
+pub fn keep_base(install_dir: &Path) -> std::io::Result<()> {
+    let base = pristine_dir(install_dir).join(BASE_FOLDER);
+    replace_folder(&base)?;
+
+    let diff = std::fs::read(install_dir.join(INSTALLER_FOLDER).join(SAVED_DIFF_NAME))?;
+
+    for relative in diff_targets(&diff) {
+        let path = install_dir.join(&relative);
+
+        if path.is_file() {
+            copy_one(&path, &base.join(&relative))?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Updates the install to `new_diff`, merging it with edits made since it was installed
+pub async fn update(
+    install_dir: &Path,
+    new_diff: &[u8],
+    target: TargetPlatform,
+    new_label: &str,
+) -> Result<UpdateReport, UpdateError> {
+    if !has_pristine(install_dir) {
+        return Err(UpdateError::NoPristine);
+    }
+
+    let pristine = pristine_dir(install_dir);
+    let vanilla = pristine.join(VANILLA_FOLDER);
+    let base = pristine.join(BASE_FOLDER);
+    let next = pristine.join(NEXT_FOLDER);
+
+    // Vanilla + the new diff
+    replace_folder(&next)?;
+    std::fs::create_dir_all(next.join(INSTALLER_FOLDER))?;
+    std::fs::write(next.join(INSTALLER_FOLDER).join(SAVED_DIFF_NAME), new_diff)?;
+
+    let new_targets = diff_targets(new_diff);
+    for relative in &new_targets {
+        if vanilla.join(relative).is_file() {
+            copy_one(&vanilla.join(relative), &next.join(relative))?;
+        }
+    }
+
+    normalize_for_patch(&next, target.line_ending())?;
+
+    let next_diff = next.join(INSTALLER_FOLDER).join(SAVED_DIFF_NAME);
+    apply_diff(install_dir, &next, &next_diff, target)
+        .await
+        .map_err(|output| UpdateError::Patch { output })?;
+
+    let saved_diff = install_dir.join(INSTALLER_FOLDER).join(SAVED_DIFF_NAME);
+    let old_targets = diff_targets(&std::fs::read(&saved_diff)?);
+
+    let labels = MergeLabels {
+        ours: "your changes",
+        theirs: new_label,
+    };
+
+    let mut report = UpdateReport::default();
+    let every_target: BTreeSet<&String> = old_targets.iter().chain(&new_targets).collect();
+
+    for relative in every_target {
+        let ancestor = if old_targets.contains(relative) {
+            base.join(relative)
+        } else {
+            vanilla.join(relative)
+        };
+        let newer = if new_targets.contains(relative) {
+            next.join(relative)
+        } else {
+            vanilla.join(relative)
+        };
+
+        let ancestor = read_if_exists(&ancestor)?;
+        let newer = read_if_exists(&newer)?;
+        let path = install_dir.join(relative);
+        let ours = read_if_exists(&path)?;
+
+        // The pristine copies are normalized for patching, the install may have its endings back
+        let (ancestor, newer) = match &ours {
+            Some(ours) => (
+                ancestor.map(|ancestor| in_format_of(&ancestor, ours)),
+                newer.map(|newer| in_format_of(&newer, ours)),
+            ),
+            None => (ancestor, newer),
+        };
+
+        if ours == ancestor {
+            match &newer {
+                Some(contents) => {
+                    if let Some(parent) = path.parent() {
+                        std::fs::create_dir_all(parent)?;
+                    }
+                    std::fs::write(&path, contents)?;
+                }
+                None if path.exists() => std::fs::remove_file(&path)?,
+                None => {}
+            }
+
+            if ours != newer {
+                report.replaced.push(relative.clone());
+            }
+            continue;
+        }
+
+        if newer == ancestor {
+            continue;
+        }
+
+        let merged = merge3(
+            ancestor.as_deref().unwrap_or_default(),
+            ours.as_deref().unwrap_or_default(),
+            newer.as_deref().unwrap_or_default(),
+            &labels,
+        );
+
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&path, &merged.contents)?;
+
+        if merged.conflicts > 0 {
+            report.conflicts.push((relative.clone(), merged.conflicts));
+        } else {
+            report.merged.push(relative.clone());
+        }
+    }
+
+    // The new diff's files become the base of the next update
+    replace_folder(&base)?;
+    for relative in &new_targets {
+        if next.join(relative).is_file() {
+            copy_one(&next.join(relative), &base.join(relative))?;
+        }
+    }
+
+    std::fs::copy(&next_diff, &saved_diff)?;
+    std::fs::remove_dir_all(&next)?;
+
+    std::fs::write(
+        install_dir
+            .join(INSTALLER_FOLDER)
+            .join(MERGE_REPORT_FILENAME),
+        report.to_text(),
+    )?;
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::installer::line_endings::restore_after_patch;
+    use crate::installer::merge::unified_diff;
+
+    const FILE: &str = "daisyMoon/a.lua";
+    const UTF8_BOM: &[u8] = b"\xef\xbb\xbf";
+
+    /// Patches the install the way `install` does, keeping the pristine copies
+    async fn install_with(install_dir: &Path, diff: &[u8], target: TargetPlatform) {
+        std::fs::create_dir_all(install_dir.join(INSTALLER_FOLDER)).unwrap();
+        let diff_path = install_dir.join(INSTALLER_FOLDER).join(SAVED_DIFF_NAME);
+        std::fs::write(&diff_path, diff).unwrap();
+
+        let normalized = normalize_for_patch(install_dir, target.line_ending()).unwrap();
+        keep_vanilla(install_dir).unwrap();
+        apply_diff(install_dir, install_dir, &diff_path, target)
+            .await
+            .unwrap();
+        keep_base(install_dir).unwrap();
+        restore_after_patch(install_dir, &normalized, true).unwrap();
+    }
+
+    #[tokio::test]
+    async fn unedited_bom_crlf_files_take_the_new_version() {
+        let install = tempfile::tempdir().unwrap();
+        let target = TargetPlatform::host();
+
+        let vanilla = b"local a = 1\nreturn a\n";
+        let alloy = b"local a = 2\nreturn a\n";
+        let newer = b"local a = 3\nreturn a\n";
+
+        let mut original = UTF8_BOM.to_vec();
+        original.extend_from_slice(b"local a = 1\r\nreturn a\r\n");
+        std::fs::create_dir_all(install.path().join("daisyMoon")).unwrap();
+        std::fs::write(install.path().join(FILE), &original).unwrap();
+
+        install_with(
+            install.path(),
+            &unified_diff(FILE, Some(vanilla), Some(alloy)),
+            target,
+        )
+        .await;
+
+        let report = update(
+            install.path(),
+            &unified_diff(FILE, Some(vanilla), Some(newer)),
+            target,
+            "new Alloy",
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(report.replaced, vec![FILE.to_string()]);
+        assert!(report.merged.is_empty());
+        assert!(report.conflicts.is_empty());
+
+        let mut expected = UTF8_BOM.to_vec();
+        expected.extend_from_slice(b"local a = 3\r\nreturn a\r\n");
+        assert_eq!(std::fs::read(install.path().join(FILE)).unwrap(), expected);
+    }
+}
@@ -0,0 +1,20 @@
+//! Module related to the per-user folder shared by every install
+
+use std::path::PathBuf;
+
+/// Name of the installer's folder inside the user's data directory
+const USER_DATA_FOLDER: &str = "alloy-installer";
+
+/// Environment variable overriding where the per-user folder is
+const USER_DATA_ENV: &str = "ALLOY_INSTALLER_DATA";
+
+/// Returns the per-user folder, e.g. `$XDG_DATA_HOME/alloy-installer` or `%APPDATA%\alloy-installer`
+pub fn user_data_dir() -> PathBuf {
+    if let Some(dir) = std::env::var_os(USER_DATA_ENV) {
+        return dir.into();
+    }
+
+    dirs::data_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join(USER_DATA_FOLDER)
+}
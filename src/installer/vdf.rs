@@ -0,0 +1,435 @@
+//! Module related to reading and writing Steam's VDF files
+//!
+//! Steam keeps most of its settings in text VDF (`config.vdf`, `libraryfolders.vdf`),
+//! but non-Steam games live in binary VDF (`shortcuts.vdf`). Both are read into the
+//! same tree, keeping the order of keys so writing a file back changes nothing else.
+
+use super::errors::VdfError;
+
+/// Binary type byte of a nested map
+const TYPE_MAP: u8 = 0x00;
+/// Binary type byte of a string
+const TYPE_STRING: u8 = 0x01;
+/// Binary type byte of a 32 bit integer
+const TYPE_INT: u8 = 0x02;
+/// Binary type byte of a 32 bit float
+const TYPE_FLOAT: u8 = 0x03;
+/// Binary type byte of a 64 bit integer
+const TYPE_UINT64: u8 = 0x07;
+/// Binary byte ending a map
+const TYPE_END: u8 = 0x08;
+
+/// The keys and values of a map, in file order
+pub type VdfMap = Vec<(String, VdfValue)>;
+
+/// A value in a VDF file
+#[derive(Debug, Clone, PartialEq)]
+pub enum VdfValue {
+    Map(VdfMap),
+    String(String),
+    Int(i32),
+    Float(f32),
+    UInt64(u64),
+}
+
+impl VdfValue {
+    /// The map inside, if this is one
+    pub fn as_map(&self) -> Option<&VdfMap> {
+        match self {
+            VdfValue::Map(map) => Some(map),
+            _ => None,
+        }
+    }
+
+    /// The string inside, if this is one
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            VdfValue::String(string) => Some(string),
+            _ => None,
+        }
+    }
+}
+
+/// Finds a value by key, ignoring case like Steam does
+pub fn get<'a>(map: &'a VdfMap, key: &str) -> Option<&'a VdfValue> {
+    map.iter()
+        .find(|(found, _)| found.eq_ignore_ascii_case(key))
+        .map(|(_, value)| value)
+}
+
+/// Sets a value, replacing the one with the same key if there is one
+pub fn set(map: &mut VdfMap, key: &str, value: VdfValue) {
+    match map
+        .iter_mut()
+        .find(|(found, _)| found.eq_ignore_ascii_case(key))
+    {
+        Some((_, existing)) => *existing = value,
+        None => map.push((key.to_string(), value)),
+    }
+}
+
+/// Returns the map at `key`, creating it if needed
+pub fn map_entry<'a>(map: &'a mut VdfMap, key: &str) -> &'a mut VdfMap {
+    let index = match map
+        .iter()
+        .position(|(found, value)| found.eq_ignore_ascii_case(key) && value.as_map().is_some())
+    {
+        Some(index) => index,
+        None => {
+            map.retain(|(found, _)| !found.eq_ignore_ascii_case(key));
+            map.push((key.to_string(), VdfValue::Map(Vec::new())));
+            map.len() - 1
+        }
+    };
+
+    match &mut map[index].1 {
+        VdfValue::Map(inner) => inner,
+        _ => unreachable!("the entry was just checked to be a map"),
+    }
+}
+
+struct BinaryReader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl BinaryReader<'_> {
+    fn byte(&mut self) -> Result<u8, VdfError> {
+        let byte = *self.bytes.get(self.offset).ok_or(VdfError::UnexpectedEnd)?;
+        self.offset += 1;
+        Ok(byte)
+    }
+
+    fn take<const N: usize>(&mut self) -> Result<[u8; N], VdfError> {
+        let slice = self
+            .bytes
+            .get(self.offset..self.offset + N)
+            .ok_or(VdfError::UnexpectedEnd)?;
+        self.offset += N;
+        Ok(slice.try_into().expect("the slice has the right length"))
+    }
+
+    fn string(&mut self) -> Result<String, VdfError> {
+        let rest = &self.bytes[self.offset.min(self.bytes.len())..];
+        let end = rest
+            .iter()
+            .position(|byte| *byte == 0)
+            .ok_or(VdfError::UnexpectedEnd)?;
+        self.offset += end + 1;
+        Ok(String::from_utf8_lossy(&rest[..end]).to_string())
+    }
+
+    fn map(&mut self) -> Result<VdfMap, VdfError> {
+        let mut map = Vec::new();
+
+        loop {
+            let kind = self.byte()?;
+            if kind == TYPE_END {
+                return Ok(map);
+            }
+
+            let key = self.string()?;
+            let value = match kind {
+                TYPE_MAP => VdfValue::Map(self.map()?),
+                TYPE_STRING => VdfValue::String(self.string()?),
+                TYPE_INT => VdfValue::Int(i32::from_le_bytes(self.take()?)),
+                TYPE_FLOAT => VdfValue::Float(f32::from_le_bytes(self.take()?)),
+                TYPE_UINT64 => VdfValue::UInt64(u64::from_le_bytes(self.take()?)),
+                kind => {
+                    return Err(VdfError::UnknownType {
+                        kind,
+                        offset: self.offset,
+                    })
+                }
+            };
+
+            map.push((key, value));
+        }
+    }
+}
+
+/// Reads a binary VDF file
+pub fn read_binary(bytes: &[u8]) -> Result<VdfMap, VdfError> {
+    let mut reader = BinaryReader { bytes, offset: 0 };
+    let map = reader.map()?;
+
+    Ok(map)
+}
+
+fn write_binary_map(map: &VdfMap, out: &mut Vec<u8>) {
+    for (key, value) in map {
+        let kind = match value {
+            VdfValue::Map(_) => TYPE_MAP,
+            VdfValue::String(_) => TYPE_STRING,
+            VdfValue::Int(_) => TYPE_INT,
+            VdfValue::Float(_) => TYPE_FLOAT,
+            VdfValue::UInt64(_) => TYPE_UINT64,
+        };
+
+        out.push(kind);
+        out.extend_from_slice(key.as_bytes());
+        out.push(0);
+
+        match value {
+            VdfValue::Map(inner) => write_binary_map(inner, out),
+            VdfValue::String(string) => {
+                out.extend_from_slice(string.as_bytes());
+                out.push(0);
+            }
+            VdfValue::Int(int) => out.extend_from_slice(&int.to_le_bytes()),
+            VdfValue::Float(float) => out.extend_from_slice(&float.to_le_bytes()),
+            VdfValue::UInt64(int) => out.extend_from_slice(&int.to_le_bytes()),
+        }
+    }
+
+    out.push(TYPE_END);
+}
+
+/// Writes a binary VDF file
+pub fn write_binary(map: &VdfMap) -> Vec<u8> {
+    let mut out = Vec::new();
+    write_binary_map(map, &mut out);
+    out
+}
+
+/// Splits text VDF into quoted strings and braces, with the line each starts on
+fn text_tokens(text: &str) -> Result<Vec<(String, bool, usize)>, VdfError> {
+    let mut tokens = Vec::new();
+    let mut chars = text.chars().peekable();
+    let mut line = 1;
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\n' => line += 1,
+            c if c.is_whitespace() => {}
+            '{' | '}' => tokens.push((c.to_string(), false, line)),
+            '/' if chars.peek() == Some(&'/') => {
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        line += 1;
+                        break;
+                    }
+                }
+            }
+            '"' => {
+                let mut string = String::new();
+                let start = line;
+
+                loop {
+                    match chars.next().ok_or(VdfError::UnexpectedEnd)? {
+                        '"' => break,
+                        '\\' => match chars.next().ok_or(VdfError::UnexpectedEnd)? {
+                            'n' => string.push('\n'),
+                            't' => string.push('\t'),
+                            'r' => string.push('\r'),
+                            other => string.push(other),
+                        },
+                        '\n' => {
+                            line += 1;
+                            string.push('\n');
+                        }
+                        other => string.push(other),
+                    }
+                }
+
+                tokens.push((string, true, start));
+            }
+            _ => return Err(VdfError::Syntax { line }),
+        }
+    }
+
+    Ok(tokens)
+}
+
+/// Reads a text VDF file, every value is a string or a map
+pub fn read_text(text: &str) -> Result<VdfMap, VdfError> {
+    let tokens = text_tokens(text)?;
+    let mut stack: Vec<(String, VdfMap)> = vec![(String::new(), Vec::new())];
+    let mut tokens = tokens.into_iter().peekable();
+
+    while let Some((token, quoted, line)) = tokens.next() {
+        if !quoted {
+            if token != "}" || stack.len() < 2 {
+                return Err(VdfError::Syntax { line });
+            }
+
+            let (key, map) = stack.pop().expect("the stack was just checked");
+            stack
+                .last_mut()
+                .expect("the root is never popped")
+                .1
+                .push((key, VdfValue::Map(map)));
+            continue;
+        }
+
+        match tokens.next() {
+            Some((value, true, _)) => stack
+                .last_mut()
+                .expect("the root is never popped")
+                .1
+                .push((token, VdfValue::String(value))),
+            Some((brace, false, _)) if brace == "{" => stack.push((token, Vec::new())),
+            Some((_, _, line)) => return Err(VdfError::Syntax { line }),
+            None => return Err(VdfError::UnexpectedEnd),
+        }
+    }
+
+    if stack.len() != 1 {
+        return Err(VdfError::UnexpectedEnd);
+    }
+
+    Ok(stack.pop().expect("the root is never popped").1)
+}
+
+/// Escapes a string the way `text_tokens` reads it back
+fn escape(string: &str) -> String {
+    let mut escaped = String::with_capacity(string.len());
+
+    for c in string.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '"' => escaped.push_str("\\\""),
+            '\n' => escaped.push_str("\\n"),
+            '\t' => escaped.push_str("\\t"),
+            '\r' => escaped.push_str("\\r"),
+            c => escaped.push(c),
+        }
+    }
+
+    escaped
+}
+
+fn write_text_map(map: &VdfMap, depth: usize, out: &mut String) {
+    let indent = "\t".repeat(depth);
+
+    for (key, value) in map {
+        match value {
+            VdfValue::Map(inner) => {
+                out.push_str(&format!("{}\"{}\"\n{}{{\n", indent, escape(key), indent));
+                write_text_map(inner, depth + 1, out);
+                out.push_str(&format!("{}}}\n", indent));
+            }
+            VdfValue::String(string) => out.push_str(&format!(
+                "{}\"{}\"\t\t\"{}\"\n",
+                indent,
+                escape(key),
+                escape(string)
+            )),
+            VdfValue::Int(int) => {
+                out.push_str(&format!("{}\"{}\"\t\t\"{}\"\n", indent, escape(key), int))
+            }
+            VdfValue::Float(float) => {
+                out.push_str(&format!("{}\"{}\"\t\t\"{}\"\n", indent, escape(key), float))
+            }
+            VdfValue::UInt64(int) => {
+                out.push_str(&format!("{}\"{}\"\t\t\"{}\"\n", indent, escape(key), int))
+            }
+        }
+    }
+}
+
+/// Writes a text VDF file the way Steam formats them
+pub fn write_text(map: &VdfMap) -> String {
+    let mut out = String::new();
+    write_text_map(map, 0, &mut out);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escaped_strings_read_back_the_same() {
+        let tricky = "C:\\Games\\\"Cobalt\"\n\tline\r\n".to_string();
+        let map = vec![(tricky.clone(), VdfValue::String(tricky.clone()))];
+
+        let text = write_text(&map);
+
+        assert_eq!(text.lines().count(), 1);
+        assert_eq!(read_text(&text).unwrap(), map);
+    }
+
+    /// A `shortcuts.vdf` with one entry, using every binary type
+    fn shortcuts_bytes() -> Vec<u8> {
+        let mut bytes = vec![TYPE_MAP];
+        bytes.extend_from_slice(b"shortcuts\0");
+        bytes.push(TYPE_MAP);
+        bytes.extend_from_slice(b"0\0");
+        bytes.push(TYPE_INT);
+        bytes.extend_from_slice(b"appid\0");
+        bytes.extend_from_slice(&(-1198373739i32).to_le_bytes());
+        bytes.push(TYPE_STRING);
+        bytes.extend_from_slice(b"AppName\0Cobalt Alloy\0");
+        bytes.push(TYPE_FLOAT);
+        bytes.extend_from_slice(b"Scale\0");
+        bytes.extend_from_slice(&1.5f32.to_le_bytes());
+        bytes.push(TYPE_UINT64);
+        bytes.extend_from_slice(b"LastPlayed\0");
+        bytes.extend_from_slice(&u64::MAX.to_le_bytes());
+        bytes.push(TYPE_MAP);
+        bytes.extend_from_slice(b"tags\0");
+        bytes.extend_from_slice(&[TYPE_END, TYPE_END, TYPE_END, TYPE_END]);
+        bytes
+    }
+
+    #[test]
+    fn binary_files_write_back_unchanged() {
+        let bytes = shortcuts_bytes();
+
+        let map = read_binary(&bytes).unwrap();
+        let written = write_binary(&map);
+
+        assert_eq!(written, bytes);
+        assert_eq!(read_binary(&written).unwrap(), map);
+
+        let entry = get(&map, "Shortcuts").unwrap().as_map().unwrap()[0]
+            .1
+            .as_map()
+            .unwrap();
+        assert_eq!(
+            get(entry, "appname").unwrap().as_str(),
+            Some("Cobalt Alloy")
+        );
+        assert_eq!(get(entry, "Scale"), Some(&VdfValue::Float(1.5)));
+    }
+
+    #[test]
+    fn truncated_binary_files_are_errors() {
+        let bytes = shortcuts_bytes();
+
+        assert!(read_binary(&bytes[..bytes.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn text_files_write_back_unchanged() {
+        let text = concat!(
+            "\"InstallConfigStore\"\n",
+            "{\n",
+            "\t\"Software\"\n",
+            "\t{\n",
+            "\t\t\"Valve\"\n",
+            "\t\t{\n",
+            "\t\t\t\"Steam\"\n",
+            "\t\t\t{\n",
+            "\t\t\t\t\"CompatToolMapping\"\n",
+            "\t\t\t\t{\n",
+            "\t\t\t\t\t\"3096593557\"\n",
+            "\t\t\t\t\t{\n",
+            "\t\t\t\t\t\t\"name\"\t\t\"proton_experimental\"\n",
+            "\t\t\t\t\t\t\"config\"\t\t\"\"\n",
+            "\t\t\t\t\t\t\"priority\"\t\t\"250\"\n",
+            "\t\t\t\t\t}\n",
+            "\t\t\t\t}\n",
+            "\t\t\t\t\"BaseInstallFolder_1\"\t\t\"D:\\\\Steam\"\n",
+            "\t\t\t}\n",
+            "\t\t}\n",
+            "\t}\n",
+            "}\n",
+        );
+
+        let map = read_text(text).unwrap();
+
+        assert_eq!(write_text(&map), text);
+    }
+}
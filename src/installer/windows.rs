@@ -4,7 +4,7 @@ use cfg_if::cfg_if;
 use std::io::Read;
 
 /// Exit or do the "press and key to exit" thing on windows
-pub fn exit_or_windows(code: i32) {
+pub fn exit_or_windows(code: i32) -> ! {
     cfg_if! {
         if #[cfg(target_os = "windows")] {
 